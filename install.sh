@@ -74,6 +74,7 @@ User=crates_proxy
 Group=crates_proxy
 WorkingDirectory=${INSTALL_DIR}
 ExecStart=${INSTALL_DIR}/crates_proxy
+ExecReload=/bin/kill -HUP \$MAINPID
 Restart=always
 RestartSec=5
 StandardOutput=journal
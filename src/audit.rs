@@ -0,0 +1,77 @@
+//! 下载审计日志：每次返回包文件时追加一行JSON，与应用日志分开保存
+
+use crate::clock;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// 连接的客户端地址，由服务端写入请求的extensions
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub std::net::SocketAddr);
+
+/// 附加在返回包文件的响应上，记录实际返回的包和版本
+#[derive(Debug, Clone)]
+pub struct DownloadRecord {
+    pub crate_name: String,
+    pub version: String,
+    pub cache_hit: bool,
+}
+
+/// 审计日志中的一行
+#[derive(Debug, Serialize)]
+pub struct AuditEntry<'a> {
+    /// Unix时间戳（秒）
+    pub timestamp: u64,
+    #[serde(rename = "crate")]
+    pub crate_name: &'a str,
+    pub version: &'a str,
+    pub client_ip: Option<IpAddr>,
+    pub bytes: u64,
+    /// `hit` 或 `miss`
+    pub cache: &'static str,
+}
+
+/// 只追加的JSON-lines审计日志
+pub struct AuditLog {
+    file: Mutex<File>,
+    /// 每行写入后是否fsync
+    fsync: bool,
+}
+
+impl AuditLog {
+    pub fn open<P: AsRef<Path>>(path: P, fsync: bool) -> io::Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            fsync,
+        })
+    }
+
+    /// 记录一次下载，整行一次写入，避免并发请求的内容交错
+    pub fn record(&self, record: &DownloadRecord, client: Option<ClientAddr>, bytes: u64) -> io::Result<()> {
+        let entry = AuditEntry {
+            timestamp: clock::unix_secs(SystemTime::now()),
+            crate_name: &record.crate_name,
+            version: &record.version,
+            client_ip: client.map(|addr| addr.0.ip()),
+            bytes,
+            cache: if record.cache_hit { "hit" } else { "miss" },
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(&line)?;
+        if self.fsync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
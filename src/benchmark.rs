@@ -0,0 +1,288 @@
+//! 基准测试：在进程内用合成上游驱动 ProxyService，统计缓存命中和未命中的延迟与吞吐量
+
+use crate::config::Config;
+use crate::proxy::{ProxyError, ProxyService};
+use http_body_util::{Empty, Full};
+use hyper::body::Bytes;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+
+/// 基准测试参数
+#[derive(Debug, Clone)]
+pub struct BenchmarkOptions {
+    /// 每个阶段的请求数（未命中阶段每个请求对应一个不同的包）
+    pub requests: usize,
+    /// 并发请求数
+    pub concurrency: usize,
+    /// 合成上游返回的包文件大小（字节）
+    pub payload_bytes: usize,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            requests: 200,
+            concurrency: 16,
+            payload_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// 一个阶段的延迟统计
+#[derive(Debug, Clone)]
+pub struct LatencyStats {
+    /// 成功的请求数
+    pub count: usize,
+    /// 失败或返回非200的请求数
+    pub errors: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    /// 每秒完成的请求数
+    pub throughput: f64,
+}
+
+impl LatencyStats {
+    /// 由成功请求的延迟样本和阶段总耗时计算统计值（最近秩法取分位数）
+    pub fn from_samples(mut samples: Vec<Duration>, errors: usize, elapsed: Duration) -> Self {
+        samples.sort();
+        let percentile = |p: usize| -> Duration {
+            if samples.is_empty() {
+                return Duration::ZERO;
+            }
+            let rank = (p * samples.len()).div_ceil(100).max(1);
+            samples[rank - 1]
+        };
+
+        let throughput = if elapsed.is_zero() {
+            0.0
+        } else {
+            samples.len() as f64 / elapsed.as_secs_f64()
+        };
+
+        Self {
+            count: samples.len(),
+            errors,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            throughput,
+        }
+    }
+}
+
+/// 基准测试结果
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    /// 缓存未命中（从上游下载）
+    pub miss: LatencyStats,
+    /// 缓存命中
+    pub hit: LatencyStats,
+}
+
+impl BenchmarkReport {
+    pub fn print(&self) {
+        for (name, stats) in [("缓存未命中", &self.miss), ("缓存命中", &self.hit)] {
+            println!("{}:", name);
+            println!("  成功请求: {}，失败请求: {}", stats.count, stats.errors);
+            println!(
+                "  延迟 p50: {:.2}ms，p95: {:.2}ms，p99: {:.2}ms",
+                stats.p50.as_secs_f64() * 1000.0,
+                stats.p95.as_secs_f64() * 1000.0,
+                stats.p99.as_secs_f64() * 1000.0
+            );
+            println!("  吞吐量: {:.1} 请求/秒", stats.throughput);
+        }
+    }
+}
+
+/// 启动合成上游：任意包名都返回唯一版本 1.0.0，下载返回指定大小的伪造包文件
+///
+/// 代理处理请求时会阻塞调用curl，合成上游必须运行在独立线程的运行时上，避免与被测服务争抢工作线程
+fn start_synthetic_upstream(payload_bytes: usize) -> Result<SocketAddr, ProxyError> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let mut payload = vec![0u8; payload_bytes.max(2)];
+    payload[0] = 0x1f;
+    payload[1] = 0x8b;
+    let payload = Bytes::from(payload);
+    let checksum: Arc<str> = crate::checksum::sha256_hex(&payload).into();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?;
+
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let Ok(listener) = TcpListener::from_std(listener) else {
+                rat_logger::error!("合成上游监听失败");
+                return;
+            };
+
+            while let Ok((stream, _)) = listener.accept().await {
+                let payload = payload.clone();
+                let checksum = checksum.clone();
+                let service = hyper::service::service_fn(move |req: Request<hyper::body::Incoming>| {
+                    let response = synthetic_response(req.uri().path(), payload.clone(), &checksum);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+
+                tokio::spawn(async move {
+                    let io = TokioIo::new(stream);
+                    if let Err(e) = hyper::server::conn::http1::Builder::new().serve_connection(io, service).await {
+                        rat_logger::debug!("合成上游连接错误: {}", e);
+                    }
+                });
+            }
+        });
+    });
+
+    Ok(addr)
+}
+
+fn synthetic_response(path: &str, payload: Bytes, checksum: &str) -> Response<Full<Bytes>> {
+    let parts: Vec<&str> = path.trim_start_matches("/api/v1/crates/").split('/').collect();
+    let (status, body) = match parts.as_slice() {
+        [name] => {
+            let body = serde_json::json!({
+                "crate": { "id": name, "name": name, "max_version": "1.0.0", "downloads": 0 },
+                "versions": [{
+                    "num": "1.0.0",
+                    "dl_path": format!("/api/v1/crates/{}/1.0.0/download", name),
+                    "checksum": checksum,
+                    "yanked": false,
+                }],
+            });
+            (StatusCode::OK, Bytes::from(body.to_string()))
+        }
+        [_, "1.0.0", "download"] => (StatusCode::OK, payload),
+        _ => (StatusCode::NOT_FOUND, Bytes::new()),
+    };
+
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// 并发请求一组路径，返回阶段统计
+async fn run_phase(service: &ProxyService, paths: Vec<String>, concurrency: usize) -> LatencyStats {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let started = Instant::now();
+    let mut tasks = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let service = service.clone();
+        let semaphore = semaphore.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await.ok()?;
+            let request = Request::builder().uri(&path).body(Empty::<Bytes>::new()).ok()?;
+            let request_started = Instant::now();
+            match service.handle_request(request).await {
+                Ok(response) if response.status() == StatusCode::OK => Some(request_started.elapsed()),
+                Ok(response) => {
+                    rat_logger::warn!("基准请求 {} 返回 {}", path, response.status());
+                    None
+                }
+                Err(e) => {
+                    rat_logger::warn!("基准请求 {} 失败: {}", path, e);
+                    None
+                }
+            }
+        }));
+    }
+
+    let mut samples = Vec::with_capacity(tasks.len());
+    let mut errors = 0;
+    for task in tasks {
+        match task.await {
+            Ok(Some(latency)) => samples.push(latency),
+            _ => errors += 1,
+        }
+    }
+
+    LatencyStats::from_samples(samples, errors, started.elapsed())
+}
+
+/// 运行基准测试。使用独立的临时缓存目录，不影响正式缓存
+pub async fn run_benchmark(config: &Config, options: &BenchmarkOptions) -> Result<BenchmarkReport, ProxyError> {
+    let upstream = start_synthetic_upstream(options.payload_bytes)?;
+
+    let storage_path = std::env::temp_dir().join(format!("crates_proxy_bench_{}", std::process::id()));
+    let mut bench_config = config.clone();
+    bench_config.cache.storage_path = storage_path.display().to_string();
+    bench_config.cache.hot_path = None;
+    bench_config.cache.cold_path = None;
+    bench_config.cache.readonly_fallback_paths.clear();
+    bench_config.upstream.api_url = format!("http://{}", upstream);
+    bench_config.upstream.proxy_url = None;
+    bench_config.upstream.validate_crate_structure = false;
+
+    let result = run_with_service(&bench_config, options).await;
+    remove_storage(&storage_path);
+    result
+}
+
+async fn run_with_service(config: &Config, options: &BenchmarkOptions) -> Result<BenchmarkReport, ProxyError> {
+    let service = ProxyService::new(config)?;
+    let paths: Vec<String> = (0..options.requests)
+        .map(|i| format!("/api/v1/crates/bench-{}/1.0.0/download", i))
+        .collect();
+
+    println!("未命中阶段: {} 个请求，并发 {}", paths.len(), options.concurrency);
+    let miss = run_phase(&service, paths.clone(), options.concurrency).await;
+    println!("命中阶段: {} 个请求，并发 {}", paths.len(), options.concurrency);
+    let hit = run_phase(&service, paths, options.concurrency).await;
+
+    Ok(BenchmarkReport { miss, hit })
+}
+
+fn remove_storage(path: &Path) {
+    if let Err(e) = std::fs::remove_dir_all(path) {
+        rat_logger::warn!("删除基准测试临时目录失败 {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let stats = LatencyStats::from_samples(samples, 0, Duration::from_secs(2));
+
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p95, Duration::from_millis(95));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.throughput, 50.0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_tiny_benchmark_produces_stats() {
+        let options = BenchmarkOptions {
+            requests: 6,
+            concurrency: 2,
+            payload_bytes: 1024,
+        };
+        let report = run_benchmark(&Config::default(), &options).await.unwrap();
+
+        for stats in [&report.miss, &report.hit] {
+            assert_eq!(stats.count, 6);
+            assert_eq!(stats.errors, 0);
+            assert!(stats.p50 > Duration::ZERO);
+            assert!(stats.p50 <= stats.p95 && stats.p95 <= stats.p99);
+            assert!(stats.throughput > 0.0);
+        }
+    }
+}
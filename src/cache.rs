@@ -1,16 +1,70 @@
+use crate::checksum::sha256_hex;
+use crate::clock;
+use crate::config::{Config, DEFAULT_FILENAME_TEMPLATE, EvictionPolicy, REGISTRIES_DIR, Recompress};
+use crate::index_cache::{API_CACHE_DIR, INDEX_CACHE_DIR};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+/// 版本管理器数据库在缓存目录下的子目录名，遍历缓存文件时需要跳过
+pub const VERSIONS_DB_DIR: &str = "versions_db";
+
+/// 本地发布的包在缓存目录下的子目录名，不参与过期清理和淘汰
+pub const LOCAL_CRATES_DIR: &str = "local_crates";
+
 #[derive(Debug, Error)]
 pub enum CacheError {
+    #[error("缓存不存在: {0}")]
+    NotFound(String),
+    #[error("没有访问缓存的权限: {0}")]
+    PermissionDenied(String),
+    #[error("缓存磁盘空间已满: {0}")]
+    StorageFull(String),
     #[error("IO错误: {0}")]
-    IoError(#[from] std::io::Error),
+    IoError(std::io::Error),
     #[error("路径构建错误: {0}")]
+    #[allow(dead_code)]
     PathError(String),
 }
 
+/// zstd帧的魔数，用于识别再压缩过的缓存文件
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Linux下的 ENOSPC（设备上没有剩余空间）
+const ENOSPC: i32 = 28;
+/// Linux下的 EDQUOT（超出磁盘配额）
+const EDQUOT: i32 = 122;
+
+/// IO错误是否由磁盘空间或配额耗尽导致
+pub fn is_storage_full(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(e.kind(), ErrorKind::StorageFull | ErrorKind::QuotaExceeded)
+        || matches!(e.raw_os_error(), Some(ENOSPC | EDQUOT))
+}
+
+impl From<std::io::Error> for CacheError {
+    /// 按 `io::ErrorKind` 细分错误，便于调用方映射为不同的HTTP状态码
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        match e.kind() {
+            ErrorKind::NotFound => CacheError::NotFound(e.to_string()),
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => {
+                CacheError::PermissionDenied(e.to_string())
+            }
+            _ if is_storage_full(&e) => CacheError::StorageFull(e.to_string()),
+            _ => CacheError::IoError(e),
+        }
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct CacheEntry {
     pub path: PathBuf,
@@ -21,63 +75,414 @@ pub struct CacheEntry {
 #[derive(Debug)]
 pub struct CacheManager {
     storage_path: PathBuf,
-    default_ttl: u64,
+    default_ttl: AtomicU64,
+    /// `.crate` 文件的TTL（秒），None时使用 `default_ttl`，0表示永不过期
+    crate_ttl: Option<u64>,
+    /// 可用空间告警阈值（字节）
+    min_free_space_bytes: u64,
+    /// 缓存总大小上限（字节），0表示不限制
+    max_size_bytes: u64,
+    /// 超过上限时的淘汰策略
+    eviction_policy: EvictionPolicy,
+    /// 文件访问记录，供LRU/LFU策略使用（仅在内存中维护）
+    access_records: Mutex<HashMap<PathBuf, AccessRecord>>,
+    /// 逻辑访问时钟，保证访问先后顺序可比较
+    access_clock: AtomicU64,
+    /// 冷层目录，设置后 `storage_path` 作为热层使用
+    cold_path: Option<PathBuf>,
+    /// 热层大小上限（字节），超出时把文件降级到冷层，0表示不限制
+    hot_max_size_bytes: u64,
+    /// 热层（未分层时即整个缓存）中缓存文件的总大小，写入、删除和移动时增量更新
+    hot_bytes: AtomicU64,
+    /// 冷层中缓存文件的总大小
+    cold_bytes: AtomicU64,
+    /// 是否已遍历目录统计过 `hot_bytes` 和 `cold_bytes`，首次检查大小上限时统计
+    sizes_counted: AtomicBool,
+    /// 落盘时的再压缩方式
+    recompress: Recompress,
+    /// TTL过期判断允许的时钟偏差（秒）
+    clock_skew_tolerance: u64,
+    /// 只读的cargo注册表缓存目录
+    readonly_fallback_paths: Vec<PathBuf>,
+    /// 包目录前的哈希前缀目录层数，0表示包目录直接位于缓存根目录下
+    shard_depth: usize,
+    /// 只读模式：不创建目录，读取冷层文件时不提升到热层
+    read_only: bool,
+    /// 缓存包文件的文件名模板，见 `crate_filename`
+    filename_template: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct AccessRecord {
+    /// 最后一次访问的逻辑时间
+    last_access: u64,
+    /// 访问次数
+    hits: u64,
+}
+
+/// 一次淘汰的结果
+#[derive(Debug, Default)]
+pub struct EvictionReport {
+    pub evicted_files: u64,
+    pub freed_bytes: u64,
+    /// 从热层降级到冷层的文件数
+    pub demoted_files: u64,
+}
+
+/// 缓存文件（路径、大小、修改时间）
+type CacheFile = (PathBuf, u64, SystemTime);
+
+/// 缓存所在文件系统的空间使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    /// 可用空间是否低于告警阈值
+    pub low_space: bool,
+}
+
+/// 按semver从新到旧排序的比较函数，无法解析的版本号排在最后
+pub fn newest_first(a: &str, b: &str) -> std::cmp::Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => b.cmp(&a),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => b.cmp(a),
+    }
+}
+
+/// 文件的修改时间（Unix秒）
+pub fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    Some(clock::unix_secs(modified))
 }
 
 impl CacheManager {
     pub fn new<P: AsRef<Path>>(storage_path: P, default_ttl: u64) -> Result<Self, CacheError> {
-        let storage_path = storage_path.as_ref().to_path_buf();
-        fs::create_dir_all(&storage_path)?;
+        fs::create_dir_all(storage_path.as_ref())?;
+        Ok(Self::without_create(storage_path, default_ttl))
+    }
+
+    /// 不创建缓存目录，供只读模式使用
+    fn without_create<P: AsRef<Path>>(storage_path: P, default_ttl: u64) -> Self {
+        Self {
+            storage_path: storage_path.as_ref().to_path_buf(),
+            default_ttl: AtomicU64::new(default_ttl),
+            crate_ttl: None,
+            min_free_space_bytes: 0,
+            max_size_bytes: 0,
+            eviction_policy: EvictionPolicy::default(),
+            access_records: Mutex::new(HashMap::new()),
+            access_clock: AtomicU64::new(0),
+            cold_path: None,
+            hot_max_size_bytes: 0,
+            hot_bytes: AtomicU64::new(0),
+            cold_bytes: AtomicU64::new(0),
+            sizes_counted: AtomicBool::new(false),
+            recompress: Recompress::None,
+            clock_skew_tolerance: 0,
+            readonly_fallback_paths: Vec::new(),
+            shard_depth: 0,
+            read_only: false,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+        }
+    }
+
+    /// 根据完整配置创建缓存管理器
+    pub fn from_config(config: &Config) -> Result<Self, CacheError> {
+        let files_path = config.cache.hot_path.as_deref().unwrap_or(&config.cache.storage_path);
+        let mut manager = if config.cache.read_only {
+            Self::without_create(files_path, config.cache.default_ttl)
+        } else {
+            Self::new(files_path, config.cache.default_ttl)?
+        };
+        manager.read_only = config.cache.read_only;
+        manager.crate_ttl = config.cache.crate_ttl;
+        manager.filename_template = config.cache.filename_template.clone();
+        manager.min_free_space_bytes = config.cache.min_free_space_bytes;
+        manager.max_size_bytes = config.cache.max_size_bytes;
+        manager.eviction_policy = config.cache.eviction_policy;
+        manager.recompress = config.cache.recompress;
+        manager.clock_skew_tolerance = config.cache.clock_skew_tolerance;
+        manager.readonly_fallback_paths = config.cache.readonly_fallback_paths.iter().map(PathBuf::from).collect();
+        manager.shard_depth = config.cache.shard_depth as usize;
+        if let Some(cold_path) = &config.cache.cold_path {
+            if !manager.read_only {
+                fs::create_dir_all(cold_path)?;
+            }
+            manager.cold_path = Some(PathBuf::from(cold_path));
+            manager.hot_max_size_bytes = config.cache.hot_max_size_bytes;
+        }
+        Ok(manager)
+    }
+
+    /// 查询缓存目录所在文件系统的总空间和可用空间，可用空间不足时记录警告
+    pub fn disk_usage(&self) -> Result<DiskUsage, CacheError> {
+        let total_bytes = fs2::total_space(&self.storage_path)?;
+        let available_bytes = fs2::available_space(&self.storage_path)?;
+        let low_space = available_bytes < self.min_free_space_bytes;
+
+        if low_space {
+            rat_logger::warn!(
+                "缓存磁盘可用空间不足: 剩余 {} 字节，告警阈值 {} 字节",
+                available_bytes,
+                self.min_free_space_bytes
+            );
+        }
 
-        Ok(Self {
-            storage_path,
-            default_ttl,
+        Ok(DiskUsage {
+            total_bytes,
+            available_bytes,
+            low_space,
         })
     }
 
+    /// 当前的默认TTL（秒）
+    pub fn default_ttl(&self) -> u64 {
+        self.default_ttl.load(Ordering::Relaxed)
+    }
+
+    /// 运行时调整默认TTL（配置重载时使用）
+    pub fn set_default_ttl(&self, ttl: u64) {
+        self.default_ttl.store(ttl, Ordering::Relaxed);
+    }
+
+    /// 包目录：`{root}/{分片前缀}/{crate}`，分片前缀取包名sha256的前 `shard_depth` 个字节，每个字节一层目录
+    fn crate_dir(&self, root: &Path, crate_name: &str) -> PathBuf {
+        let hash = sha256_hex(crate_name.as_bytes());
+        let mut dir = root.to_path_buf();
+        for level in 0..self.shard_depth {
+            dir.push(&hash[level * 2..level * 2 + 2]);
+        }
+        dir.join(crate_name)
+    }
+
     pub fn get_cache_path(&self, crate_name: &str, version: &str, filename: &str) -> PathBuf {
-        let path = self.storage_path
-            .join(crate_name)
+        let path = self.crate_dir(&self.storage_path, crate_name)
             .join(version)
             .join(filename);
 
         // 确保目录存在
-        if let Some(parent) = path.parent() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                rat_logger::error!("创建缓存目录失败: {:?}, 错误: {}", parent, e);
-            }
+        if !self.read_only
+            && let Some(parent) = path.parent()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
+            rat_logger::error!("创建缓存目录失败: {:?}, 错误: {}", parent, e);
         }
 
         path
     }
 
+    /// 缓存文件的写入时间（Unix秒），即TTL的计算起点，文件不存在时返回None
+    pub fn cached_at(&self, crate_name: &str, version: &str, filename: &str) -> Option<u64> {
+        let path = self.get_cache_path(crate_name, version, filename);
+        modified_secs(&path).or_else(|| modified_secs(&self.cold_counterpart(&path)?))
+    }
+
+    /// 缓存文件是否会在window秒内过期（包括已过期和不在缓存中），永不过期的文件返回false
+    pub fn expires_within(&self, crate_name: &str, version: &str, filename: &str, window: u64) -> bool {
+        let Some(cached_at) = self.cached_at(crate_name, version, filename) else {
+            return true;
+        };
+        let Some(ttl) = self.file_ttl(Path::new(filename)) else {
+            return false;
+        };
+        let now = clock::unix_secs(SystemTime::now());
+        cached_at.saturating_add(ttl) <= now.saturating_add(window)
+    }
+
     pub fn is_cached(&self, crate_name: &str, version: &str, filename: &str) -> bool {
         let path = self.get_cache_path(crate_name, version, filename);
-        path.exists() // 临时禁用TTL检查
+        // 临时禁用TTL检查
+        path.exists() || self.cold_counterpart(&path).is_some_and(|cold| cold.exists())
     }
 
-    pub fn is_expired(&self, path: &Path) -> bool {
-        if let Ok(metadata) = fs::metadata(path) {
-            if let Ok(created) = metadata.created() {
-                if let Ok(duration) = created.duration_since(UNIX_EPOCH) {
-                    let elapsed = duration.as_secs();
-                    return elapsed > self.default_ttl;
+    /// 缓存文件返回给客户端时的大小，再压缩的文件按还原后的大小计算，文件不存在时返回None
+    pub fn cached_size(&self, crate_name: &str, version: &str, filename: &str) -> Option<u64> {
+        let path = self.get_cache_path(crate_name, version, filename);
+        let path = if path.exists() { path } else { self.cold_counterpart(&path).filter(|cold| cold.exists())? };
+
+        let mut magic = [0u8; 4];
+        let compressed = fs::File::open(&path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == ZSTD_MAGIC;
+        if compressed {
+            return Self::decode(fs::read(&path).ok()?).ok().map(|content| content.len() as u64);
+        }
+        fs::metadata(&path).ok().map(|metadata| metadata.len())
+    }
+
+    /// 包文件在缓存目录中的文件名，按 `cache.filename_template` 替换 `{crate}` 和 `{version}`
+    pub fn crate_filename(&self, crate_name: &str, version: &str) -> String {
+        self.filename_template.replace("{crate}", crate_name).replace("{version}", version)
+    }
+
+    /// 列出包在本地缓存（包括冷层）中已有包文件的版本，按semver从新到旧排序，无法解析的版本号排在最后
+    pub fn cached_versions(&self, crate_name: &str) -> Vec<String> {
+        let mut versions: Vec<String> = Vec::new();
+        let roots = std::iter::once(&self.storage_path).chain(self.cold_path.as_ref());
+        for root in roots {
+            let Ok(entries) = fs::read_dir(self.crate_dir(root, crate_name)) else { continue };
+            for entry in entries.flatten() {
+                let version = entry.file_name().to_string_lossy().into_owned();
+                let crate_file = entry.path().join(self.crate_filename(crate_name, &version));
+                if crate_file.is_file() && !versions.contains(&version) {
+                    versions.push(version);
                 }
             }
         }
-        true
+
+        versions.sort_by(|a, b| newest_first(a, b));
+        versions
+    }
+
+    /// 列出所有缓存文件（包括冷层），返回 `{crate}/{version}/{file}` 形式的相对路径（不含分片前缀）和实际路径
+    pub fn list_cache_files(&self) -> Result<Vec<(PathBuf, PathBuf)>, CacheError> {
+        let mut listed = Vec::new();
+        for root in std::iter::once(&self.storage_path).chain(self.cold_path.as_ref()) {
+            let mut files = Vec::new();
+            self.collect_cache_files(root, root, &mut files)?;
+            for (path, _, _) in files {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    let relative: PathBuf = relative.components().skip(self.shard_depth).collect();
+                    listed.push((relative, path.clone()));
+                }
+            }
+        }
+        Ok(listed)
+    }
+
+    /// 热层文件在冷层中对应的路径，未启用分层时返回None
+    fn cold_counterpart(&self, hot_path: &Path) -> Option<PathBuf> {
+        let cold_root = self.cold_path.as_ref()?;
+        let relative = hot_path.strip_prefix(&self.storage_path).ok()?;
+        Some(cold_root.join(relative))
+    }
+
+    #[allow(dead_code)]
+    pub fn is_expired(&self, path: &Path) -> bool {
+        match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => self.is_expired_since(path, modified),
+            Err(_) => true,
+        }
+    }
+
+    /// 路径是否为 `{crate}/{version}/` 下按文件名模板命名的包文件
+    fn is_crate_file(&self, path: &Path) -> bool {
+        fn name(path: Option<&Path>) -> Option<&str> {
+            path.and_then(Path::file_name).and_then(|name| name.to_str())
+        }
+        let version_dir = path.parent();
+        match (name(Some(path)), name(version_dir), name(version_dir.and_then(Path::parent))) {
+            (Some(file), Some(version), Some(crate_name)) => file == self.crate_filename(crate_name, version),
+            _ => false,
+        }
+    }
+
+    /// 文件的TTL（秒），永不过期时返回None。已发布的包文件不可变，`.crate` 可单独设置 `crate_ttl`
+    fn file_ttl(&self, path: &Path) -> Option<u64> {
+        match self.crate_ttl {
+            Some(0) if self.is_crate_file(path) => None,
+            Some(ttl) if self.is_crate_file(path) => Some(ttl),
+            _ => Some(self.default_ttl()),
+        }
+    }
+
+    /// 按写入时间判断文件是否超过TTL，系统时钟回拨超过容差时按过期处理
+    fn is_expired_since(&self, path: &Path, modified: SystemTime) -> bool {
+        let Some(ttl) = self.file_ttl(path) else {
+            return false;
+        };
+        let modified = clock::unix_secs(modified);
+        let now = clock::unix_secs(SystemTime::now());
+        clock::is_expired(now, modified, modified.saturating_add(ttl), self.clock_skew_tolerance)
     }
 
     pub fn get_cached_content(&self, crate_name: &str, version: &str, filename: &str) -> Result<Vec<u8>, CacheError> {
         let path = self.get_cache_path(crate_name, version, filename);
 
         if !self.is_cached(crate_name, version, filename) {
-            return Err(CacheError::PathError("缓存不存在或已过期".to_string()));
+            return Err(CacheError::NotFound(format!("{:?}", path)));
+        }
+
+        if self.read_only {
+            let path = if path.exists() { path } else { self.cold_counterpart(&path).unwrap_or(path) };
+            return Self::decode(fs::read(&path)?);
+        }
+
+        if !path.exists()
+            && let Some(cold) = self.cold_counterpart(&path)
+        {
+            rat_logger::info!("冷层命中，提升到热层: {:?}", path);
+            self.move_file(&cold, &path)?;
         }
 
-        Ok(fs::read(path)?)
+        // 提升后热层可能超出上限，由写入后和定期执行的 `enforce_size_limit` 降级，避免每次读取都遍历热层
+        let content = Self::decode(fs::read(&path)?)?;
+        self.record_access(&path, true);
+        Ok(content)
+    }
+
+    /// 在只读的cargo注册表缓存中查找 `{name}-{version}.crate`
+    ///
+    /// cargo的布局为 `registry/cache/<索引目录>/{name}-{version}.crate`，
+    /// 因此既查找配置目录本身，也查找其下一级子目录。
+    pub fn find_in_fallback(&self, crate_name: &str, version: &str) -> Option<PathBuf> {
+        let filename = format!("{}-{}.crate", crate_name, version);
+
+        for root in &self.readonly_fallback_paths {
+            let direct = root.join(&filename);
+            if direct.is_file() {
+                return Some(direct);
+            }
+
+            let Ok(entries) = fs::read_dir(root) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let candidate = entry.path().join(&filename);
+                if candidate.is_file() {
+                    return Some(candidate);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// 在两个层之间移动文件，跨文件系统时退化为复制后删除，访问记录随文件转移
+    fn move_file(&self, from: &Path, to: &Path) -> Result<(), CacheError> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let size = fs::metadata(from)?.len();
+        if fs::rename(from, to).is_err() {
+            fs::copy(from, to)?;
+            fs::remove_file(from)?;
+        }
+        self.sub_size(from, size);
+        self.add_size(to, size);
+
+        let mut records = self.access_records.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(record) = records.remove(from) {
+            records.insert(to.to_path_buf(), record);
+        }
+        drop(records);
+
+        self.remove_empty_parents(from);
+        Ok(())
     }
 
+    /// 记录一次文件访问，hit为false时只更新访问时间（例如新写入）
+    fn record_access(&self, path: &Path, hit: bool) {
+        let now = self.access_clock.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut records = self.access_records.lock().unwrap_or_else(PoisonError::into_inner);
+        let record = records.entry(path.to_path_buf()).or_default();
+        record.last_access = now;
+        if hit {
+            record.hits += 1;
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn save_to_cache(&self, crate_name: &str, version: &str, filename: &str, content: &[u8]) -> Result<(), CacheError> {
         let path = self.get_cache_path(crate_name, version, filename);
 
@@ -86,48 +491,382 @@ impl CacheManager {
             fs::create_dir_all(parent)?;
         }
 
-        fs::write(path, content)?;
+        let previous_size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        let encoded = self.encode(content)?;
+        fs::write(&path, &encoded)?;
+        self.sub_size(&path, previous_size);
+        self.add_size(&path, encoded.len() as u64);
+        self.record_access(&path, false);
         Ok(())
     }
 
-    pub fn clear_expired_cache(&self) -> Result<(), CacheError> {
-        self.clear_expired_cache_recursive(&self.storage_path)?;
-        Ok(())
+    /// 计入下载器直接写入（并已再压缩）的缓存文件的大小
+    pub fn record_saved_file(&self, crate_name: &str, version: &str, filename: &str) {
+        let path = self.get_cache_path(crate_name, version, filename);
+        if let Ok(metadata) = fs::metadata(&path) {
+            self.add_size(&path, metadata.len());
+        }
     }
 
-    fn clear_expired_cache_recursive(&self, dir: &Path) -> Result<(), CacheError> {
-        if !dir.exists() {
+    /// 文件所在层的总大小计数
+    fn tier_bytes(&self, path: &Path) -> &AtomicU64 {
+        match &self.cold_path {
+            Some(cold_path) if path.starts_with(cold_path) => &self.cold_bytes,
+            _ => &self.hot_bytes,
+        }
+    }
+
+    fn add_size(&self, path: &Path, size: u64) {
+        self.tier_bytes(path).fetch_add(size, Ordering::Relaxed);
+    }
+
+    fn sub_size(&self, path: &Path, size: u64) {
+        let _ = self
+            .tier_bytes(path)
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| Some(total.saturating_sub(size)));
+    }
+
+    /// 遍历目录重新统计各层的总大小，修正增量更新遗漏的变化（如导入或手工删除的文件）。
+    /// 未配置任何大小上限时不统计
+    pub fn recount_sizes(&self) -> Result<(), CacheError> {
+        if self.max_size_bytes == 0 && (self.cold_path.is_none() || self.hot_max_size_bytes == 0) {
             return Ok(());
         }
+        let tier_size = |root: &Path| -> Result<u64, CacheError> {
+            let mut files = Vec::new();
+            self.collect_cache_files(root, root, &mut files)?;
+            Ok(files.iter().map(|(_, size, _)| size).sum())
+        };
+        self.hot_bytes.store(tier_size(&self.storage_path)?, Ordering::Relaxed);
+        if let Some(cold_path) = &self.cold_path {
+            self.cold_bytes.store(tier_size(cold_path)?, Ordering::Relaxed);
+        }
+        self.sizes_counted.store(true, Ordering::Relaxed);
+        Ok(())
+    }
 
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+    /// 本地发布的包文件路径：`{storage_path}/local_crates/{crate}/{version}/{crate}-{version}.crate`
+    pub fn local_crate_path(&self, crate_name: &str, version: &str) -> PathBuf {
+        self.storage_path
+            .join(LOCAL_CRATES_DIR)
+            .join(crate_name)
+            .join(version)
+            .join(format!("{}-{}.crate", crate_name, version))
+    }
 
-            if path.is_dir() {
-                self.clear_expired_cache_recursive(&path)?;
+    /// 包在本地发布目录中已有包文件的版本，按semver从新到旧排序
+    pub fn local_crate_versions(&self, crate_name: &str) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(self.storage_path.join(LOCAL_CRATES_DIR).join(crate_name)) else {
+            return Vec::new();
+        };
+        let mut versions: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|version| self.local_crate_path(crate_name, version).is_file())
+            .collect();
+        versions.sort_by(|a, b| newest_first(a, b));
+        versions
+    }
+
+    /// 保存本地发布的包文件，先写临时文件再重命名，不做再压缩
+    pub fn save_local_crate(&self, crate_name: &str, version: &str, content: &[u8]) -> Result<(), CacheError> {
+        let path = self.local_crate_path(crate_name, version);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temp_path = crate::crates_api::partial_path(&path);
+        if let Err(e) = fs::write(&temp_path, content).and_then(|_| fs::rename(&temp_path, &path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// 读取本地发布的包文件
+    pub fn get_local_crate(&self, crate_name: &str, version: &str) -> Result<Vec<u8>, CacheError> {
+        let path = self.local_crate_path(crate_name, version);
+        match fs::read(&path) {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(CacheError::NotFound(format!("本地包文件不存在: {:?}", path)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 缓存中出现过的全部包名，包括冷层和本地发布的包
+    pub fn crate_names(&self) -> Result<BTreeSet<String>, CacheError> {
+        let mut names: BTreeSet<String> = self
+            .list_cache_files()?
+            .into_iter()
+            .filter_map(|(relative, _)| Some(relative.components().next()?.as_os_str().to_string_lossy().into_owned()))
+            .collect();
+        if let Ok(entries) = fs::read_dir(self.storage_path.join(LOCAL_CRATES_DIR)) {
+            names.extend(entries.flatten().map(|entry| entry.file_name().to_string_lossy().into_owned()));
+        }
+        Ok(names)
+    }
 
-                // 如果目录为空，删除它
-                if fs::read_dir(&path)?.next().is_none() {
-                    fs::remove_dir(&path)?;
+    /// 把一个包目录（热层、冷层和本地发布目录）中的文件合并到另一个包名下，文件名中的包名一并替换。
+    /// 目标中已有同名文件时保留目标文件并删除源文件，返回移动的文件数
+    pub fn merge_crate_dir(&self, from: &str, to: &str) -> Result<usize, CacheError> {
+        let mut dirs = vec![(self.crate_dir(&self.storage_path, from), self.crate_dir(&self.storage_path, to))];
+        if let Some(cold_path) = &self.cold_path {
+            dirs.push((self.crate_dir(cold_path, from), self.crate_dir(cold_path, to)));
+        }
+        let local_root = self.storage_path.join(LOCAL_CRATES_DIR);
+        dirs.push((local_root.join(from), local_root.join(to)));
+
+        let file_prefix = format!("{}-", from);
+        let mut moved = 0;
+        for (source_dir, target_dir) in dirs {
+            let Ok(versions) = fs::read_dir(&source_dir) else { continue };
+            for version in versions {
+                let version = version?;
+                if !version.path().is_dir() {
+                    continue;
                 }
-            } else {
-                if self.is_expired(&path) {
-                    fs::remove_file(&path)?;
+                for file in fs::read_dir(version.path())? {
+                    let source = file?.path();
+                    let Some(file_name) = source.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+                        continue;
+                    };
+                    let file_name = match file_name.strip_prefix(&file_prefix) {
+                        Some(rest) => format!("{}-{}", to, rest),
+                        None => file_name,
+                    };
+                    let target = target_dir.join(version.file_name()).join(file_name);
+
+                    if target.exists() {
+                        let size = fs::metadata(&source)?.len();
+                        fs::remove_file(&source)?;
+                        self.sub_size(&source, size);
+                    } else {
+                        fs::create_dir_all(target_dir.join(version.file_name()))?;
+                        fs::rename(&source, &target)?;
+                        moved += 1;
+                    }
+                    self.access_records.lock().unwrap_or_else(PoisonError::into_inner).remove(&source);
+                    self.remove_empty_parents(&source);
+                }
+            }
+        }
+        Ok(moved)
+    }
+
+    /// 删除包的全部缓存文件（包括冷层），不影响本地发布的包，返回删除的文件数
+    pub fn remove_crate(&self, crate_name: &str) -> Result<usize, CacheError> {
+        let mut removed = 0;
+        for root in std::iter::once(&self.storage_path).chain(self.cold_path.as_ref()) {
+            let dir = self.crate_dir(root, crate_name);
+            let Ok(versions) = fs::read_dir(&dir) else { continue };
+            let mut removed_bytes = 0;
+            for version in versions.flatten() {
+                if let Ok(files) = fs::read_dir(version.path()) {
+                    for metadata in files.flatten().filter_map(|file| file.metadata().ok()).filter(fs::Metadata::is_file) {
+                        removed += 1;
+                        removed_bytes += metadata.len();
+                    }
+                }
+            }
+            fs::remove_dir_all(&dir)?;
+            self.sub_size(&dir, removed_bytes);
+            self.access_records.lock().unwrap_or_else(PoisonError::into_inner).retain(|path, _| !path.starts_with(&dir));
+            self.remove_empty_parents(&dir);
+        }
+        Ok(removed)
+    }
+
+    /// 按配置的再压缩方式编码待落盘的内容
+    fn encode(&self, content: &[u8]) -> Result<Vec<u8>, CacheError> {
+        match self.recompress {
+            Recompress::None => Ok(content.to_vec()),
+            Recompress::Zstd => Ok(zstd::encode_all(content, 0)?),
+        }
+    }
+
+    /// 删除指定的缓存文件（包括冷层中的副本），返回是否确实删除了文件
+    pub fn remove_cached_file(&self, crate_name: &str, version: &str, filename: &str) -> Result<bool, CacheError> {
+        let path = self.get_cache_path(crate_name, version, filename);
+        let mut removed = false;
+        for candidate in std::iter::once(path.clone()).chain(self.cold_counterpart(&path)) {
+            let size = fs::metadata(&candidate).map(|metadata| metadata.len()).unwrap_or(0);
+            match fs::remove_file(&candidate) {
+                Ok(()) => {
+                    self.sub_size(&candidate, size);
+                    self.access_records.lock().unwrap_or_else(PoisonError::into_inner).remove(&candidate);
+                    removed = true;
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
             }
         }
+        Ok(removed)
+    }
 
+    /// 还原缓存文件的原始内容。按魔数识别，关闭再压缩后旧文件仍可读取
+    fn decode(data: Vec<u8>) -> Result<Vec<u8>, CacheError> {
+        if data.starts_with(&ZSTD_MAGIC) {
+            return Ok(zstd::decode_all(data.as_slice())?);
+        }
+        Ok(data)
+    }
+
+    /// 对下载器直接写入的缓存文件按配置再压缩，已压缩过或未启用时不做处理
+    pub fn recompress_file(&self, crate_name: &str, version: &str, filename: &str) -> Result<(), CacheError> {
+        if self.recompress == Recompress::None {
+            return Ok(());
+        }
+
+        let path = self.get_cache_path(crate_name, version, filename);
+        let content = fs::read(&path)?;
+        if content.starts_with(&ZSTD_MAGIC) {
+            return Ok(());
+        }
+
+        let encoded = self.encode(&content)?;
+        let temp_path = path.with_extension("zst.part");
+        if let Err(e) = fs::write(&temp_path, &encoded).and_then(|_| fs::rename(&temp_path, &path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+
+        rat_logger::debug!("缓存文件已再压缩: {:?} ({} -> {} 字节)", path, content.len(), encoded.len());
         Ok(())
     }
 
-    pub fn get_cache_stats(&self) -> Result<CacheStats, CacheError> {
-        let mut stats = CacheStats::default();
-        self.calculate_stats_recursive(&self.storage_path, &mut stats)?;
-        Ok(stats)
+    /// 缓存总大小超过上限时，按配置的策略淘汰文件直到低于上限。
+    /// 启用分层时先把热层超出的文件降级到冷层，大小上限作用于冷层
+    /// 按增量维护的总大小判断是否超出上限，未超出时不遍历目录
+    pub fn enforce_size_limit(&self) -> Result<EvictionReport, CacheError> {
+        if !self.sizes_counted.load(Ordering::Relaxed) {
+            self.recount_sizes()?;
+        }
+        let demoted_files = self.demote_overflow()?;
+        let root_bytes = if self.cold_path.is_some() { &self.cold_bytes } else { &self.hot_bytes };
+        if self.max_size_bytes == 0 || root_bytes.load(Ordering::Relaxed) <= self.max_size_bytes {
+            return Ok(EvictionReport {
+                demoted_files,
+                ..Default::default()
+            });
+        }
+
+        let root = self.cold_path.as_deref().unwrap_or(&self.storage_path);
+        let mut report = self.evict_until(root, |_| self.max_size_bytes)?;
+        report.demoted_files = demoted_files;
+        Ok(report)
+    }
+
+    /// 热层超过大小上限时，按淘汰策略把文件降级到冷层，返回降级的文件数
+    fn demote_overflow(&self) -> Result<u64, CacheError> {
+        if self.cold_path.is_none()
+            || self.hot_max_size_bytes == 0
+            || self.hot_bytes.load(Ordering::Relaxed) <= self.hot_max_size_bytes
+        {
+            return Ok(0);
+        }
+
+        let (files, mut total_size) = self.eviction_candidates(&self.storage_path)?;
+        self.hot_bytes.store(total_size, Ordering::Relaxed);
+        let mut demoted = 0;
+
+        for (path, size, _) in files {
+            if total_size <= self.hot_max_size_bytes {
+                break;
+            }
+            let Some(cold) = self.cold_counterpart(&path) else {
+                continue;
+            };
+
+            match self.move_file(&path, &cold) {
+                Ok(()) => {
+                    rat_logger::info!("热层超出大小上限，降级到冷层: {:?} ({} 字节)", path, size);
+                    total_size -= size;
+                    demoted += 1;
+                }
+                Err(e) => rat_logger::warn!("降级缓存文件失败 {:?}: {}", path, e),
+            }
+        }
+
+        Ok(demoted)
+    }
+
+    /// 磁盘写满时的紧急淘汰：在大小上限和当前总大小中取较小者，再多释放10%的空间。
+    /// 未配置大小上限时不做任何淘汰
+    pub fn emergency_evict(&self) -> Result<EvictionReport, CacheError> {
+        if self.max_size_bytes == 0 {
+            return Ok(EvictionReport::default());
+        }
+        rat_logger::warn!("缓存磁盘空间已满，执行紧急淘汰");
+        self.evict_until(&self.storage_path, |total_size| total_size.min(self.max_size_bytes) / 10 * 9)
+    }
+
+    /// 按配置的策略淘汰root下的文件，target根据当前总大小给出目标大小，淘汰到不超过该值为止
+    fn evict_until(&self, root: &Path, target: impl Fn(u64) -> u64) -> Result<EvictionReport, CacheError> {
+        let mut report = EvictionReport::default();
+
+        let (files, mut total_size) = self.eviction_candidates(root)?;
+        self.tier_bytes(root).store(total_size, Ordering::Relaxed);
+        let target_size = target(total_size);
+        if total_size <= target_size {
+            return Ok(report);
+        }
+
+        for (path, size, _) in files {
+            if total_size <= target_size {
+                break;
+            }
+
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    rat_logger::info!("缓存超出目标大小 {} 字节，淘汰文件: {:?} ({} 字节)", target_size, path, size);
+                    total_size -= size;
+                    self.sub_size(&path, size);
+                    report.evicted_files += 1;
+                    report.freed_bytes += size;
+                    self.access_records.lock().unwrap_or_else(PoisonError::into_inner).remove(&path);
+                    self.remove_empty_parents(&path);
+                }
+                Err(e) => rat_logger::warn!("淘汰缓存文件失败 {:?}: {}", path, e),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// 收集root下的缓存文件并按淘汰顺序排序，同时返回总大小
+    fn eviction_candidates(&self, root: &Path) -> Result<(Vec<CacheFile>, u64), CacheError> {
+        let mut files = Vec::new();
+        self.collect_cache_files(root, root, &mut files)?;
+        let total_size: u64 = files.iter().map(|(_, size, _)| size).sum();
+
+        {
+            let records = self.access_records.lock().unwrap_or_else(PoisonError::into_inner);
+            let record_of = |path: &PathBuf| records.get(path).copied().unwrap_or_default();
+
+            // 排在前面的文件先被淘汰，同等条件下按修改时间从旧到新
+            match self.eviction_policy {
+                EvictionPolicy::Ttl => {
+                    files.sort_by_key(|(path, _, modified)| (!self.is_expired_since(path, *modified), *modified));
+                }
+                EvictionPolicy::Lru => {
+                    files.sort_by_key(|(path, _, modified)| (record_of(path).last_access, *modified));
+                }
+                EvictionPolicy::Lfu => {
+                    files.sort_by_key(|(path, _, modified)| {
+                        let record = record_of(path);
+                        (record.hits, record.last_access, *modified)
+                    });
+                }
+            }
+        }
+
+        Ok((files, total_size))
     }
 
-    fn calculate_stats_recursive(&self, dir: &Path, stats: &mut CacheStats) -> Result<(), CacheError> {
+    /// 收集缓存文件（路径、大小、修改时间），跳过版本数据库、索引缓存、本地发布和其他注册表的目录
+    fn collect_cache_files(&self, root: &Path, dir: &Path, files: &mut Vec<CacheFile>) -> Result<(), CacheError> {
         if !dir.exists() {
             return Ok(());
         }
@@ -137,23 +876,77 @@ impl CacheManager {
             let path = entry.path();
 
             if path.is_dir() {
-                self.calculate_stats_recursive(&path, stats)?;
-            } else {
-                stats.total_files += 1;
-                if let Ok(metadata) = fs::metadata(&path) {
-                    stats.total_size += metadata.len();
+                if dir == root && [VERSIONS_DB_DIR, INDEX_CACHE_DIR, API_CACHE_DIR, LOCAL_CRATES_DIR, REGISTRIES_DIR].iter().any(|name| entry.file_name() == *name) {
+                    continue;
                 }
+                self.collect_cache_files(root, &path, files)?;
+            } else if dir == root {
+                // 根目录下只有实例锁等辅助文件，缓存文件都在包目录中
+                continue;
+            } else if let Ok(metadata) = entry.metadata() {
+                let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+                files.push((path, metadata.len(), modified));
+            }
+        }
 
-                if self.is_expired(&path) {
-                    stats.expired_files += 1;
-                } else {
-                    stats.valid_files += 1;
-                }
+        Ok(())
+    }
+
+    /// 删除文件后清理空的版本目录和包目录
+    fn remove_empty_parents(&self, path: &Path) {
+        let mut current = path.parent();
+        while let Some(dir) = current {
+            if dir == self.storage_path || self.cold_path.as_deref() == Some(dir) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+            current = dir.parent();
+        }
+    }
+
+    /// 删除过期的缓存文件，跳过版本数据库和根目录下的辅助文件
+    pub fn clear_expired_cache(&self) -> Result<(), CacheError> {
+        let mut files = Vec::new();
+        self.collect_cache_files(&self.storage_path, &self.storage_path, &mut files)?;
+        if let Some(cold_path) = &self.cold_path {
+            self.collect_cache_files(cold_path, cold_path, &mut files)?;
+        }
+
+        for (path, size, modified) in files {
+            if self.is_expired_since(&path, modified) {
+                fs::remove_file(&path)?;
+                self.sub_size(&path, size);
+                self.access_records.lock().unwrap_or_else(PoisonError::into_inner).remove(&path);
+                self.remove_empty_parents(&path);
             }
         }
 
         Ok(())
     }
+
+    pub fn get_cache_stats(&self) -> Result<CacheStats, CacheError> {
+        let mut files = Vec::new();
+        self.collect_cache_files(&self.storage_path, &self.storage_path, &mut files)?;
+        if let Some(cold_path) = &self.cold_path {
+            self.collect_cache_files(cold_path, cold_path, &mut files)?;
+        }
+
+        let mut stats = CacheStats::default();
+        for (path, size, modified) in files {
+            stats.total_files += 1;
+            stats.total_size += size;
+            if self.is_expired_since(&path, modified) {
+                stats.expired_files += 1;
+            } else {
+                stats.valid_files += 1;
+            }
+        }
+
+        let disk = self.disk_usage()?;
+        stats.disk_total_bytes = disk.total_bytes;
+        stats.disk_available_bytes = disk.available_bytes;
+        stats.disk_low_space = disk.low_space;
+        Ok(stats)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -162,4 +955,392 @@ pub struct CacheStats {
     pub valid_files: u64,
     pub expired_files: u64,
     pub total_size: u64,
+    /// 缓存所在文件系统的总空间
+    pub disk_total_bytes: u64,
+    /// 缓存所在文件系统的可用空间
+    pub disk_available_bytes: u64,
+    /// 可用空间是否低于告警阈值
+    pub disk_low_space: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_stats_include_disk_usage() {
+        let dir = tempdir().unwrap();
+        let manager = CacheManager::new(dir.path(), 3600).unwrap();
+        manager.save_to_cache("serde", "1.0.0", "serde-1.0.0.crate", b"data").unwrap();
+
+        let stats = manager.get_cache_stats().unwrap();
+        assert_eq!(stats.total_files, 1);
+        assert!(stats.disk_total_bytes > 0);
+        assert!(stats.disk_available_bytes > 0);
+        assert!(stats.disk_available_bytes <= stats.disk_total_bytes);
+    }
+
+    fn eviction_manager(dir: &Path, policy: EvictionPolicy) -> CacheManager {
+        let mut config = Config::default();
+        config.cache.storage_path = dir.display().to_string();
+        config.cache.max_size_bytes = 25;
+        config.cache.eviction_policy = policy;
+        CacheManager::from_config(&config).unwrap()
+    }
+
+    /// 写入a、b、c三个10字节文件并制造访问模式：
+    /// a 访问5次但最早，b 访问1次，c 访问2次且最近
+    fn seed_access_pattern(manager: &CacheManager) {
+        for name in ["a", "b", "c"] {
+            manager.save_to_cache(name, "1.0.0", "file.crate", &[0u8; 10]).unwrap();
+        }
+        for _ in 0..5 {
+            manager.get_cached_content("a", "1.0.0", "file.crate").unwrap();
+        }
+        manager.get_cached_content("b", "1.0.0", "file.crate").unwrap();
+        for _ in 0..2 {
+            manager.get_cached_content("c", "1.0.0", "file.crate").unwrap();
+        }
+    }
+
+    fn remaining(manager: &CacheManager) -> Vec<&'static str> {
+        ["a", "b", "c"]
+            .into_iter()
+            .filter(|name| manager.storage_path.join(name).join("1.0.0").join("file.crate").exists())
+            .collect()
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let dir = tempdir().unwrap();
+        let manager = eviction_manager(dir.path(), EvictionPolicy::Lru);
+        seed_access_pattern(&manager);
+
+        let report = manager.enforce_size_limit().unwrap();
+        assert_eq!(report.evicted_files, 1);
+        assert_eq!(report.freed_bytes, 10);
+        assert_eq!(remaining(&manager), vec!["b", "c"]);
+        assert!(!dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_frequently_used() {
+        let dir = tempdir().unwrap();
+        let manager = eviction_manager(dir.path(), EvictionPolicy::Lfu);
+        seed_access_pattern(&manager);
+
+        manager.enforce_size_limit().unwrap();
+        assert_eq!(remaining(&manager), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_ttl_evicts_oldest_and_skips_version_db() {
+        let dir = tempdir().unwrap();
+        let manager = eviction_manager(dir.path(), EvictionPolicy::Ttl);
+        seed_access_pattern(&manager);
+
+        let db_file = dir.path().join(VERSIONS_DB_DIR).join("data");
+        fs::create_dir_all(db_file.parent().unwrap()).unwrap();
+        fs::write(&db_file, [0u8; 100]).unwrap();
+
+        // c 最先写入
+        let now = SystemTime::now();
+        for (name, age) in [("a", 10), ("b", 20), ("c", 30)] {
+            let path = dir.path().join(name).join("1.0.0").join("file.crate");
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age)).unwrap();
+        }
+
+        manager.enforce_size_limit().unwrap();
+        assert_eq!(remaining(&manager), vec!["a", "b"]);
+        assert!(db_file.exists());
+    }
+
+    #[test]
+    fn test_emergency_evict_frees_space_below_limit() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.max_size_bytes = 100;
+        config.cache.eviction_policy = EvictionPolicy::Lru;
+        let manager = CacheManager::from_config(&config).unwrap();
+        seed_access_pattern(&manager);
+
+        // 30字节未超过上限，普通检查不淘汰
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 0);
+
+        let report = manager.emergency_evict().unwrap();
+        assert_eq!(report.evicted_files, 1);
+        assert_eq!(remaining(&manager), vec!["b", "c"]);
+    }
+
+    fn tiered_manager(dir: &Path) -> CacheManager {
+        let mut config = Config::default();
+        config.cache.storage_path = dir.join("cache").display().to_string();
+        config.cache.hot_path = Some(dir.join("hot").display().to_string());
+        config.cache.cold_path = Some(dir.join("cold").display().to_string());
+        config.cache.hot_max_size_bytes = 25;
+        config.cache.eviction_policy = EvictionPolicy::Lru;
+        CacheManager::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_hot_overflow_demotes_to_cold() {
+        let dir = tempdir().unwrap();
+        let manager = tiered_manager(dir.path());
+        for name in ["a", "b", "c"] {
+            manager.save_to_cache(name, "1.0.0", "file.crate", &[0u8; 10]).unwrap();
+        }
+
+        // a 最早写入，最先被降级
+        let report = manager.enforce_size_limit().unwrap();
+        assert_eq!(report.demoted_files, 1);
+        assert_eq!(report.evicted_files, 0);
+
+        let relative = Path::new("a").join("1.0.0").join("file.crate");
+        assert!(!dir.path().join("hot").join(&relative).exists());
+        assert!(dir.path().join("cold").join(&relative).exists());
+        assert!(manager.is_cached("a", "1.0.0", "file.crate"));
+    }
+
+    #[test]
+    fn test_cold_hit_promotes_to_hot() {
+        let dir = tempdir().unwrap();
+        let manager = tiered_manager(dir.path());
+
+        let relative = Path::new("serde").join("1.0.0").join("serde-1.0.0.crate");
+        let cold_file = dir.path().join("cold").join(&relative);
+        fs::create_dir_all(cold_file.parent().unwrap()).unwrap();
+        fs::write(&cold_file, b"data").unwrap();
+
+        assert!(manager.is_cached("serde", "1.0.0", "serde-1.0.0.crate"));
+        let content = manager.get_cached_content("serde", "1.0.0", "serde-1.0.0.crate").unwrap();
+        assert_eq!(content, b"data");
+        assert!(dir.path().join("hot").join(&relative).exists());
+        assert!(!cold_file.exists());
+        assert!(!dir.path().join("cold").join("serde").exists());
+    }
+
+    #[test]
+    fn test_zstd_recompress_round_trip() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.recompress = Recompress::Zstd;
+        let manager = CacheManager::from_config(&config).unwrap();
+
+        let original = [&[0x1f, 0x8b][..], &[7u8; 4096][..]].concat();
+        manager.save_to_cache("foo", "1.0.0", "foo-1.0.0.crate", &original).unwrap();
+
+        let stored = fs::read(manager.get_cache_path("foo", "1.0.0", "foo-1.0.0.crate")).unwrap();
+        assert!(stored.starts_with(&ZSTD_MAGIC));
+        assert!(stored.len() < original.len());
+        assert_eq!(manager.get_cached_content("foo", "1.0.0", "foo-1.0.0.crate").unwrap(), original);
+
+        // 关闭再压缩后仍能读取已压缩的文件
+        let plain = CacheManager::new(dir.path(), 3600).unwrap();
+        assert_eq!(plain.get_cached_content("foo", "1.0.0", "foo-1.0.0.crate").unwrap(), original);
+    }
+
+    #[test]
+    fn test_crate_ttl_zero_never_expires() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.default_ttl = 60;
+        config.cache.crate_ttl = Some(0);
+        let manager = CacheManager::from_config(&config).unwrap();
+
+        manager.save_to_cache("serde", "1.0.0", "serde-1.0.0.crate", b"crate").unwrap();
+        manager.save_to_cache("serde", "1.0.0", "README.md", b"readme").unwrap();
+        let ten_years_ago = SystemTime::now() - std::time::Duration::from_secs(10 * 365 * 86400);
+        for filename in ["serde-1.0.0.crate", "README.md"] {
+            let path = manager.get_cache_path("serde", "1.0.0", filename);
+            fs::File::options().write(true).open(path).unwrap().set_modified(ten_years_ago).unwrap();
+        }
+
+        // 包文件不会仅因时间过期，其他文件仍按default_ttl过期
+        let crate_path = manager.get_cache_path("serde", "1.0.0", "serde-1.0.0.crate");
+        assert!(!manager.is_expired(&crate_path));
+        assert!(manager.is_expired(&manager.get_cache_path("serde", "1.0.0", "README.md")));
+
+        manager.clear_expired_cache().unwrap();
+        assert!(crate_path.exists());
+        assert!(!manager.is_cached("serde", "1.0.0", "README.md"));
+        assert_eq!(manager.get_cache_stats().unwrap().expired_files, 0);
+    }
+
+    #[test]
+    fn test_shard_depth_prefixes_crate_dirs() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.shard_depth = 2;
+        config.cache.default_ttl = 3600;
+        let manager = CacheManager::from_config(&config).unwrap();
+
+        let hash = sha256_hex(b"serde");
+        let expected = dir
+            .path()
+            .join(&hash[0..2])
+            .join(&hash[2..4])
+            .join("serde")
+            .join("1.0.0")
+            .join("serde-1.0.0.crate");
+        assert_eq!(manager.get_cache_path("serde", "1.0.0", "serde-1.0.0.crate"), expected);
+
+        manager.save_to_cache("serde", "1.0.0", "serde-1.0.0.crate", b"data").unwrap();
+        assert!(expected.exists());
+        assert!(manager.is_cached("serde", "1.0.0", "serde-1.0.0.crate"));
+        assert_eq!(manager.get_cached_content("serde", "1.0.0", "serde-1.0.0.crate").unwrap(), b"data");
+        assert_eq!(manager.cached_versions("serde"), vec!["1.0.0".to_string()]);
+
+        // 导出使用的相对路径不含分片前缀
+        let listed = manager.list_cache_files().unwrap();
+        assert_eq!(listed, vec![(Path::new("serde").join("1.0.0").join("serde-1.0.0.crate"), expected.clone())]);
+        assert_eq!(manager.get_cache_stats().unwrap().total_files, 1);
+
+        // 清理时连同空的分片目录一起删除
+        let old = SystemTime::now() - std::time::Duration::from_secs(7200);
+        fs::File::options().write(true).open(&expected).unwrap().set_modified(old).unwrap();
+        manager.clear_expired_cache().unwrap();
+        assert!(!dir.path().join(&hash[0..2]).exists());
+    }
+
+    #[test]
+    fn test_no_eviction_without_size_limit() {
+        let dir = tempdir().unwrap();
+        let manager = CacheManager::new(dir.path(), 3600).unwrap();
+        seed_access_pattern(&manager);
+
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 0);
+        assert_eq!(manager.emergency_evict().unwrap().evicted_files, 0);
+        assert_eq!(remaining(&manager), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_size_limit_uses_running_total() {
+        let dir = tempdir().unwrap();
+        let manager = eviction_manager(dir.path(), EvictionPolicy::Lru);
+        manager.save_to_cache("a", "1.0.0", "file.crate", &[0u8; 10]).unwrap();
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 0);
+
+        // 绕过缓存管理器写入的文件不计入总大小，不会触发遍历和淘汰
+        let untracked = dir.path().join("b").join("1.0.0").join("file.crate");
+        fs::create_dir_all(untracked.parent().unwrap()).unwrap();
+        fs::write(&untracked, [0u8; 20]).unwrap();
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 0);
+
+        // 重新统计后超出上限
+        manager.recount_sizes().unwrap();
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 1);
+
+        manager.save_to_cache("c", "1.0.0", "file.crate", &[0u8; 10]).unwrap();
+        manager.remove_cached_file("c", "1.0.0", "file.crate").unwrap();
+        assert_eq!(manager.enforce_size_limit().unwrap().evicted_files, 0);
+    }
+
+    #[test]
+    fn test_cache_error_from_io_error_kind() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(matches!(CacheError::from(Error::from(ErrorKind::NotFound)), CacheError::NotFound(_)));
+        assert!(matches!(
+            CacheError::from(Error::from(ErrorKind::PermissionDenied)),
+            CacheError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            CacheError::from(Error::from(ErrorKind::StorageFull)),
+            CacheError::StorageFull(_)
+        ));
+        assert!(matches!(
+            CacheError::from(Error::from_raw_os_error(ENOSPC)),
+            CacheError::StorageFull(_)
+        ));
+        assert!(matches!(
+            CacheError::from(Error::from(ErrorKind::InvalidData)),
+            CacheError::IoError(_)
+        ));
+    }
+
+    #[test]
+    fn test_missing_entry_is_not_found() {
+        let dir = tempdir().unwrap();
+        let manager = CacheManager::new(dir.path(), 3600).unwrap();
+
+        assert!(matches!(
+            manager.get_cached_content("serde", "1.0.0", "serde-1.0.0.crate"),
+            Err(CacheError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_clear_expired_respects_ttl_and_clock_skew() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.default_ttl = 3600;
+        config.cache.clock_skew_tolerance = 30;
+        let manager = CacheManager::from_config(&config).unwrap();
+
+        let db_file = dir.path().join(VERSIONS_DB_DIR).join("data");
+        fs::create_dir_all(db_file.parent().unwrap()).unwrap();
+        fs::write(&db_file, b"db").unwrap();
+
+        let now = SystemTime::now();
+        let secs = std::time::Duration::from_secs;
+        for (name, modified) in [
+            ("fresh", now - secs(60)),
+            ("old", now - secs(7200)),
+            ("slightly_ahead", now + secs(10)),
+            ("far_ahead", now + secs(86400)),
+        ] {
+            manager.save_to_cache(name, "1.0.0", "file.crate", b"data").unwrap();
+            let path = dir.path().join(name).join("1.0.0").join("file.crate");
+            fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+        }
+        fs::File::options().write(true).open(&db_file).unwrap().set_modified(now - secs(7200)).unwrap();
+
+        let stats = manager.get_cache_stats().unwrap();
+        assert_eq!((stats.total_files, stats.expired_files), (4, 2));
+
+        manager.clear_expired_cache().unwrap();
+        assert!(dir.path().join("fresh").exists());
+        assert!(dir.path().join("slightly_ahead").exists());
+        assert!(!dir.path().join("old").exists());
+        assert!(!dir.path().join("far_ahead").exists());
+        assert!(db_file.exists());
+    }
+
+    #[test]
+    fn test_find_in_cargo_registry_layout() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("registry").join("cache");
+        let index_dir = registry.join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(&index_dir).unwrap();
+        fs::write(index_dir.join("serde-1.0.0.crate"), b"data").unwrap();
+
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().join("cache").display().to_string();
+        config.cache.readonly_fallback_paths = vec![registry.display().to_string()];
+        let manager = CacheManager::from_config(&config).unwrap();
+
+        assert_eq!(
+            manager.find_in_fallback("serde", "1.0.0"),
+            Some(index_dir.join("serde-1.0.0.crate"))
+        );
+        assert_eq!(manager.find_in_fallback("serde", "2.0.0"), None);
+    }
+
+    #[test]
+    fn test_low_space_threshold() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.min_free_space_bytes = u64::MAX;
+
+        let manager = CacheManager::from_config(&config).unwrap();
+        assert!(manager.disk_usage().unwrap().low_space);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum ChecksumError {
+    #[error("校验和清单读取失败: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("校验和清单JSON解析失败: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("校验和清单格式错误，第 {0} 行: {1}")]
+    FormatError(usize, String),
+}
+
+/// 计算数据的sha256，返回小写十六进制字符串
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 本地校验和清单，`crate:version -> sha256`
+///
+/// 支持两种格式：
+/// - `.json`：`{"serde:1.0.0": "<sha256>", ...}`
+/// - 其他扩展名：每行 `crate:version,sha256`（也可用空白分隔），`#` 开头为注释
+#[derive(Debug, Default)]
+pub struct ChecksumManifest {
+    entries: HashMap<String, String>,
+}
+
+impl ChecksumManifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ChecksumError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+
+        let entries = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str::<HashMap<String, String>>(&content)?
+        } else {
+            Self::parse_lines(&content)?
+        };
+
+        let entries = entries
+            .into_iter()
+            .map(|(key, checksum)| (key, checksum.to_ascii_lowercase()))
+            .collect::<HashMap<_, _>>();
+
+        rat_logger::info!("已加载校验和清单 {:?}，共 {} 条", path, entries.len());
+        Ok(Self { entries })
+    }
+
+    fn parse_lines(content: &str) -> Result<HashMap<String, String>, ChecksumError> {
+        let mut entries = HashMap::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty());
+            match (fields.next(), fields.next()) {
+                (Some(key), Some(checksum)) if key.contains(':') => {
+                    entries.insert(key.to_string(), checksum.to_string());
+                }
+                _ => return Err(ChecksumError::FormatError(index + 1, line.to_string())),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// 查询指定版本的校验和
+    pub fn get(&self, crate_name: &str, version: &str) -> Option<&str> {
+        self.entries
+            .get(&format!("{}:{}", crate_name, version))
+            .map(|s| s.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_load_json_manifest_and_verify() {
+        let dir = tempdir().unwrap();
+        let data = b"crate contents";
+        let path = dir.path().join("checksums.json");
+        fs::write(
+            &path,
+            format!(r#"{{"foo:1.0.0": "{}"}}"#, sha256_hex(data).to_ascii_uppercase()),
+        )
+        .unwrap();
+
+        let manifest = ChecksumManifest::load(&path).unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.get("foo", "1.0.0"), Some(sha256_hex(data).as_str()));
+        assert_eq!(manifest.get("foo", "2.0.0"), None);
+    }
+
+    #[test]
+    fn test_load_csv_manifest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checksums.csv");
+        fs::write(&path, "# crate:version,sha256\nfoo:1.0.0,aaaa\nbar:0.1.0 bbbb\n").unwrap();
+
+        let manifest = ChecksumManifest::load(&path).unwrap();
+        assert_eq!(manifest.get("foo", "1.0.0"), Some("aaaa"));
+        assert_eq!(manifest.get("bar", "0.1.0"), Some("bbbb"));
+
+        fs::write(&path, "foo-1.0.0\n").unwrap();
+        assert!(matches!(
+            ChecksumManifest::load(&path),
+            Err(ChecksumError::FormatError(1, _))
+        ));
+    }
+}
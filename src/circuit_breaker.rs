@@ -0,0 +1,95 @@
+//! 上游熔断：连续失败达到阈值后在冷却时间内直接拒绝需要访问上游的请求，避免上游故障时请求堆积在超时上
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+pub struct CircuitBreaker {
+    /// 触发熔断的连续失败次数，0表示不启用
+    threshold: u32,
+    /// 熔断打开后的冷却时间
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    /// 熔断打开时为恢复放行的时间
+    open_until: Option<Instant>,
+    /// 冷却结束后的半开状态：成功前的第一次失败直接重新打开熔断
+    half_open: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// 熔断打开时返回剩余的冷却时间，否则返回None。冷却结束后放行请求，成功则恢复，失败则重新打开
+    pub fn remaining(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let open_until = state.open_until?;
+        let now = Instant::now();
+        if now < open_until {
+            return Some(open_until - now);
+        }
+        state.open_until = None;
+        state.half_open = true;
+        None
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.consecutive_failures = 0;
+        state.open_until = None;
+        state.half_open = false;
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.consecutive_failures += 1;
+        if state.half_open || state.consecutive_failures >= self.threshold {
+            rat_logger::warn!("上游连续失败 {} 次，熔断 {:?}", state.consecutive_failures, self.cooldown);
+            state.consecutive_failures = 0;
+            state.half_open = false;
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_after_cooldown_reopens_immediately() {
+        let breaker = CircuitBreaker::new(3, Duration::from_millis(50));
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        assert!(breaker.remaining().is_some());
+
+        // 冷却结束后放行，半开状态下一次失败就重新熔断
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.remaining().is_none());
+        breaker.record_failure();
+        assert!(breaker.remaining().is_some());
+
+        // 半开状态下成功则恢复，之后重新累计到阈值才熔断
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.remaining().is_none());
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.remaining().is_none());
+        breaker.record_failure();
+        assert!(breaker.remaining().is_some());
+    }
+}
@@ -0,0 +1,46 @@
+//! 时间工具：容忍系统时钟偏差和回拨的过期判断
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 转换为Unix时间戳（秒）。系统时钟早于Unix纪元时返回0，而不是把错误传给调用方
+pub fn unix_secs(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(e) => {
+            rat_logger::warn!("系统时钟早于Unix纪元 {:?}，按0处理", e.duration());
+            0
+        }
+    }
+}
+
+/// 判断记录是否过期，tolerance为允许的时钟偏差（秒）
+///
+/// 当前时间超过 `expires_at + tolerance` 时过期；记录的创建时间比当前时间晚超过
+/// tolerance 时说明系统时钟发生了回拨，记录的真实年龄无法判断，同样视为过期。
+pub fn is_expired(now: u64, created_at: u64, expires_at: u64, tolerance: u64) -> bool {
+    now > expires_at.saturating_add(tolerance) || now.saturating_add(tolerance) < created_at
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unix_secs_before_epoch() {
+        assert_eq!(unix_secs(UNIX_EPOCH - Duration::from_secs(10)), 0);
+        assert_eq!(unix_secs(UNIX_EPOCH + Duration::from_secs(10)), 10);
+    }
+
+    #[test]
+    fn test_is_expired_with_tolerance() {
+        // 创建于1000，1100过期，容差30秒
+        assert!(!is_expired(1050, 1000, 1100, 30));
+        assert!(!is_expired(1120, 1000, 1100, 30));
+        assert!(is_expired(1131, 1000, 1100, 30));
+
+        // 时钟小幅回拨在容差内，大幅回拨视为过期
+        assert!(!is_expired(980, 1000, 1100, 30));
+        assert!(is_expired(900, 1000, 1100, 30));
+    }
+}
@@ -1,9 +1,12 @@
-use serde::Deserialize;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum ConfigError {
     #[error("配置文件读取失败: {0}")]
     IoError(#[from] std::io::Error),
@@ -11,41 +14,676 @@ pub enum ConfigError {
     ParseError(#[from] toml::de::Error),
     #[error("绑定地址格式错误: {0}")]
     BindAddrError(String),
+    #[error("User-Agent配置错误: {0}")]
+    UserAgentError(String),
+    #[error("缓存配置错误: {0}")]
+    CacheError(String),
+    #[error("响应头配置错误: {0}")]
+    ResponseHeaderError(String),
+    #[error("固定校验和配置错误: {0}")]
+    PinnedChecksumError(String),
+    #[error("日志配置错误: {0}")]
+    LoggingError(String),
+    #[error("注册表配置错误: {0}")]
+    RegistryError(String),
+    #[error("分页配置错误: {0}")]
+    PageSizeError(String),
+    #[error("路径前缀配置错误: {0}")]
+    PathPrefixError(String),
+    #[error("监听套接字配置错误: {0}")]
+    SocketError(String),
 }
 
-#[derive(Debug, Deserialize)]
+/// 程序版本（`Cargo.toml` 中的版本号），命令行 `--version`、User-Agent、`/healthz` 和 `Server` 响应头统一使用
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 标识本程序的产品名和版本，用作默认User-Agent和 `Server` 响应头
+pub const PRODUCT: &str = concat!("crates-proxy/", env!("CARGO_PKG_VERSION"));
+
+/// 与HTTP协议本身相关、不允许通过 `server.response_headers` 覆盖的响应头
+const PROTECTED_RESPONSE_HEADERS: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "date",
+    "keep-alive",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub cache: CacheConfig,
-    pub upstream: Option<UpstreamConfig>,
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+    #[serde(default)]
     pub user_agent: UserAgentConfig,
     pub logging: LoggingConfig,
+    /// 按路径前缀提供的其他注册表，键为前缀（如 `internal` 对应 `/internal/api/v1/crates/...`）
+    #[serde(default)]
+    pub registries: BTreeMap<String, RegistryConfig>,
+}
+
+/// 诊断输出中替换敏感信息的占位符
+const REDACTED: &str = "***";
+
+/// 额外注册表在 `storage_path` 下的缓存目录名，每个注册表使用其中以前缀命名的子目录
+pub const REGISTRIES_DIR: &str = "registries";
+
+/// 不能用作注册表前缀的名称，这些路径由代理本身处理
+const RESERVED_REGISTRY_PREFIXES: &[&str] = &["admin", "api", "healthz", "index", "info", "metrics", "resolve"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// 注册表API根地址
+    pub api_url: String,
+    /// 稀疏索引根地址，未设置时与 api_url 相同
+    #[serde(default)]
+    pub index_url: Option<String>,
+    /// 访问该注册表使用的代理，未设置时使用 upstream.proxy_url
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    /// 访问该注册表使用的完整User-Agent，未设置时使用全局的 `[user_agent]`
+    #[serde(default)]
+    pub user_agent: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub bind_addr: String,
+    /// 在响应中附加 X-Upstream-Final-Url 等诊断头
+    #[serde(default)]
+    pub debug_headers: bool,
+    /// 监听队列长度
+    #[serde(default = "default_socket_backlog")]
+    pub socket_backlog: u32,
+    /// 监听套接字是否设置 SO_REUSEADDR
+    #[serde(default = "default_socket_reuseaddr")]
+    pub socket_reuseaddr: bool,
+    /// 是否对客户端连接设置 TCP_NODELAY
+    #[serde(default)]
+    pub socket_nodelay: bool,
+    /// 维护模式：所有包请求返回503
+    #[serde(default)]
+    pub maintenance: bool,
+    /// 管理接口（`/admin/...`）的访问令牌，未设置时管理接口不可用
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// 解析 latest 时是否把已撤销（yanked）的版本也算在内
+    #[serde(default)]
+    pub latest_includes_yanked: bool,
+    /// 是否遵循客户端请求中的 `Cache-Control: no-cache / no-store`
+    #[serde(default)]
+    pub honor_client_cache_control: bool,
+    /// 附加到所有响应上的自定义响应头
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, String>,
+    /// 固定的校验和（`crate:version -> sha256`），优先于上游和本地记录的校验和，不一致的文件拒绝返回
+    #[serde(default)]
+    pub pinned_checksums: BTreeMap<String, String>,
+    /// 每个客户端IP同时打开的连接数上限，超出时新连接直接关闭，0表示不限制
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+    /// 每个包文件响应的发送速率上限（字节/秒），0表示不限制
+    #[serde(default)]
+    pub max_response_bytes_per_sec: u64,
+    /// 列表接口（如 `/api/v1/crates/{name}/versions`）未指定 `per_page` 时每页的条数
+    #[serde(default = "default_page_size")]
+    pub default_page_size: usize,
+    /// 列表接口每页条数的上限，更大的 `per_page` 按此值处理
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: usize,
+    /// 部署在反向代理的子路径下时的路径前缀（如 `/mirror`），请求路径去掉该前缀后再处理，
+    /// 返回给客户端的地址（索引中的下载地址、分页链接）会带上该前缀。为空表示不使用前缀
+    #[serde(default)]
+    pub path_prefix: String,
+    /// 每个包排队等待上游下载（`upstream.max_concurrent_per_crate`）的请求数上限，
+    /// 超出时直接返回503和 `Retry-After`，不再排队。0表示不限制
+    #[serde(default)]
+    pub max_queue_depth: usize,
 }
 
-#[derive(Debug, Deserialize)]
+impl ServerConfig {
+    /// 去掉结尾 `/` 后的 `path_prefix`，未设置或为 `/` 时为空字符串
+    pub fn normalized_path_prefix(&self) -> &str {
+        self.path_prefix.trim_end_matches('/')
+    }
+
+    /// 检查 `path_prefix` 以 `/` 开头且只包含路径字符
+    fn validate_path_prefix(&self) -> Result<(), ConfigError> {
+        let prefix = &self.path_prefix;
+        if prefix.is_empty() {
+            return Ok(());
+        }
+        if !prefix.starts_with('/') {
+            return Err(ConfigError::PathPrefixError(format!("必须以 / 开头: {}", prefix)));
+        }
+        if prefix.contains("//") || prefix.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '?' | '#' | '%')) {
+            return Err(ConfigError::PathPrefixError(format!("包含无效字符: {}", prefix)));
+        }
+        Ok(())
+    }
+
+    /// 把 `response_headers` 解析为HeaderMap，拒绝无效的头以及协议相关的头
+    pub fn response_header_map(&self) -> Result<HeaderMap, ConfigError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.response_headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| ConfigError::ResponseHeaderError(format!("无效的响应头名称: {}", name)))?;
+            if PROTECTED_RESPONSE_HEADERS.contains(&header_name.as_str()) {
+                return Err(ConfigError::ResponseHeaderError(format!("不允许覆盖响应头: {}", name)));
+            }
+            let header_value = HeaderValue::from_str(value)
+                .map_err(|_| ConfigError::ResponseHeaderError(format!("响应头 {} 的值无效", name)))?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
+    }
+
+    /// 指定版本的固定校验和
+    pub fn pinned_checksum(&self, crate_name: &str, version: &str) -> Option<&str> {
+        self.pinned_checksums
+            .get(&format!("{}:{}", crate_name, version))
+            .map(String::as_str)
+    }
+
+    /// 检查 `pinned_checksums` 的键为 `crate:version`、值为64位十六进制sha256
+    fn validate_pinned_checksums(&self) -> Result<(), ConfigError> {
+        for (key, checksum) in &self.pinned_checksums {
+            let valid_key = key
+                .split_once(':')
+                .is_some_and(|(crate_name, version)| !crate_name.is_empty() && !version.is_empty());
+            if !valid_key {
+                return Err(ConfigError::PinnedChecksumError(format!("键应为 crate:version: {}", key)));
+            }
+            if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ConfigError::PinnedChecksumError(format!("{} 的sha256格式无效: {}", key, checksum)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
     pub storage_path: String,
     pub default_ttl: u64,
+    /// 缓存所在磁盘可用空间低于该值（字节）时发出警告
+    #[serde(default = "default_min_free_space_bytes")]
+    pub min_free_space_bytes: u64,
+    /// 本地校验和清单（`crate:version -> sha256`），在数据库和API都没有校验和时用于校验下载
+    #[serde(default)]
+    pub checksum_manifest_path: Option<String>,
+    /// 缓存文件总大小上限（字节），0表示不限制
+    #[serde(default)]
+    pub max_size_bytes: u64,
+    /// 超过大小上限时的淘汰策略
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+    /// 上游返回404的包名在该时间（秒）内直接返回404，0表示不缓存
+    #[serde(default = "default_negative_ttl")]
+    pub negative_ttl: u64,
+    /// 热层目录（如SSD），未设置时使用 `storage_path`
+    #[serde(default)]
+    pub hot_path: Option<String>,
+    /// 冷层目录（如HDD），设置后启用分层缓存
+    #[serde(default)]
+    pub cold_path: Option<String>,
+    /// 热层大小上限（字节），超出时降级到冷层，0表示不限制
+    #[serde(default)]
+    pub hot_max_size_bytes: u64,
+    /// 缓存文件落盘时的再压缩方式，读取时透明还原
+    #[serde(default)]
+    pub recompress: Recompress,
+    /// TTL过期判断允许的系统时钟偏差（秒）
+    #[serde(default = "default_clock_skew_tolerance")]
+    pub clock_skew_tolerance: u64,
+    /// 只读的cargo注册表缓存目录（如 `~/.cargo/registry/cache`），缓存未命中时先在其中查找
+    #[serde(default)]
+    pub readonly_fallback_paths: Vec<String>,
+    /// 自身缓存与只读缓存目录的查找顺序
+    #[serde(default)]
+    pub fallback_order: FallbackOrder,
+    /// 稀疏索引文件的缓存时间（秒），过期后向上游发送条件请求重新验证
+    #[serde(default = "default_index_ttl")]
+    pub index_ttl: u64,
+    /// 索引文件和包子接口响应以gzip压缩保存，返回时按客户端的 `Accept-Encoding` 决定是否解压
+    #[serde(default)]
+    pub gzip_index: bool,
+    /// 不读取缓存、每次都从上游获取的包名，支持 `*` 和 `?` 通配符
+    #[serde(default)]
+    pub no_cache_crates: Vec<String>,
+    /// 版本信息过期时间的随机抖动（TTL的百分比），避免同时写入的记录同时过期
+    #[serde(default = "default_ttl_jitter_pct")]
+    pub ttl_jitter_pct: u64,
+    /// 包目录前插入的哈希前缀目录层数（每层两位十六进制，如 `ab/cd/{crate}`），0表示不分片
+    #[serde(default)]
+    pub shard_depth: u8,
+    /// 遍历版本数据库时把无法解析的记录移入隔离区，未开启时只记录日志并跳过
+    #[serde(default)]
+    pub repair_corrupt_entries: bool,
+    /// 每个包在版本数据库中最多保存的版本数，只保留最新的N个，0表示不限制。缓存的包文件不受影响
+    #[serde(default)]
+    pub max_versions_per_crate: usize,
+    /// `.crate` 文件的TTL（秒），未设置时使用 `default_ttl`，0表示永不过期（只由大小上限淘汰）。版本信息仍按 `default_ttl` 过期
+    #[serde(default)]
+    pub crate_ttl: Option<u64>,
+    /// 常驻缓存的包列表文件，每行 `crate` 或 `crate@version`，后台任务定期确保它们已缓存且不过期
+    #[serde(default)]
+    pub warm_list_path: Option<String>,
+    /// 检查常驻缓存列表的间隔（秒），在下一次检查前就会过期的文件提前重新下载
+    #[serde(default = "default_warm_list_interval")]
+    pub warm_list_interval: u64,
+    /// 上游下载量（包信息的 `downloads`）低于该值的包只转发不缓存，0表示全部缓存
+    #[serde(default)]
+    pub min_downloads_to_cache: u64,
+    /// 只读模式：只提供已有的缓存，不创建目录也不写入，未命中时返回503
+    #[serde(default)]
+    pub read_only: bool,
+    /// 缓存包文件在 `{crate}/{version}/` 目录下的文件名模板，支持 `{crate}` 和 `{version}` 占位符
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    /// 重新缓存已有版本信息时的处理方式
+    #[serde(default)]
+    pub duplicate_version_policy: DuplicateVersionPolicy,
+    /// 缓存命中时按上游最新的元数据核对校验和，上游以相同版本号重新发布了不同内容时重新下载。
+    /// 只对非crates.io的上游生效（crates.io的版本不可变）
+    #[serde(default)]
+    pub verify_on_hit: bool,
+    /// 缓存命中时重新计算包文件的sha256并与已知校验和比对，不一致（磁盘损坏）时删除并重新下载
+    #[serde(default)]
+    pub verify_on_read: bool,
+    /// 版本数据库（MelangeDB）的内存缓存容量（字节）
+    #[serde(default = "default_db_cache_capacity_bytes")]
+    pub db_cache_capacity_bytes: usize,
+    /// 启动时检查缓存文件与版本数据库是否一致
+    #[serde(default)]
+    pub check_on_start: ConsistencyCheck,
+    /// 启动检查的时间上限（秒），超出时停止检查，0表示不限制
+    #[serde(default = "default_check_on_start_timeout_secs")]
+    pub check_on_start_timeout_secs: u64,
+}
+
+impl CacheConfig {
+    /// 包名是否匹配 `no_cache_crates` 中的某一项
+    pub fn bypasses_cache(&self, crate_name: &str) -> bool {
+        self.no_cache_crates.iter().any(|pattern| glob_match(pattern, crate_name))
+    }
+}
+
+/// 简单的通配符匹配：`*` 匹配任意长度字符，`?` 匹配单个字符
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 `*` 的位置及其当时对应的名称位置，用于回溯
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 缓存文件再压缩方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recompress {
+    /// 按原样保存
+    #[default]
+    None,
+    /// 用zstd包装原始文件
+    Zstd,
+}
+
+/// 启动时对缓存文件和版本数据库做一致性检查的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsistencyCheck {
+    /// 不检查
+    #[default]
+    Off,
+    /// 只记录孤立的文件和记录
+    Log,
+    /// 记录并删除孤立的文件和记录
+    Prune,
+}
+
+/// 自身缓存与 `readonly_fallback_paths` 的优先顺序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackOrder {
+    /// 自身缓存中没有该文件时才查找只读缓存目录
+    #[default]
+    OwnFirst,
+    /// 先查找只读缓存目录（如预热好的共享缓存），没有时再使用自身缓存
+    FallbackFirst,
+}
+
+/// 重新缓存已有版本信息（相同的 `{crate}:{version}`）时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateVersionPolicy {
+    /// 整条覆盖，`created_at` 和 `expires_at` 都重新计算
+    #[default]
+    Overwrite,
+    /// 写入新内容，但保留原有的 `created_at`，只刷新 `expires_at`
+    PreserveCreated,
+    /// 内容（校验和、撤销状态、下载路径）没有变化时不写入，原记录按原来的时间过期；有变化时同 `preserve_created`
+    SkipUnchanged,
+}
+
+/// 缓存淘汰策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// 按写入时间淘汰最旧（最先过期）的文件
+    #[default]
+    Ttl,
+    /// 淘汰最久未访问的文件
+    Lru,
+    /// 淘汰访问次数最少的文件
+    Lfu,
+}
+
+/// 下载的包没有可用的校验和时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingChecksumPolicy {
+    /// 直接下载并缓存
+    Allow,
+    /// 记录警告后下载并缓存
+    #[default]
+    Warn,
+    /// 拒绝下载，返回502
+    Reject,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpstreamConfig {
     pub proxy_url: Option<String>,
+    /// crates.io API 根地址
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// 缓存前解压检查crate包内是否有 `{name}-{version}/Cargo.toml`
+    #[serde(default)]
+    pub validate_crate_structure: bool,
+    /// 稀疏索引根地址
+    #[serde(default = "default_index_url")]
+    pub index_url: String,
+    /// 包文件下载地址，格式同索引 config.json 中的 `dl`；未设置时从 api_url 下载
+    #[serde(default)]
+    pub download_url: Option<String>,
+    /// 解析 latest 失败（如上游限流）时，返回本地缓存中最新的版本
+    #[serde(default)]
+    pub stale_latest_on_failure: bool,
+    /// 上游支持Range时分成多少块并行下载，0或1表示不分块
+    #[serde(default)]
+    pub parallel_download_chunks: u32,
+    /// 传输速度低于该值（字节/秒）持续 `low_speed_time` 秒时中止，0表示不启用
+    #[serde(default)]
+    pub low_speed_limit: u32,
+    /// 低速持续时间（秒）
+    #[serde(default = "default_low_speed_time")]
+    pub low_speed_time: u64,
+    /// 启动时向各上游根地址发送HEAD请求并记录是否可达
+    #[serde(default)]
+    pub probe_on_start: bool,
+    /// 上游返回200但内容是HTML错误页（常见于CDN故障）或小于 `min_crate_size` 时的重试次数，0表示不重试
+    #[serde(default)]
+    pub html_error_retries: u32,
+    /// 下载内容与期望的校验和不一致时重新下载的次数（传输中损坏通常重新下载即可恢复），0表示不重试
+    #[serde(default)]
+    pub checksum_mismatch_retries: u32,
+    /// 包文件的最小合理大小（字节），上游返回200但响应体更小（如CDN故障时的空响应）时不缓存，
+    /// 按 `html_error_retries` 重试，仍然过小时返回502
+    #[serde(default = "default_min_crate_size")]
+    pub min_crate_size: u64,
+    /// 单连接下载时边接收边解压检查gzip数据流，解码出错立即中止下载，不缓存也不用等到下载完成；
+    /// 分块并行下载的分块乱序到达，不做流式检查
+    #[serde(default)]
+    pub validate_gzip_stream: bool,
+    /// 每个包同时进行的上游下载数上限，超出的请求排队，0表示不限制
+    #[serde(default)]
+    pub max_concurrent_per_crate: u32,
+    /// 本地crates.io索引快照目录（或git检出），解析版本和校验和时优先于API
+    #[serde(default)]
+    pub index_snapshot_path: Option<String>,
+    /// 上游、版本数据库和校验和清单都没有校验和时的处理方式
+    #[serde(default)]
+    pub on_missing_checksum: MissingChecksumPolicy,
+    /// 元数据API请求（版本列表、版本详情）的超时（秒），0表示不限制
+    #[serde(default = "default_upstream_timeout_secs")]
+    pub api_timeout_secs: u64,
+    /// 包文件下载的超时（秒），0表示不限制
+    #[serde(default = "default_upstream_timeout_secs")]
+    pub download_timeout_secs: u64,
+    /// 稀疏索引请求的超时（秒），0表示不限制
+    #[serde(default = "default_upstream_timeout_secs")]
+    pub index_timeout_secs: u64,
+    /// 上游连续失败多少次后熔断，0表示不启用
+    #[serde(default)]
+    pub circuit_breaker_threshold: u32,
+    /// 熔断的冷却时间（秒），期间需要访问上游的请求直接返回503
+    #[serde(default = "default_circuit_breaker_cooldown_secs")]
+    pub circuit_breaker_cooldown_secs: u64,
+    /// 同一版本连续下载失败多少次后进入冷却，0表示不启用
+    #[serde(default)]
+    pub download_failure_threshold: u32,
+    /// 下载失败冷却时间（秒），期间该版本的请求直接返回上一次的错误
+    #[serde(default = "default_download_failure_cooldown_secs")]
+    pub download_failure_cooldown_secs: u64,
+    /// 允许转发到上游的包子接口（`/api/v1/crates/{name}/{endpoint}`），响应按 `cache.index_ttl` 缓存
+    #[serde(default = "default_passthrough_endpoints")]
+    pub passthrough_endpoints: Vec<String>,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            proxy_url: None,
+            api_url: default_api_url(),
+            validate_crate_structure: false,
+            index_url: default_index_url(),
+            download_url: None,
+            stale_latest_on_failure: false,
+            parallel_download_chunks: 0,
+            low_speed_limit: 0,
+            low_speed_time: default_low_speed_time(),
+            probe_on_start: false,
+            html_error_retries: 0,
+            checksum_mismatch_retries: 0,
+            min_crate_size: default_min_crate_size(),
+            validate_gzip_stream: false,
+            max_concurrent_per_crate: 0,
+            index_snapshot_path: None,
+            on_missing_checksum: MissingChecksumPolicy::default(),
+            api_timeout_secs: default_upstream_timeout_secs(),
+            download_timeout_secs: default_upstream_timeout_secs(),
+            index_timeout_secs: default_upstream_timeout_secs(),
+            circuit_breaker_threshold: 0,
+            circuit_breaker_cooldown_secs: default_circuit_breaker_cooldown_secs(),
+            download_failure_threshold: 0,
+            download_failure_cooldown_secs: default_download_failure_cooldown_secs(),
+            passthrough_endpoints: default_passthrough_endpoints(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// 访问上游时使用的User-Agent
+///
+/// crates.io 要求自动化客户端使用能标识自身并附带联系方式的User-Agent，
+/// 未显式设置 `value` 时使用 `crates-proxy/<版本> (+<联系方式>)`。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserAgentConfig {
-    pub value: String,
+    /// 完整的User-Agent，设置后原样使用
+    #[serde(default)]
+    pub value: Option<String>,
+    /// 联系方式（邮箱或网址），附加在默认User-Agent中
+    #[serde(default)]
+    pub contact: Option<String>,
+}
+
+impl UserAgentConfig {
+    /// 生成实际发送的User-Agent
+    pub fn compose(&self) -> String {
+        if let Some(value) = &self.value {
+            return value.trim().to_string();
+        }
+
+        match self.contact.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+            Some(contact) => format!("{} (+{})", PRODUCT, contact),
+            None => PRODUCT.to_string(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     pub level: String,
+    /// 下载审计日志（JSON lines）路径，未设置时不记录
+    #[serde(default)]
+    pub audit_path: Option<String>,
+    /// 每条审计记录写入后是否fsync
+    #[serde(default)]
+    pub audit_fsync: bool,
+    /// 单个日志文件的大小上限（字节），超过后轮转
+    #[serde(default = "default_log_max_file_size")]
+    pub max_file_size: u64,
+    /// 保留的压缩日志文件数
+    #[serde(default = "default_log_max_compressed_files")]
+    pub max_compressed_files: usize,
+    /// 轮转日志的压缩级别（0~9）
+    #[serde(default = "default_log_compression_level")]
+    pub compression_level: u8,
+}
+
+/// 单个日志文件大小的上限（1GB）
+pub const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// 保留的压缩日志文件数上限
+pub const MAX_LOG_COMPRESSED_FILES: usize = 1000;
+
+fn default_log_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_log_max_compressed_files() -> usize {
+    5
+}
+
+fn default_log_compression_level() -> u8 {
+    6
+}
+
+fn default_socket_backlog() -> u32 {
+    1024
+}
+
+fn default_page_size() -> usize {
+    100
+}
+
+fn default_max_page_size() -> usize {
+    1000
+}
+
+fn default_socket_reuseaddr() -> bool {
+    true
+}
+
+fn default_api_url() -> String {
+    "https://crates.io".to_string()
+}
+
+fn default_index_url() -> String {
+    "https://index.crates.io".to_string()
+}
+
+fn default_low_speed_time() -> u64 {
+    30
+}
+
+fn default_min_crate_size() -> u64 {
+    1
+}
+
+fn default_upstream_timeout_secs() -> u64 {
+    30
+}
+
+fn default_circuit_breaker_cooldown_secs() -> u64 {
+    30
+}
+
+fn default_download_failure_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_passthrough_endpoints() -> Vec<String> {
+    ["owners", "reverse_dependencies", "downloads"].map(String::from).to_vec()
+}
+
+fn default_warm_list_interval() -> u64 {
+    600
+}
+
+/// 默认的缓存文件名，与cargo下载的文件名一致
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{crate}-{version}.crate";
+
+fn default_filename_template() -> String {
+    DEFAULT_FILENAME_TEMPLATE.to_string()
+}
+
+fn default_check_on_start_timeout_secs() -> u64 {
+    60
+}
+
+fn default_index_ttl() -> u64 {
+    60
+}
+
+/// `cache.shard_depth` 的上限，每层256个目录，4层已足够分散
+pub const MAX_SHARD_DEPTH: u8 = 4;
+
+/// `cache.db_cache_capacity_bytes` 的下限，更小的缓存会让版本查询几乎都落到磁盘
+pub const MIN_DB_CACHE_CAPACITY_BYTES: usize = 1024 * 1024;
+
+fn default_db_cache_capacity_bytes() -> usize {
+    100 * 1024 * 1024
+}
+
+fn default_ttl_jitter_pct() -> u64 {
+    10
+}
+
+fn default_negative_ttl() -> u64 {
+    60
+}
+
+fn default_clock_skew_tolerance() -> u64 {
+    30
+}
+
+fn default_min_free_space_bytes() -> u64 {
+    1024 * 1024 * 1024 // 1GB
 }
 
 impl Config {
@@ -55,6 +693,54 @@ impl Config {
         Ok(config)
     }
 
+    /// 前缀对应注册表的完整配置：上游地址替换为该注册表的地址，缓存放在 `storage_path/registries/{前缀}` 下，
+    /// 只适用于主注册表（crates.io）的本地数据（只读缓存目录、索引快照、校验和清单等）不再使用
+    pub fn registry_config(&self, prefix: &str) -> Option<Config> {
+        let registry = self.registries.get(prefix)?;
+        let namespace = |path: &str| Path::new(path).join(REGISTRIES_DIR).join(prefix).display().to_string();
+
+        let mut config = self.clone();
+        config.registries.clear();
+        config.upstream.api_url = registry.api_url.clone();
+        config.upstream.index_url = registry.index_url.clone().unwrap_or_else(|| registry.api_url.clone());
+        config.upstream.download_url = None;
+        if registry.proxy_url.is_some() {
+            config.upstream.proxy_url = registry.proxy_url.clone();
+        }
+        if registry.user_agent.is_some() {
+            config.user_agent.value = registry.user_agent.clone();
+        }
+        config.upstream.index_snapshot_path = None;
+        config.cache.storage_path = namespace(&self.cache.storage_path);
+        config.cache.hot_path = self.cache.hot_path.as_deref().map(namespace);
+        config.cache.cold_path = self.cache.cold_path.as_deref().map(namespace);
+        config.cache.readonly_fallback_paths.clear();
+        config.cache.checksum_manifest_path = None;
+        config.server.pinned_checksums.clear();
+        // 下载审计由主服务统一记录
+        config.logging.audit_path = None;
+        Some(config)
+    }
+
+    /// 用于诊断输出的完整配置，管理令牌和各地址中的账号密码已脱敏
+    pub fn redacted_json(&self) -> serde_json::Value {
+        let mut config = self.clone();
+        if config.server.admin_token.is_some() {
+            config.server.admin_token = Some(REDACTED.to_string());
+        }
+        let upstream = &mut config.upstream;
+        upstream.proxy_url = upstream.proxy_url.as_deref().map(redact_url_credentials);
+        upstream.api_url = redact_url_credentials(&upstream.api_url);
+        upstream.index_url = redact_url_credentials(&upstream.index_url);
+        upstream.download_url = upstream.download_url.as_deref().map(redact_url_credentials);
+        for registry in config.registries.values_mut() {
+            registry.api_url = redact_url_credentials(&registry.api_url);
+            registry.index_url = registry.index_url.as_deref().map(redact_url_credentials);
+            registry.proxy_url = registry.proxy_url.as_deref().map(redact_url_credentials);
+        }
+        serde_json::to_value(&config).unwrap_or_default()
+    }
+
     pub fn validate(&self) -> Result<(), ConfigError> {
         // 验证绑定地址格式
         if !self.server.bind_addr.contains(':') {
@@ -63,8 +749,104 @@ impl Config {
             ));
         }
 
-        // 验证缓存目录
-        fs::create_dir_all(&self.cache.storage_path)?;
+        if self.server.socket_backlog == 0 {
+            return Err(ConfigError::SocketError(
+                "监听队列长度必须大于0".to_string(),
+            ));
+        }
+
+        self.server.response_header_map()?;
+        self.server.validate_pinned_checksums()?;
+        self.server.validate_path_prefix()?;
+
+        if self.server.default_page_size == 0 || self.server.default_page_size > self.server.max_page_size {
+            return Err(ConfigError::PageSizeError(format!(
+                "default_page_size ({}) 必须大于0且不超过 max_page_size ({})",
+                self.server.default_page_size, self.server.max_page_size
+            )));
+        }
+
+        // 验证User-Agent
+        if self.user_agent.compose().is_empty() {
+            return Err(ConfigError::UserAgentError(
+                "User-Agent不能为空".to_string(),
+            ));
+        }
+
+        if self.logging.max_file_size == 0 || self.logging.max_file_size > MAX_LOG_FILE_SIZE {
+            return Err(ConfigError::LoggingError(
+                format!("max_file_size 必须在1到{}字节之间", MAX_LOG_FILE_SIZE),
+            ));
+        }
+        if self.logging.max_compressed_files > MAX_LOG_COMPRESSED_FILES {
+            return Err(ConfigError::LoggingError(
+                format!("max_compressed_files 不能超过{}", MAX_LOG_COMPRESSED_FILES),
+            ));
+        }
+        if self.logging.compression_level > 9 {
+            return Err(ConfigError::LoggingError(
+                "compression_level 必须在0到9之间".to_string(),
+            ));
+        }
+
+        // 验证缓存目录，只读模式下不创建，目录必须已存在
+        if !self.cache.read_only {
+            fs::create_dir_all(&self.cache.storage_path)?;
+        } else if !Path::new(&self.cache.storage_path).is_dir() {
+            return Err(ConfigError::CacheError(format!(
+                "只读模式下缓存目录必须已存在: {}",
+                self.cache.storage_path
+            )));
+        }
+
+        if self.cache.ttl_jitter_pct > 100 {
+            return Err(ConfigError::CacheError(
+                "ttl_jitter_pct 不能超过100".to_string(),
+            ));
+        }
+
+        if self.cache.shard_depth > MAX_SHARD_DEPTH {
+            return Err(ConfigError::CacheError(
+                format!("shard_depth 不能超过{}", MAX_SHARD_DEPTH),
+            ));
+        }
+
+        if self.cache.db_cache_capacity_bytes < MIN_DB_CACHE_CAPACITY_BYTES {
+            return Err(ConfigError::CacheError(
+                format!("db_cache_capacity_bytes 不能小于{}", MIN_DB_CACHE_CAPACITY_BYTES),
+            ));
+        }
+
+        let template = &self.cache.filename_template;
+        if template.is_empty() || template.contains(['/', '\\']) || template == "." || template == ".." {
+            return Err(ConfigError::CacheError(
+                format!("filename_template 必须是不含路径分隔符的文件名: {:?}", template),
+            ));
+        }
+
+        if let Some(cold_path) = &self.cache.cold_path {
+            let hot_path = self.cache.hot_path.as_ref().unwrap_or(&self.cache.storage_path);
+            if Path::new(cold_path) == Path::new(hot_path) {
+                return Err(ConfigError::CacheError(
+                    "冷层目录不能与热层目录相同".to_string(),
+                ));
+            }
+        }
+
+        for (prefix, registry) in &self.registries {
+            let valid_prefix = !prefix.is_empty()
+                && prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                && !RESERVED_REGISTRY_PREFIXES.contains(&prefix.as_str());
+            if !valid_prefix {
+                return Err(ConfigError::RegistryError(format!("无效的注册表前缀: {}", prefix)));
+            }
+            if registry.api_url.is_empty() {
+                return Err(ConfigError::RegistryError(format!("注册表 {} 缺少 api_url", prefix)));
+            }
+            if registry.user_agent.as_deref().is_some_and(|user_agent| user_agent.trim().is_empty()) {
+                return Err(ConfigError::RegistryError(format!("注册表 {} 的 user_agent 不能为空", prefix)));
+            }
+        }
 
         Ok(())
     }
@@ -75,18 +857,201 @@ impl Default for Config {
         Self {
             server: ServerConfig {
                 bind_addr: "127.0.0.1:8080".to_string(),
+                debug_headers: false,
+                socket_backlog: default_socket_backlog(),
+                socket_reuseaddr: default_socket_reuseaddr(),
+                socket_nodelay: false,
+                maintenance: false,
+                admin_token: None,
+                latest_includes_yanked: false,
+                honor_client_cache_control: false,
+                response_headers: BTreeMap::new(),
+                pinned_checksums: BTreeMap::new(),
+                max_connections_per_ip: 0,
+                max_response_bytes_per_sec: 0,
+                default_page_size: default_page_size(),
+                max_page_size: default_max_page_size(),
+                path_prefix: String::new(),
+                max_queue_depth: 0,
             },
             cache: CacheConfig {
                 storage_path: "./cache".to_string(),
                 default_ttl: 3600,
+                min_free_space_bytes: default_min_free_space_bytes(),
+                checksum_manifest_path: None,
+                max_size_bytes: 0,
+                eviction_policy: EvictionPolicy::default(),
+                negative_ttl: default_negative_ttl(),
+                hot_path: None,
+                cold_path: None,
+                hot_max_size_bytes: 0,
+                recompress: Recompress::default(),
+                clock_skew_tolerance: default_clock_skew_tolerance(),
+                readonly_fallback_paths: Vec::new(),
+                fallback_order: FallbackOrder::default(),
+                index_ttl: default_index_ttl(),
+                gzip_index: false,
+                no_cache_crates: Vec::new(),
+                ttl_jitter_pct: default_ttl_jitter_pct(),
+                shard_depth: 0,
+                repair_corrupt_entries: false,
+                max_versions_per_crate: 0,
+                crate_ttl: None,
+                warm_list_path: None,
+                warm_list_interval: default_warm_list_interval(),
+                min_downloads_to_cache: 0,
+                read_only: false,
+                filename_template: default_filename_template(),
+                duplicate_version_policy: DuplicateVersionPolicy::default(),
+                verify_on_hit: false,
+                verify_on_read: false,
+                db_cache_capacity_bytes: default_db_cache_capacity_bytes(),
+                check_on_start: ConsistencyCheck::default(),
+                check_on_start_timeout_secs: default_check_on_start_timeout_secs(),
             },
-            upstream: None,
-            user_agent: UserAgentConfig {
-                value: "Mozilla/5.0 ( compatible crates-proxy/0.1.0 )".to_string(),
-            },
+            upstream: UpstreamConfig::default(),
+            user_agent: UserAgentConfig::default(),
             logging: LoggingConfig {
                 level: "info".to_string(),
+                audit_path: None,
+                audit_fsync: false,
+                max_file_size: default_log_max_file_size(),
+                max_compressed_files: default_log_max_compressed_files(),
+                compression_level: default_log_compression_level(),
             },
+            registries: BTreeMap::new(),
         }
     }
-}
\ No newline at end of file
+}
+/// 把URL中的用户名和密码替换为占位符，不含账号密码的地址原样返回，无法解析的地址整体替换
+pub fn redact_url_credentials(raw: &str) -> String {
+    let Ok(mut url) = url::Url::parse(raw) else {
+        return REDACTED.to_string();
+    };
+    if url.username().is_empty() && url.password().is_none() {
+        return raw.to_string();
+    }
+    let _ = url.set_username(REDACTED);
+    let _ = url.set_password(Some(REDACTED));
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_user_agent_includes_version_and_contact() {
+        let config: Config = toml::from_str(
+            r#"
+[server]
+bind_addr = "127.0.0.1:8080"
+
+[cache]
+storage_path = "./cache"
+default_ttl = 3600
+
+[user_agent]
+contact = "ops@example.com"
+
+[logging]
+level = "info"
+"#,
+        )
+        .unwrap();
+
+        let user_agent = config.user_agent.compose();
+        assert_eq!(
+            user_agent,
+            format!("crates-proxy/{} (+ops@example.com)", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(!user_agent.contains("  "));
+    }
+
+    #[test]
+    fn test_no_cache_crates_globs() {
+        let mut config = Config::default();
+        config.cache.no_cache_crates = vec!["internal-*".to_string(), "my-crate".to_string(), "tool-?".to_string()];
+
+        assert!(config.cache.bypasses_cache("internal-core"));
+        assert!(config.cache.bypasses_cache("internal-"));
+        assert!(config.cache.bypasses_cache("my-crate"));
+        assert!(config.cache.bypasses_cache("tool-a"));
+
+        assert!(!config.cache.bypasses_cache("my-crate2"));
+        assert!(!config.cache.bypasses_cache("tool-ab"));
+        assert!(!config.cache.bypasses_cache("serde"));
+    }
+
+    #[test]
+    fn test_pinned_checksums_validation() {
+        let mut config = Config::default();
+        config.cache.storage_path = std::env::temp_dir().display().to_string();
+        config.server.pinned_checksums.insert("serde:1.0.0".to_string(), "ab".repeat(32));
+        assert!(config.validate().is_ok());
+        assert_eq!(config.server.pinned_checksum("serde", "1.0.0"), Some("ab".repeat(32).as_str()));
+        assert_eq!(config.server.pinned_checksum("serde", "1.0.1"), None);
+
+        config.server.pinned_checksums.insert("serde".to_string(), "ab".repeat(32));
+        assert!(matches!(config.validate(), Err(ConfigError::PinnedChecksumError(_))));
+
+        config.server.pinned_checksums.remove("serde");
+        config.server.pinned_checksums.insert("tokio:1.0.0".to_string(), "not-a-sha256".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::PinnedChecksumError(_))));
+    }
+
+    #[test]
+    fn test_logging_rotation_validation() {
+        let mut config = Config::default();
+        config.cache.storage_path = std::env::temp_dir().display().to_string();
+        assert!(config.validate().is_ok());
+
+        config.logging.max_file_size = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::LoggingError(_))));
+        config.logging.max_file_size = MAX_LOG_FILE_SIZE;
+        config.logging.compression_level = 10;
+        assert!(matches!(config.validate(), Err(ConfigError::LoggingError(_))));
+        config.logging.compression_level = 9;
+        config.logging.max_compressed_files = MAX_LOG_COMPRESSED_FILES + 1;
+        assert!(matches!(config.validate(), Err(ConfigError::LoggingError(_))));
+    }
+
+    #[test]
+    fn test_zero_socket_backlog_is_rejected() {
+        let mut config = Config::default();
+        config.server.socket_backlog = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::SocketError(_))));
+    }
+
+    #[test]
+    fn test_response_headers_validation() {
+        let mut config = Config::default();
+        config.server.response_headers.insert("X-Content-Type-Options".to_string(), "nosniff".to_string());
+        let headers = config.server.response_header_map().unwrap();
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+
+        config.server.response_headers.insert("Content-Length".to_string(), "0".to_string());
+        assert!(matches!(config.server.response_header_map(), Err(ConfigError::ResponseHeaderError(_))));
+
+        config.server.response_headers.clear();
+        config.server.response_headers.insert("bad header".to_string(), "x".to_string());
+        assert!(matches!(config.server.response_header_map(), Err(ConfigError::ResponseHeaderError(_))));
+    }
+
+    #[test]
+    fn test_user_agent_validation() {
+        let mut config = Config::default();
+        config.cache.storage_path = std::env::temp_dir().display().to_string();
+        assert_eq!(
+            config.user_agent.compose(),
+            format!("crates-proxy/{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(config.validate().is_ok());
+
+        config.user_agent.value = Some("custom-client/1.0".to_string());
+        assert_eq!(config.user_agent.compose(), "custom-client/1.0");
+
+        config.user_agent.value = Some("   ".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::UserAgentError(_))));
+    }
+}
@@ -0,0 +1,76 @@
+//! 按客户端IP限制同时打开的连接数，避免单个客户端占满连接，超出上限的新连接直接关闭
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+pub struct ConnectionLimiter {
+    /// 每个IP同时打开的连接数上限
+    max_per_ip: usize,
+    /// 有连接的IP及其连接数，连接数归零时删除
+    connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// 一个已接受的连接，释放时减少该IP的连接数
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize) -> Self {
+        Self {
+            max_per_ip,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 为来自该IP的新连接登记，已达上限时返回None
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionPermit> {
+        let mut connections = self.connections.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = connections.entry(ip).or_insert(0);
+        if *count >= self.max_per_ip {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionPermit {
+            limiter: Arc::clone(self),
+            ip,
+        })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut connections = self.limiter.connections.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(count) = connections.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_is_per_ip_and_released_on_drop() {
+        let limiter = Arc::new(ConnectionLimiter::new(2));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.try_acquire(a).unwrap();
+        let _second = limiter.try_acquire(a).unwrap();
+        assert!(limiter.try_acquire(a).is_none());
+        let other = limiter.try_acquire(b).unwrap();
+        assert_eq!(limiter.connections.lock().unwrap().len(), 2);
+
+        drop(first);
+        assert!(limiter.try_acquire(a).is_some());
+        drop(other);
+        assert_eq!(limiter.connections.lock().unwrap().len(), 1);
+    }
+}
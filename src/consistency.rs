@@ -0,0 +1,186 @@
+//! 启动时的缓存一致性检查
+//!
+//! 上游版本记录只是带TTL的元数据缓存，过期后会被定期清理，而包文件保留得更久，
+//! 因此"有文件没有记录"和"有记录没有文件"都是正常状态（前者可用 `--rebuild-index` 补回记录），不据此删除任何东西。
+//! 检查只针对确实不一致的情况：内容与 `server.pinned_checksums` 不符的缓存文件、
+//! 没有本地发布记录的本地发布文件，以及找不到包文件的本地发布记录（本地发布的记录不会过期）。
+//! 按配置只记录日志或同时删除它们。检查有时间上限，超出时停止并报告已检查的部分。
+
+use crate::cache::{CacheError, CacheManager};
+use crate::checksum::sha256_hex;
+use crate::version_manager::{VersionManager, VersionManagerError};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConsistencyError {
+    #[error("缓存错误: {0}")]
+    CacheError(#[from] CacheError),
+    #[error("版本管理错误: {0}")]
+    VersionManagerError(#[from] VersionManagerError),
+}
+
+#[derive(Debug, Default)]
+pub struct ConsistencyReport {
+    /// 检查过的包数
+    pub crates: usize,
+    /// 与固定校验和不一致的缓存文件和没有本地发布记录的本地发布文件（`crate@version`）
+    pub inconsistent_files: Vec<String>,
+    /// 找不到包文件的本地发布记录（`crate@version`）
+    pub orphan_records: Vec<String>,
+    /// 是否已删除上面的文件和记录
+    pub pruned: bool,
+    /// 达到时间上限、没有检查完
+    pub timed_out: bool,
+}
+
+/// 检查缓存文件与版本数据库、固定校验和是否一致，`prune` 为true时删除不一致的文件和孤立的本地发布记录，
+/// `time_limit` 为None时不限制时间
+pub fn check_consistency(
+    cache_manager: &CacheManager,
+    version_manager: &VersionManager,
+    pinned_checksums: &BTreeMap<String, String>,
+    prune: bool,
+    time_limit: Option<Duration>,
+) -> Result<ConsistencyReport, ConsistencyError> {
+    let started = Instant::now();
+    let out_of_time = || time_limit.is_some_and(|limit| started.elapsed() >= limit);
+    let mut report = ConsistencyReport {
+        pruned: prune,
+        ..ConsistencyReport::default()
+    };
+
+    for crate_name in cache_manager.crate_names()? {
+        if out_of_time() {
+            report.timed_out = true;
+            break;
+        }
+        report.crates += 1;
+
+        // 只有配置了固定校验和的版本才计算哈希，其余文件没有可比对的可信校验和
+        for version in cache_manager.cached_versions(&crate_name) {
+            let Some(expected) = pinned_checksums.get(&format!("{}:{}", crate_name, version)) else { continue };
+            let filename = cache_manager.crate_filename(&crate_name, &version);
+            let actual = sha256_hex(&cache_manager.get_cached_content(&crate_name, &version, &filename)?);
+            if actual.eq_ignore_ascii_case(expected) {
+                continue;
+            }
+            rat_logger::warn!("缓存文件与固定校验和不一致: {}@{}（期望 {}，实际 {}）", crate_name, version, expected, actual);
+            if prune {
+                cache_manager.remove_cached_file(&crate_name, &version, &filename)?;
+            }
+            report.inconsistent_files.push(format!("{}@{}", crate_name, version));
+        }
+
+        let local_versions = cache_manager.local_crate_versions(&crate_name);
+        if local_versions.is_empty() {
+            continue;
+        }
+        let records = version_manager.get_local_versions(&crate_name)?;
+        for version in local_versions {
+            if records.iter().any(|info| info.version == version) {
+                continue;
+            }
+            rat_logger::warn!("本地发布的包文件在版本数据库中没有记录: {}@{}", crate_name, version);
+            if prune {
+                std::fs::remove_file(cache_manager.local_crate_path(&crate_name, &version)).map_err(CacheError::from)?;
+            }
+            report.inconsistent_files.push(format!("{}@{}", crate_name, version));
+        }
+    }
+
+    if !report.timed_out {
+        for record in version_manager.local_version_records()? {
+            if out_of_time() {
+                report.timed_out = true;
+                break;
+            }
+            if cache_manager.local_crate_path(&record.crate_name, &record.info.version).is_file() {
+                continue;
+            }
+            rat_logger::warn!("本地发布记录找不到包文件: {}@{}", record.crate_name, record.info.version);
+            if prune {
+                version_manager.remove_version(&record.crate_name, &record.info.version)?;
+            }
+            report.orphan_records.push(format!("{}@{}", record.crate_name, record.info.version));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::fake_crate_bytes;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_inconsistencies_are_reported_and_pruned() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        let version_manager = VersionManager::new(&config).unwrap();
+
+        // 一致的缓存文件和本地发布版本
+        version_manager.create_version_info("foo", "1.0.0", "", "", false).unwrap();
+        cache_manager.save_to_cache("foo", "1.0.0", "foo-1.0.0.crate", &fake_crate_bytes("foo")).unwrap();
+        version_manager.publish_local_version("mine", "0.1.0", "").unwrap();
+        cache_manager.save_local_crate("mine", "0.1.0", &fake_crate_bytes("mine")).unwrap();
+
+        // 与固定校验和不一致的缓存文件、没有记录的本地发布文件、找不到文件的本地发布记录
+        cache_manager.save_to_cache("bar", "2.0.0", "bar-2.0.0.crate", &fake_crate_bytes("bar")).unwrap();
+        cache_manager.save_local_crate("lost", "0.3.0", &fake_crate_bytes("lost")).unwrap();
+        version_manager.publish_local_version("gone", "0.2.0", "").unwrap();
+        let pinned = BTreeMap::from([
+            ("bar:2.0.0".to_string(), sha256_hex(b"something else")),
+            ("foo:1.0.0".to_string(), sha256_hex(&fake_crate_bytes("foo"))),
+        ]);
+
+        let report = check_consistency(&cache_manager, &version_manager, &pinned, false, None).unwrap();
+        assert_eq!(report.inconsistent_files, ["bar@2.0.0", "lost@0.3.0"]);
+        assert_eq!(report.orphan_records, ["gone@0.2.0"]);
+        assert!(!report.pruned && !report.timed_out);
+        assert!(cache_manager.is_cached("bar", "2.0.0", "bar-2.0.0.crate"));
+
+        let report = check_consistency(&cache_manager, &version_manager, &pinned, true, None).unwrap();
+        assert_eq!((report.inconsistent_files.len(), report.orphan_records.len()), (2, 1));
+        assert!(!cache_manager.is_cached("bar", "2.0.0", "bar-2.0.0.crate"));
+        assert!(!cache_manager.local_crate_path("lost", "0.3.0").exists());
+        assert!(version_manager.get_local_versions("gone").unwrap().is_empty());
+        assert!(cache_manager.is_cached("foo", "1.0.0", "foo-1.0.0.crate"));
+        assert!(cache_manager.local_crate_path("mine", "0.1.0").is_file());
+
+        let report = check_consistency(&cache_manager, &version_manager, &pinned, true, None).unwrap();
+        assert!(report.inconsistent_files.is_empty() && report.orphan_records.is_empty());
+
+        // 时间上限为0时立即停止
+        let report = check_consistency(&cache_manager, &version_manager, &pinned, false, Some(Duration::ZERO)).unwrap();
+        assert!(report.timed_out);
+        assert_eq!(report.crates, 0);
+    }
+
+    #[test]
+    fn test_file_with_expired_record_survives_prune() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        let version_manager = VersionManager::new(&config).unwrap();
+
+        version_manager.create_version_info("foo", "1.0.0", "", "", false).unwrap();
+        cache_manager.save_to_cache("foo", "1.0.0", "foo-1.0.0.crate", &fake_crate_bytes("foo")).unwrap();
+
+        // 记录过期后被定期清理，包文件仍然有效
+        version_manager.set_clock_offset(config.cache.default_ttl as i64 * 2);
+        assert!(version_manager.cleanup_expired_data().unwrap() > 0);
+        assert!(version_manager.get_all_versions("foo").unwrap().is_empty());
+
+        let report = check_consistency(&cache_manager, &version_manager, &BTreeMap::new(), true, None).unwrap();
+        assert!(report.inconsistent_files.is_empty() && report.orphan_records.is_empty());
+        assert!(cache_manager.is_cached("foo", "1.0.0", "foo-1.0.0.crate"));
+    }
+}
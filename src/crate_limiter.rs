@@ -0,0 +1,140 @@
+//! 按包名限制同时进行的上游下载数，避免一个包的大量版本占满上游并发，其他包的请求不受影响
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 排队等待的请求数已达上限，新请求不再排队
+#[derive(Debug, Error)]
+#[error("包 {crate_name} 已有 {waiting} 个下载请求在排队")]
+pub struct QueueFull {
+    pub crate_name: String,
+    pub waiting: usize,
+}
+
+pub struct CrateLimiter {
+    /// 每个包同时进行的下载数上限，0表示不限制
+    max_per_crate: usize,
+    /// 每个包排队等待的请求数上限，0表示不限制
+    max_queue_depth: usize,
+    /// 正在使用的包的信号量，没有持有者时删除
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// 一个包的下载许可，释放时清理不再使用的信号量
+pub struct CratePermit<'a> {
+    limiter: &'a CrateLimiter,
+    crate_name: String,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl CrateLimiter {
+    pub fn new(max_per_crate: usize, max_queue_depth: usize) -> Self {
+        Self {
+            max_per_crate,
+            max_queue_depth,
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 等待该包的下载许可，超过上限的请求排队，不限制时立即返回None。
+    /// 排队的请求数已达 `max_queue_depth` 时不再排队，返回 `QueueFull`
+    pub async fn acquire(&self, crate_name: &str) -> Result<Option<CratePermit<'_>>, QueueFull> {
+        if self.max_per_crate == 0 {
+            return Ok(None);
+        }
+
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().unwrap_or_else(PoisonError::into_inner);
+            let semaphore = semaphores
+                .entry(crate_name.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_crate)));
+            if semaphore.available_permits() == 0 {
+                // 除表中的引用外，每个持有许可或正在等待的请求各持有一个引用
+                let waiting = (Arc::strong_count(semaphore) - 1).saturating_sub(self.max_per_crate);
+                if self.max_queue_depth > 0 && waiting >= self.max_queue_depth {
+                    return Err(QueueFull { crate_name: crate_name.to_string(), waiting });
+                }
+                rat_logger::info!("包 {} 的并发下载数已达上限 {}，排队等待", crate_name, self.max_per_crate);
+            }
+            semaphore.clone()
+        };
+        // 信号量不会被关闭
+        let Ok(permit) = semaphore.acquire_owned().await else {
+            return Ok(None);
+        };
+
+        Ok(Some(CratePermit {
+            limiter: self,
+            crate_name: crate_name.to_string(),
+            permit: Some(permit),
+        }))
+    }
+}
+
+impl Drop for CratePermit<'_> {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        let mut semaphores = self.limiter.semaphores.lock().unwrap_or_else(PoisonError::into_inner);
+        // 只剩表中的引用时说明没有其他持有者或等待者
+        if semaphores
+            .get(&self.crate_name)
+            .is_some_and(|semaphore| Arc::strong_count(semaphore) == 1)
+        {
+            semaphores.remove(&self.crate_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 当前有下载或排队的包数
+    fn active_crates(limiter: &CrateLimiter) -> usize {
+        limiter.semaphores.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    #[tokio::test]
+    async fn test_permits_are_per_crate() {
+        let limiter = CrateLimiter::new(1, 0);
+        let foo = limiter.acquire("foo").await.unwrap().unwrap();
+
+        // 同一个包的第二个请求需要等待，其他包不受影响
+        let waiting = tokio::time::timeout(std::time::Duration::from_millis(50), limiter.acquire("foo")).await;
+        assert!(waiting.is_err());
+        let bar = limiter.acquire("bar").await.unwrap().unwrap();
+        assert_eq!(active_crates(&limiter), 2);
+
+        drop(foo);
+        drop(bar);
+        assert_eq!(active_crates(&limiter), 0);
+        assert!(CrateLimiter::new(0, 0).acquire("foo").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_queue_depth_sheds_excess_waiters() {
+        let limiter = Arc::new(CrateLimiter::new(1, 2));
+        let holder = limiter.acquire("foo").await.unwrap().unwrap();
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.acquire("foo").await.map(|permit| permit.is_some()) })
+            })
+            .collect();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // 两个请求在排队，第三个立即被拒绝，其他包不受影响
+        let rejected = limiter.acquire("foo").await;
+        assert!(matches!(rejected, Err(QueueFull { waiting: 2, .. })));
+        assert!(limiter.acquire("bar").await.unwrap().is_some());
+
+        drop(holder);
+        for waiter in waiters {
+            assert!(waiter.await.unwrap().unwrap());
+        }
+        assert_eq!(active_crates(&limiter), 0);
+    }
+}
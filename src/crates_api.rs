@@ -1,8 +1,10 @@
+use crate::cache::is_storage_full;
+use crate::checksum::sha256_hex;
 use crate::config::Config;
-use curl::easy::{Easy};
-use serde::{Deserialize, Serialize};
+use crate::curl_client::CurlErrorKind;
+use crate::index_snapshot::IndexSnapshot;
+use curl::easy::Easy;
 use serde_json::Value;
-use std::collections::HashMap;
 use std::path::Path;
 use std::time::Duration;
 
@@ -14,6 +16,7 @@ pub struct CrateVersion {
     pub yanked: bool,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct CrateInfo {
     pub id: String,
@@ -24,43 +27,209 @@ pub struct CrateInfo {
     pub versions: Vec<u64>, // 版本ID列表
 }
 
+/// 按下载量排序的包列表中的一项
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct PopularCrate {
+    pub name: String,
+    pub max_version: String,
+    pub downloads: u64,
+}
+
+/// 一次下载的上游链路信息，用于诊断重定向问题
+#[derive(Debug, Clone)]
+pub struct DownloadTrace {
+    /// 经过的重定向次数
+    pub redirect_count: u32,
+    /// 最终实际请求的地址
+    pub effective_url: String,
+}
+
+/// 下载过程中使用的临时文件路径
+pub fn partial_path(save_path: &Path) -> std::path::PathBuf {
+    let mut file_name = save_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    save_path.with_file_name(file_name)
+}
+
+/// 把下载内容保存到缓存路径：先写入临时文件再重命名，失败时删除写了一半的文件，避免被当作缓存命中
+pub fn save_crate_file(save_path: &Path, data: &[u8]) -> Result<(), ApiError> {
+    let temp_path = partial_path(save_path);
+    if let Err(e) = std::fs::write(&temp_path, data).and_then(|_| std::fs::rename(&temp_path, save_path)) {
+        let _ = std::fs::remove_file(&temp_path);
+        if is_storage_full(&e) {
+            return Err(ApiError::StorageFull(format!("保存文件失败: {}", e)));
+        }
+        return Err(ApiError::IoError(format!("保存文件失败: {}", e)));
+    }
+    Ok(())
+}
+
+/// 按 `dl` 模板生成包文件的下载地址：含 `{crate}` 或 `{version}` 时替换，否则追加 `/{crate}/{version}/download`
+fn crate_download_url(template: &str, crate_name: &str, version: &str) -> String {
+    if template.contains("{crate}") || template.contains("{version}") {
+        template.replace("{crate}", crate_name).replace("{version}", version)
+    } else {
+        format!("{}/{}/{}/download", template, crate_name, version)
+    }
+}
+
+/// 内容是否像HTML页面（跳过开头的空白和UTF-8 BOM后以 `<` 开头）
+fn looks_like_html(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    data.iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'<')
+}
+
+/// 边接收边解压gzip数据流，解码出错时立即报告，不必等下载完成后再完整解压一遍
+///
+/// 前两个字节不是gzip魔数时（如HTML错误页、非200响应的正文）不做检查，交给下载完成后的格式检查处理
+struct GzipStreamCheck {
+    decoder: Option<flate2::write::GzDecoder<std::io::Sink>>,
+    /// 凑满魔数之前收到的字节
+    head: Vec<u8>,
+    /// 已确认不是gzip数据，后续数据不再检查
+    skipped: bool,
+}
+
+impl GzipStreamCheck {
+    fn new() -> Self {
+        Self { decoder: None, head: Vec::new(), skipped: false }
+    }
+
+    /// 送入新收到的一段数据，解码失败时返回错误
+    fn feed(&mut self, buf: &[u8]) -> Result<(), String> {
+        use std::io::Write;
+
+        if self.skipped {
+            return Ok(());
+        }
+        if self.decoder.is_none() {
+            self.head.extend_from_slice(buf);
+            if self.head.len() < 2 {
+                return Ok(());
+            }
+            if !self.head.starts_with(&[0x1f, 0x8b]) {
+                self.skipped = true;
+                return Ok(());
+            }
+            let mut decoder = flate2::write::GzDecoder::new(std::io::sink());
+            let head = std::mem::take(&mut self.head);
+            decoder.write_all(&head).map_err(|e| e.to_string())?;
+            self.decoder = Some(decoder);
+            return Ok(());
+        }
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.write_all(buf).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// 数据接收完毕，确认gzip流完整（没有被截断）
+    fn finish(&mut self) -> Result<(), String> {
+        match self.decoder.as_mut() {
+            Some(decoder) => decoder.try_finish().map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 检查 `.crate` 的tar结构：所有条目都位于 `{name}-{version}/` 下，且包含该目录下的 `Cargo.toml`
+pub fn validate_crate_archive(data: &[u8], crate_name: &str, version: &str) -> Result<(), ApiError> {
+    let root = format!("{}-{}", crate_name, version);
+    let manifest = Path::new(&root).join("Cargo.toml");
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+    let mut has_manifest = false;
+
+    let entries = archive
+        .entries()
+        .map_err(|e| ApiError::InvalidFileFormat(format!("无法读取tar内容: {}", e)))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| ApiError::InvalidFileFormat(format!("tar条目损坏: {}", e)))?;
+        let path = entry
+            .path()
+            .map_err(|e| ApiError::InvalidFileFormat(format!("tar条目路径无效: {}", e)))?;
+
+        if !path.starts_with(&root) {
+            return Err(ApiError::InvalidFileFormat(format!("条目 {:?} 不在顶层目录 {} 下", path, root)));
+        }
+        if path == manifest {
+            has_manifest = true;
+        }
+    }
+
+    if !has_manifest {
+        return Err(ApiError::InvalidFileFormat(format!("缺少 {}/Cargo.toml", root)));
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct CratesApiClient {
     proxy_url: Option<String>,
     user_agent: String,
-    timeout: Duration,
+    /// 元数据API请求的超时
+    api_timeout: Duration,
+    /// 包文件下载的超时
+    download_timeout: Duration,
+    /// API根地址，不带结尾的 `/`
+    api_url: String,
+    /// 包文件下载地址模板，格式同索引 config.json 中的 `dl`
+    download_url: String,
+    /// 保存前是否解压检查包结构
+    validate_crate_structure: bool,
+    /// 上游支持Range时的并行分块数，小于2时不分块
+    parallel_download_chunks: u32,
+    /// 低速中止阈值（字节/秒），0表示不启用
+    low_speed_limit: u32,
+    /// 传输速度持续低于阈值多久后中止
+    low_speed_time: Duration,
+    /// 得到200的HTML错误页或过小的响应体时的重试次数
+    html_error_retries: u32,
+    /// 校验和不一致时的重新下载次数
+    checksum_mismatch_retries: u32,
+    /// 包文件的最小合理大小（字节），更小的200响应视为上游故障
+    min_crate_size: u64,
+    /// 单连接下载时边接收边解压检查gzip数据流
+    validate_gzip_stream: bool,
+    /// 本地索引快照，查询版本时优先使用
+    index_snapshot: Option<IndexSnapshot>,
 }
 
 impl CratesApiClient {
     pub fn new(config: &Config) -> Self {
-        let proxy_url = config.upstream
-            .as_ref()
-            .and_then(|upstream| upstream.proxy_url.clone());
-
-        let user_agent = config.user_agent.value.clone();
+        let proxy_url = config.upstream.proxy_url.clone();
+        let user_agent = config.user_agent.compose();
 
         Self {
             proxy_url,
             user_agent,
-            timeout: Duration::from_secs(30),
+            api_timeout: Duration::from_secs(config.upstream.api_timeout_secs),
+            download_timeout: Duration::from_secs(config.upstream.download_timeout_secs),
+            api_url: config.upstream.api_url.trim_end_matches('/').to_string(),
+            download_url: match &config.upstream.download_url {
+                Some(url) => url.trim_end_matches('/').to_string(),
+                None => format!("{}/api/v1/crates", config.upstream.api_url.trim_end_matches('/')),
+            },
+            validate_crate_structure: config.upstream.validate_crate_structure,
+            parallel_download_chunks: config.upstream.parallel_download_chunks,
+            low_speed_limit: config.upstream.low_speed_limit,
+            low_speed_time: Duration::from_secs(config.upstream.low_speed_time),
+            html_error_retries: config.upstream.html_error_retries,
+            checksum_mismatch_retries: config.upstream.checksum_mismatch_retries,
+            min_crate_size: config.upstream.min_crate_size,
+            validate_gzip_stream: config.upstream.validate_gzip_stream,
+            index_snapshot: config.upstream.index_snapshot_path.as_ref().map(IndexSnapshot::new),
         }
     }
 
     /// 获取包的基本信息
     pub fn get_crate_info(&self, crate_name: &str) -> Result<CrateInfo, ApiError> {
-        let api_url = format!("https://crates.io/api/v1/crates/{}", crate_name);
+        let api_url = format!("{}/api/v1/crates/{}", self.api_url, crate_name);
 
-        let mut handle = Easy::new();
-        handle.url(&api_url)?;
-        handle.useragent(&self.user_agent)?;
-        handle.timeout(self.timeout)?;
+        let mut handle = self.upstream_handle(&api_url, self.api_timeout)?;
         handle.follow_location(true)?;
-        handle.verbose(false)?;
-
-        // 设置代理
-        if let Some(ref proxy_url) = self.proxy_url {
-            handle.proxy(proxy_url)?;
-        }
 
         let mut data = Vec::new();
         {
@@ -123,69 +292,292 @@ impl CratesApiClient {
         })
     }
 
-    /// 下载指定版本的包文件
+    /// 获取包文件内容：支持Range时并行分块下载，否则单连接下载，低速中止时重试一次
+    fn fetch_crate(&self, download_url: &str, crate_name: &str, version: &str) -> Result<(Vec<u8>, DownloadTrace), ApiError> {
+        if let Some(result) = self.download_ranges(download_url) {
+            return Ok(result);
+        }
+        match self.download_single(download_url) {
+            Err(ApiError::CurlError(e)) if self.low_speed_limit > 0 && CurlErrorKind::of(&e) == CurlErrorKind::Timeout => {
+                rat_logger::warn!("下载 {}-{} 速度过低被中止，重试一次: {}", crate_name, version, e);
+                self.download_single(download_url)
+            }
+            result => result,
+        }
+    }
+
+    /// 下载指定版本的包文件，提供了期望的sha256时在保存前校验
     pub fn download_crate_version(
         &self,
         crate_name: &str,
         version: &str,
         save_path: &Path,
-    ) -> Result<(), ApiError> {
-        let download_url = format!("https://crates.io/api/v1/crates/{}/{}/download", crate_name, version);
+        expected_checksum: Option<&str>,
+    ) -> Result<DownloadTrace, ApiError> {
+        let (data, trace) = self.fetch_crate_version(crate_name, version, expected_checksum)?;
+        save_crate_file(save_path, &data)?;
+        Ok(trace)
+    }
 
+    /// 下载指定版本的包文件到内存并完成格式、校验和检查，不写入磁盘
+    pub fn fetch_crate_version(
+        &self,
+        crate_name: &str,
+        version: &str,
+        expected_checksum: Option<&str>,
+    ) -> Result<(Vec<u8>, DownloadTrace), ApiError> {
+        let download_url = crate_download_url(&self.download_url, crate_name, version);
+
+        // 校验和不一致多是传输中损坏（如被截断），按配置重新下载；传输错误直接返回，不在这里重试
+        let mut retries_left = self.checksum_mismatch_retries;
+        let (data, trace) = loop {
+            let (data, trace) = self.fetch_crate_body(&download_url, crate_name, version)?;
+            let Some(expected) = expected_checksum else { break (data, trace) };
+            let actual = sha256_hex(&data);
+            if actual.eq_ignore_ascii_case(expected) {
+                break (data, trace);
+            }
+            if retries_left == 0 {
+                return Err(ApiError::ChecksumMismatch(expected.to_string(), actual));
+            }
+            retries_left -= 1;
+            rat_logger::warn!("下载 {}-{} 校验和不一致（期望 {}，实际 {}），重试", crate_name, version, expected, actual);
+        };
+
+        if self.validate_crate_structure {
+            validate_crate_archive(&data, crate_name, version)?;
+        }
+
+        Ok((data, trace))
+    }
+
+    /// 下载包文件并检查大小和gzip格式，HTML错误页和过小的响应体按 `html_error_retries` 重试
+    fn fetch_crate_body(&self, download_url: &str, crate_name: &str, version: &str) -> Result<(Vec<u8>, DownloadTrace), ApiError> {
+        // 200的HTML错误页和空响应体通常是CDN节点的临时故障，按配置重新下载
+        let mut retries_left = self.html_error_retries;
+        loop {
+            let (data, trace) = self.fetch_crate(download_url, crate_name, version)?;
+            rat_logger::debug!(
+                "下载 {}-{} 经过 {} 次重定向，最终地址: {}",
+                crate_name, version, trace.redirect_count, trace.effective_url
+            );
+
+            if (data.len() as u64) < self.min_crate_size {
+                if retries_left == 0 {
+                    return Err(ApiError::BodyTooSmall(data.len(), trace.effective_url));
+                }
+                retries_left -= 1;
+                rat_logger::warn!("下载 {}-{} 只得到 {} 字节，重试: {}", crate_name, version, data.len(), trace.effective_url);
+                continue;
+            }
+
+            // 验证文件格式
+            if data.starts_with(&[0x1f, 0x8b]) {
+                return Ok((data, trace));
+            }
+            if !looks_like_html(&data) {
+                return Err(ApiError::InvalidFileFormat("文件不是有效的gzip格式".to_string()));
+            }
+            if retries_left == 0 {
+                return Err(ApiError::HtmlErrorPage(trace.effective_url));
+            }
+            retries_left -= 1;
+            rat_logger::warn!("下载 {}-{} 得到HTML错误页，重试: {}", crate_name, version, trace.effective_url);
+        }
+    }
+
+    /// 创建带User-Agent、超时、低速中止和代理设置的curl句柄，timeout按请求类型区分（API或下载）
+    fn upstream_handle(&self, url: &str, timeout: Duration) -> Result<Easy, ApiError> {
         let mut handle = Easy::new();
-        handle.url(&download_url)?;
+        handle.url(url)?;
         handle.useragent(&self.user_agent)?;
-        handle.timeout(self.timeout)?;
-        handle.follow_location(true)?;
+        handle.timeout(timeout)?;
         handle.verbose(false)?;
 
+        // 速度持续低于阈值时中止，避免涓流连接一直占用到总超时
+        if self.low_speed_limit > 0 {
+            handle.low_speed_limit(self.low_speed_limit)?;
+            handle.low_speed_time(self.low_speed_time)?;
+        }
+
         // 设置代理
         if let Some(ref proxy_url) = self.proxy_url {
             handle.proxy(proxy_url)?;
         }
+        Ok(handle)
+    }
+
+    /// 单连接下载完整文件
+    fn download_single(&self, download_url: &str) -> Result<(Vec<u8>, DownloadTrace), ApiError> {
+        let mut handle = self.upstream_handle(download_url, self.download_timeout)?;
+        handle.follow_location(true)?;
 
         let mut data = Vec::new();
-        {
+        let mut gzip_check = self.validate_gzip_stream.then(GzipStreamCheck::new);
+        let mut stream_error = None;
+        let performed = {
             let mut transfer = handle.transfer();
             transfer.write_function(|buf| {
+                if let Some(check) = gzip_check.as_mut()
+                    && let Err(e) = check.feed(buf)
+                {
+                    // 返回的长度小于收到的长度时curl会中止传输，不再接收剩余数据
+                    stream_error = Some(e);
+                    return Ok(0);
+                }
                 data.extend_from_slice(buf);
                 Ok(buf.len())
             })?;
-            transfer.perform()?;
+            transfer.perform()
+        };
+        if let Some(e) = stream_error {
+            return Err(ApiError::InvalidFileFormat(format!(
+                "gzip数据流解码失败（已接收 {} 字节时中止）: {}",
+                data.len(),
+                e
+            )));
         }
+        performed?;
+
+        let trace = DownloadTrace {
+            redirect_count: handle.redirect_count()?,
+            effective_url: handle.effective_url()?.unwrap_or(download_url).to_string(),
+        };
 
         let response_code = handle.response_code()?;
         if response_code != 200 {
-            return Err(ApiError::DownloadFailed(response_code, format!("下载失败: HTTP {}", response_code)));
+            return Err(ApiError::DownloadFailed(response_code, format!("下载失败: HTTP {}，最终地址: {}", response_code, trace.effective_url)));
         }
 
-        // 验证文件格式
-        if !data.starts_with(&[0x1f, 0x8b]) {
-            return Err(ApiError::InvalidFileFormat("文件不是有效的gzip格式".to_string()));
+        if let Some(check) = gzip_check.as_mut() {
+            check
+                .finish()
+                .map_err(|e| ApiError::InvalidFileFormat(format!("gzip数据流不完整: {}", e)))?;
         }
 
-        // 保存文件
-        std::fs::write(save_path, &data)
-            .map_err(|e| ApiError::IoError(format!("保存文件失败: {}", e)))?;
-
-        Ok(())
+        Ok((data, trace))
     }
 
-    /// 获取包的版本信息
-    pub fn get_available_versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>, ApiError> {
-        let api_url = format!("https://crates.io/api/v1/crates/{}", crate_name);
+    /// 按配置分块并行下载，未启用、上游不支持Range或任一分块失败时返回None，由调用方改用单连接下载
+    fn download_ranges(&self, download_url: &str) -> Option<(Vec<u8>, DownloadTrace)> {
+        if self.parallel_download_chunks < 2 {
+            return None;
+        }
 
-        let mut handle = Easy::new();
-        handle.url(&api_url)?;
-        handle.useragent(&self.user_agent)?;
-        handle.timeout(self.timeout)?;
+        match self.try_download_ranges(download_url) {
+            Ok(result) => result,
+            Err(e) => {
+                rat_logger::warn!("分块下载 {} 失败，改用单连接下载: {}", download_url, e);
+                None
+            }
+        }
+    }
+
+    fn try_download_ranges(&self, download_url: &str) -> Result<Option<(Vec<u8>, DownloadTrace)>, ApiError> {
+        // 先用HEAD跟随重定向，拿到最终地址、文件大小以及是否支持Range
+        let mut handle = self.upstream_handle(download_url, self.download_timeout)?;
         handle.follow_location(true)?;
-        handle.verbose(false)?;
+        handle.nobody(true)?;
 
-        // 设置代理
-        if let Some(ref proxy_url) = self.proxy_url {
-            handle.proxy(proxy_url)?;
+        let mut accept_ranges = false;
+        {
+            let mut transfer = handle.transfer();
+            transfer.header_function(|header| {
+                let line = String::from_utf8_lossy(header);
+                if line.starts_with("HTTP/") {
+                    // 每次重定向都会重新收到响应头，只看最终响应
+                    accept_ranges = false;
+                } else if let Some((name, value)) = line.split_once(':')
+                    && name.trim().eq_ignore_ascii_case("accept-ranges")
+                {
+                    accept_ranges = value.trim().eq_ignore_ascii_case("bytes");
+                }
+                true
+            })?;
+            transfer.perform()?;
+        }
+
+        let size = handle.content_length_download()?;
+        if handle.response_code()? != 200 || !accept_ranges || size < 1.0 {
+            rat_logger::debug!("上游不支持分块下载，使用单连接下载: {}", download_url);
+            return Ok(None);
+        }
+
+        let size = size as u64;
+        let chunks = u64::from(self.parallel_download_chunks).min(size);
+        let trace = DownloadTrace {
+            redirect_count: handle.redirect_count()?,
+            effective_url: handle.effective_url()?.unwrap_or(download_url).to_string(),
+        };
+
+        let chunk_size = size.div_ceil(chunks);
+        let ranges: Vec<(u64, u64)> = (0..size)
+            .step_by(chunk_size as usize)
+            .map(|start| (start, (start + chunk_size).min(size) - 1))
+            .collect();
+
+        let effective_url = trace.effective_url.as_str();
+        let parts = std::thread::scope(|scope| {
+            let workers: Vec<_> = ranges
+                .iter()
+                .map(|&(start, end)| scope.spawn(move || self.download_range(effective_url, start, end)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|_| Err(ApiError::IoError("分块下载线程异常退出".to_string())))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        rat_logger::debug!("分 {} 块并行下载完成: {}", parts.len(), trace.effective_url);
+        Ok(Some((parts.concat(), trace)))
+    }
+
+    /// 下载 `[start, end]` 字节区间，上游必须返回206且长度一致
+    fn download_range(&self, url: &str, start: u64, end: u64) -> Result<Vec<u8>, ApiError> {
+        let mut handle = self.upstream_handle(url, self.download_timeout)?;
+        handle.range(&format!("{}-{}", start, end))?;
+
+        let mut data = Vec::new();
+        {
+            let mut transfer = handle.transfer();
+            transfer.write_function(|buf| {
+                data.extend_from_slice(buf);
+                Ok(buf.len())
+            })?;
+            transfer.perform()?;
+        }
+
+        let response_code = handle.response_code()?;
+        if response_code != 206 || data.len() as u64 != end - start + 1 {
+            return Err(ApiError::DownloadFailed(
+                response_code,
+                format!("分块 {}-{} 返回 HTTP {}，长度 {}", start, end, response_code, data.len()),
+            ));
         }
+        Ok(data)
+    }
+
+    /// 获取包的版本信息，配置了索引快照且其中有该包时直接使用快照，否则查询API
+    pub fn get_available_versions(&self, crate_name: &str) -> Result<Vec<CrateVersion>, ApiError> {
+        if let Some(snapshot) = &self.index_snapshot {
+            match snapshot.versions(crate_name) {
+                Ok(Some(versions)) => {
+                    rat_logger::info!("从索引快照获取到 {} 个版本: {}", versions.len(), crate_name);
+                    return Ok(versions);
+                }
+                Ok(None) => {}
+                Err(e) => rat_logger::warn!("读取索引快照失败 {}: {}，改为查询API", crate_name, e),
+            }
+        }
+
+        let api_url = format!("{}/api/v1/crates/{}", self.api_url, crate_name);
+
+        let mut handle = self.upstream_handle(&api_url, self.api_timeout)?;
+        handle.follow_location(true)?;
 
         let mut data = Vec::new();
         {
@@ -242,20 +634,81 @@ impl CratesApiClient {
         Ok(versions)
     }
 
-    /// 获取特定版本的详细信息
-    fn get_version_details(&self, version_url: &str) -> Result<CrateVersion, ApiError> {
-        let mut handle = Easy::new();
-        handle.url(version_url)?;
-        handle.useragent(&self.user_agent)?;
-        handle.timeout(self.timeout)?;
+    /// 分页获取按下载量排序的包列表，page从1开始
+    pub fn list_crates_by_downloads(&self, page: usize, per_page: usize) -> Result<Vec<PopularCrate>, ApiError> {
+        let api_url = format!(
+            "{}/api/v1/crates?sort=downloads&per_page={}&page={}",
+            self.api_url, per_page, page
+        );
+
+        let mut handle = self.upstream_handle(&api_url, self.api_timeout)?;
         handle.follow_location(true)?;
-        handle.verbose(false)?;
 
-        // 设置代理
-        if let Some(ref proxy_url) = self.proxy_url {
-            handle.proxy(proxy_url)?;
+        let mut data = Vec::new();
+        {
+            let mut transfer = handle.transfer();
+            transfer.write_function(|buf| {
+                data.extend_from_slice(buf);
+                Ok(buf.len())
+            })?;
+            transfer.perform()?;
+        }
+
+        let response_code = handle.response_code()?;
+        if response_code != 200 {
+            return Err(ApiError::HttpError(response_code, String::from_utf8_lossy(&data).to_string()));
         }
 
+        let response_text = String::from_utf8(data)?;
+        let json: Value = serde_json::from_str(&response_text)?;
+
+        let crates = json.get("crates")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| ApiError::ParseError("缺少 'crates' 字段".to_string()))?;
+
+        let mut popular = Vec::with_capacity(crates.len());
+        for crate_obj in crates {
+            let name = crate_obj.get("name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ApiError::ParseError("缺少 'name' 字段".to_string()))?
+                .to_string();
+
+            let max_version = crate_obj.get("max_version")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let downloads = crate_obj.get("downloads")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+
+            popular.push(PopularCrate {
+                name,
+                max_version,
+                downloads,
+            });
+        }
+
+        rat_logger::debug!("获取热门包列表第 {} 页，共 {} 个", page, popular.len());
+        Ok(popular)
+    }
+
+    /// 按数字版本ID解析出具体版本，版本不属于该包时按不存在处理
+    pub fn resolve_version_id(&self, crate_name: &str, version_id: u64) -> Result<CrateVersion, ApiError> {
+        let version_url = format!("{}/api/v1/versions/{}", self.api_url, version_id);
+        let details = self.get_version_details(&version_url)?;
+
+        if !details.dl_path.starts_with(&format!("/api/v1/crates/{}/", crate_name)) {
+            return Err(ApiError::HttpError(404, format!("版本ID {} 不属于包 {}", version_id, crate_name)));
+        }
+        Ok(details)
+    }
+
+    /// 获取特定版本的详细信息
+    fn get_version_details(&self, version_url: &str) -> Result<CrateVersion, ApiError> {
+        let mut handle = self.upstream_handle(version_url, self.api_timeout)?;
+        handle.follow_location(true)?;
+
         let mut data = Vec::new();
         {
             let mut transfer = handle.transfer();
@@ -283,7 +736,10 @@ impl CratesApiClient {
             .to_string();
 
         let dl_path = format!("/api/v1/crates/{}/versions/{}/download",
-            version.get("crate_id").and_then(|v| v.as_str()).unwrap_or("unknown"),
+            version.get("crate")
+                .or_else(|| version.get("crate_id"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown"),
             version.get("id").and_then(|v| v.as_u64()).unwrap_or(0)
         );
 
@@ -304,6 +760,7 @@ impl CratesApiClient {
         })
     }
 
+    #[allow(dead_code)]
     fn generate_sample_versions(&self, crate_name: &str) -> Vec<CrateVersion> {
         // 简化的版本生成逻辑
         match crate_name {
@@ -373,6 +830,24 @@ impl CratesApiClient {
             )
         })
     }
+
+    /// 按semver版本要求（如 `^1.2`、`~1.2.3`、`>=1, <2`）选择满足条件的最高未撤销版本
+    pub fn resolve_version_req<'a>(
+        &self,
+        versions: &'a [CrateVersion],
+        req: &str,
+    ) -> Result<Option<&'a CrateVersion>, ApiError> {
+        let req = semver::VersionReq::parse(req.trim())
+            .map_err(|e| ApiError::InvalidVersionReq(format!("{}: {}", req, e)))?;
+
+        Ok(versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .filter_map(|v| semver::Version::parse(&v.num).ok().map(|parsed| (parsed, v)))
+            .filter(|(parsed, _)| req.matches(parsed))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, v)| v))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -389,10 +864,26 @@ pub enum ApiError {
     #[error("无效的文件格式: {0}")]
     InvalidFileFormat(String),
 
+    #[error("上游返回了HTML错误页（HTTP 200）: {0}")]
+    HtmlErrorPage(String),
+
+    #[error("上游返回的包文件过小（{0} 字节）: {1}")]
+    BodyTooSmall(usize, String),
+
+    #[error("无效的版本要求: {0}")]
+    InvalidVersionReq(String),
+
+    #[error("校验和不匹配: 期望 {0}，实际 {1}")]
+    ChecksumMismatch(String, String),
+
     #[error("IO错误: {0}")]
     IoError(String),
 
-    #[error("curl错误: {0}")]
+    #[error("磁盘空间不足: {0}")]
+    StorageFull(String),
+
+    /// 按 `CurlErrorKind` 分类显示，日志中能直接区分DNS、连接、TLS和超时错误
+    #[error("{}: {0}", CurlErrorKind::of(.0))]
     CurlError(#[from] curl::Error),
 
     #[error("JSON解析错误: {0}")]
@@ -402,18 +893,48 @@ pub enum ApiError {
     Utf8Error(#[from] std::string::FromUtf8Error),
 }
 
+impl ApiError {
+    /// 上游不可用（连接失败、超时、5xx、限流、HTML错误页），计入熔断的失败次数
+    pub fn is_upstream_unavailable(&self) -> bool {
+        match self {
+            ApiError::CurlError(_) | ApiError::HtmlErrorPage(_) | ApiError::BodyTooSmall(..) => true,
+            ApiError::HttpError(status, _) | ApiError::DownloadFailed(status, _) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// 上游对该版本返回了错误（HTTP错误、HTML错误页、过小的文件、校验和不一致），计入下载失败缓存。
+    /// 本地IO错误和磁盘空间不足与上游无关，不计入
+    pub fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self,
+            ApiError::DownloadFailed(..) | ApiError::HtmlErrorPage(_) | ApiError::BodyTooSmall(..) | ApiError::ChecksumMismatch(..)
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{crate_archive_bytes, fake_crate_bytes, MockResponse, MockServer};
     use tempfile::tempdir;
 
+    #[test]
+    fn test_local_errors_are_not_upstream_failures() {
+        assert!(ApiError::DownloadFailed(403, "forbidden".into()).is_upstream_failure());
+        assert!(ApiError::ChecksumMismatch("aa".into(), "bb".into()).is_upstream_failure());
+        assert!(!ApiError::IoError("disk".into()).is_upstream_failure());
+        assert!(!ApiError::StorageFull("disk".into()).is_upstream_failure());
+    }
+
     #[test]
     fn test_api_client_creation() {
         let config = Config::default();
         let client = CratesApiClient::new(&config);
 
-        assert_eq!(client.user_agent, "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36");
-        assert_eq!(client.timeout, Duration::from_secs(30));
+        assert_eq!(client.user_agent, config.user_agent.compose());
+        assert_eq!(client.api_timeout, Duration::from_secs(30));
+        assert_eq!(client.download_timeout, Duration::from_secs(30));
     }
 
     #[test]
@@ -440,4 +961,329 @@ mod tests {
         assert!(selected.is_some());
         assert_eq!(selected.unwrap().num, "1.0.0");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resolve_version_req() {
+        let client = CratesApiClient::new(&Config::default());
+        let versions: Vec<CrateVersion> = [
+            ("0.9.5", false),
+            ("1.0.0", false),
+            ("1.2.0", false),
+            ("1.2.7", false),
+            ("1.3.1", false),
+            ("1.4.0", true),
+            ("2.0.0-beta.1", false),
+            ("2.1.0", false),
+        ]
+        .into_iter()
+        .map(|(num, yanked)| CrateVersion {
+            num: num.to_string(),
+            dl_path: format!("/api/v1/crates/foo/{}/download", num),
+            checksum: String::new(),
+            yanked,
+        })
+        .collect();
+
+        let resolve = |req: &str| {
+            client
+                .resolve_version_req(&versions, req)
+                .unwrap()
+                .map(|v| v.num.as_str())
+        };
+
+        // 插入符：不跨主版本，跳过已撤销的1.4.0
+        assert_eq!(resolve("^1.2"), Some("1.3.1"));
+        assert_eq!(resolve("^0.9"), Some("0.9.5"));
+        // 波浪号：不跨次版本
+        assert_eq!(resolve("~1.2.3"), Some("1.2.7"));
+        assert_eq!(resolve("~1"), Some("1.3.1"));
+        // 比较符组合，不选预发布版本
+        assert_eq!(resolve(">=1, <2"), Some("1.3.1"));
+        assert_eq!(resolve(">1.3.1, <2.1"), None);
+        assert_eq!(resolve("*"), Some("2.1.0"));
+
+        assert!(matches!(
+            client.resolve_version_req(&versions, "not a req"),
+            Err(ApiError::InvalidVersionReq(_))
+        ));
+    }
+
+    #[test]
+    fn test_download_captures_redirects() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::status(302)
+                .with_header("Location", "/cdn/foo/foo-1.0.0.crate"),
+            "/cdn/foo/foo-1.0.0.crate" => MockResponse::ok(fake_crate_bytes("foo")),
+            _ => MockResponse::status(404),
+        });
+
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        let client = CratesApiClient::new(&config);
+
+        let dir = tempdir().unwrap();
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        let trace = client.download_crate_version("foo", "1.0.0", &save_path, None).unwrap();
+
+        assert_eq!(trace.redirect_count, 1);
+        assert_eq!(trace.effective_url, format!("{}/cdn/foo/foo-1.0.0.crate", server.url()));
+        assert_eq!(std::fs::read(&save_path).unwrap(), fake_crate_bytes("foo"));
+    }
+
+    #[test]
+    fn test_crate_download_url() {
+        assert_eq!(
+            crate_download_url("https://crates.io/api/v1/crates", "serde", "1.0.0"),
+            "https://crates.io/api/v1/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            crate_download_url("https://static.crates.io/crates/{crate}/{crate}-{version}.crate", "serde", "1.0.0"),
+            "https://static.crates.io/crates/serde/serde-1.0.0.crate"
+        );
+    }
+
+    #[test]
+    fn test_gzip_stream_check_aborts_on_corrupt_stream() {
+        // 合法的gzip头后面跟着保留的块类型（BTYPE=11），解码器读到第一个块就会出错
+        let mut corrupt = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0xff, 0x07];
+        corrupt.resize(4 * 1024 * 1024, 0);
+
+        let mut check = GzipStreamCheck::new();
+        let mut chunks = corrupt.chunks(16 * 1024);
+        assert!(check.feed(chunks.next().unwrap()).is_err());
+
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::ok(corrupt.clone()),
+            _ => MockResponse::status(404),
+        });
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        config.upstream.validate_gzip_stream = true;
+        let client = CratesApiClient::new(&config);
+
+        let url = format!("{}/api/v1/crates/foo/1.0.0/download", server.url());
+        match client.download_single(&url) {
+            Err(ApiError::InvalidFileFormat(msg)) => assert!(msg.contains("gzip数据流解码失败"), "{}", msg),
+            other => panic!("unexpected result: {:?}", other.map(|(data, _)| data.len())),
+        }
+
+        let dir = tempdir().unwrap();
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        assert!(client.download_crate_version("foo", "1.0.0", &save_path, None).is_err());
+        assert!(!save_path.exists());
+    }
+
+    #[test]
+    fn test_gzip_stream_check_accepts_valid_and_rejects_truncated() {
+        let archive = crate_archive_bytes("foo-1.0.0", &["Cargo.toml", "src/lib.rs"]);
+        let truncated = archive[..archive.len() - 8].to_vec();
+        let served = archive.clone();
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::ok(served.clone()),
+            "/api/v1/crates/foo/1.0.1/download" => MockResponse::ok(truncated.clone()),
+            "/api/v1/crates/foo/1.0.2/download" => MockResponse::ok("<html>bad gateway</html>"),
+            _ => MockResponse::status(404),
+        });
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        config.upstream.validate_gzip_stream = true;
+        let client = CratesApiClient::new(&config);
+
+        let dir = tempdir().unwrap();
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        client.download_crate_version("foo", "1.0.0", &save_path, None).unwrap();
+        assert_eq!(std::fs::read(&save_path).unwrap(), archive);
+
+        let result = client.download_crate_version("foo", "1.0.1", &dir.path().join("foo-1.0.1.crate"), None);
+        assert!(matches!(result, Err(ApiError::InvalidFileFormat(msg)) if msg.contains("不完整")));
+
+        // 非gzip内容不做流式检查，仍按HTML错误页处理
+        let result = client.download_crate_version("foo", "1.0.2", &dir.path().join("foo-1.0.2.crate"), None);
+        assert!(matches!(result, Err(ApiError::HtmlErrorPage(_))));
+    }
+
+    #[test]
+    fn test_parallel_ranged_download() {
+        let files: Vec<String> = (0..40).map(|i| format!("src/module_{}.rs", i)).collect();
+        let file_refs: Vec<&str> = files.iter().map(String::as_str).collect();
+        let archive = crate_archive_bytes("foo-1.0.0", &file_refs);
+        let checksum = sha256_hex(&archive);
+
+        let body = archive.clone();
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::status(302)
+                .with_header("Location", "/cdn/foo/foo-1.0.0.crate"),
+            "/cdn/foo/foo-1.0.0.crate" => {
+                let range = req
+                    .header("Range")
+                    .and_then(|r| r.strip_prefix("bytes="))
+                    .and_then(|r| r.split_once('-'))
+                    .map(|(a, b)| (a.parse::<usize>().unwrap(), b.parse::<usize>().unwrap()));
+                match range {
+                    Some((start, end)) => MockResponse::status(206)
+                        .with_header("Content-Range", &format!("bytes {}-{}/{}", start, end, body.len()))
+                        .with_body(body[start..=end].to_vec()),
+                    None => MockResponse::ok(body.clone()).with_header("Accept-Ranges", "bytes"),
+                }
+            }
+            // 不支持Range的上游
+            "/api/v1/crates/bar/1.0.0/download" => MockResponse::ok(fake_crate_bytes("bar")),
+            _ => MockResponse::status(404),
+        });
+
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        config.upstream.parallel_download_chunks = 4;
+        let client = CratesApiClient::new(&config);
+        let dir = tempdir().unwrap();
+
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        let trace = client
+            .download_crate_version("foo", "1.0.0", &save_path, Some(&checksum))
+            .unwrap();
+        assert_eq!(trace.redirect_count, 1);
+        assert_eq!(std::fs::read(&save_path).unwrap(), archive);
+
+        let cdn_requests: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.path == "/cdn/foo/foo-1.0.0.crate")
+            .collect();
+        assert_eq!(cdn_requests.iter().filter(|r| r.method == "HEAD").count(), 1);
+        assert_eq!(cdn_requests.iter().filter(|r| r.header("Range").is_some()).count(), 4);
+        // 分块直接请求最终地址，不再经过重定向
+        assert_eq!(server.hits("/api/v1/crates/foo/1.0.0/download"), 1);
+
+        let save_path = dir.path().join("bar-1.0.0.crate");
+        client.download_crate_version("bar", "1.0.0", &save_path, None).unwrap();
+        assert_eq!(std::fs::read(&save_path).unwrap(), fake_crate_bytes("bar"));
+        assert!(server.requests().iter().all(|r| r.path != "/api/v1/crates/bar/1.0.0/download" || r.header("Range").is_none()));
+    }
+
+    #[test]
+    fn test_low_speed_limit_aborts_trickling_transfer() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // 每200ms只发送1个字节的上游
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                accepted.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                        line.clear();
+                    }
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n\x1f");
+                    for _ in 0..50 {
+                        std::thread::sleep(Duration::from_millis(200));
+                        if stream.write_all(b"x").is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut config = Config::default();
+        config.upstream.api_url = format!("http://{}", addr);
+        config.upstream.low_speed_limit = 100;
+        config.upstream.low_speed_time = 1;
+        let client = CratesApiClient::new(&config);
+        assert_eq!(client.low_speed_limit, 100);
+        assert_eq!(client.low_speed_time, Duration::from_secs(1));
+
+        let dir = tempdir().unwrap();
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        let started = std::time::Instant::now();
+        let result = client.download_crate_version("foo", "1.0.0", &save_path, None);
+
+        // 远早于30秒的总超时被中止，并且重试了一次
+        assert!(matches!(result, Err(ApiError::CurlError(ref e)) if e.is_operation_timedout()));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        assert!(!save_path.exists());
+    }
+
+    #[test]
+    fn test_operation_timeouts_apply_per_request_type() {
+        let server = MockServer::start(|req| {
+            std::thread::sleep(Duration::from_millis(300));
+            match req.path.as_str() {
+                "/api/v1/crates/foo" => MockResponse::ok(r#"{"versions":[]}"#),
+                "/api/v1/crates/foo/1.0.0/download" => MockResponse::ok(fake_crate_bytes("foo")),
+                _ => MockResponse::status(404),
+            }
+        });
+
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        config.upstream.api_timeout_secs = 5;
+        config.upstream.download_timeout_secs = 600;
+        let mut client = CratesApiClient::new(&config);
+        assert_eq!(client.api_timeout, Duration::from_secs(5));
+        assert_eq!(client.download_timeout, Duration::from_secs(600));
+
+        let dir = tempdir().unwrap();
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        fn timed_out<T>(result: &Result<T, ApiError>) -> bool {
+            matches!(result, Err(ApiError::CurlError(e)) if e.is_operation_timedout())
+        }
+
+        // API超时短于上游响应时间，下载不受影响
+        client.api_timeout = Duration::from_millis(100);
+        client.download_timeout = Duration::from_secs(5);
+        assert!(timed_out(&client.get_available_versions("foo")));
+        assert!(client.download_crate_version("foo", "1.0.0", &save_path, None).is_ok());
+
+        // 反过来只有下载超时
+        std::fs::remove_file(&save_path).unwrap();
+        client.api_timeout = Duration::from_secs(5);
+        client.download_timeout = Duration::from_millis(100);
+        assert!(client.get_available_versions("foo").is_ok());
+        assert!(timed_out(&client.download_crate_version("foo", "1.0.0", &save_path, None)));
+        assert!(!save_path.exists());
+    }
+
+    #[test]
+    fn test_validate_crate_structure() {
+        let valid = crate_archive_bytes("foo-1.0.0", &["Cargo.toml", "src/lib.rs"]);
+        let missing_manifest = crate_archive_bytes("foo-1.0.0", &["src/lib.rs"]);
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::ok(valid.clone()),
+            "/api/v1/crates/foo/2.0.0/download" => MockResponse::ok(missing_manifest.clone()),
+            _ => MockResponse::status(404),
+        });
+
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+        config.upstream.validate_crate_structure = true;
+        let client = CratesApiClient::new(&config);
+        let dir = tempdir().unwrap();
+
+        let save_path = dir.path().join("foo-1.0.0.crate");
+        client.download_crate_version("foo", "1.0.0", &save_path, None).unwrap();
+        assert!(save_path.exists());
+
+        let save_path = dir.path().join("foo-2.0.0.crate");
+        let result = client.download_crate_version("foo", "2.0.0", &save_path, None);
+        assert!(matches!(result, Err(ApiError::InvalidFileFormat(_))));
+        assert!(!save_path.exists());
+    }
+
+    #[test]
+    fn test_validate_crate_archive_rejects_wrong_root() {
+        let data = crate_archive_bytes("bar-1.0.0", &["Cargo.toml"]);
+        assert!(validate_crate_archive(&data, "bar", "1.0.0").is_ok());
+        assert!(validate_crate_archive(&data, "foo", "1.0.0").is_err());
+        assert!(validate_crate_archive(&fake_crate_bytes("foo"), "foo", "1.0.0").is_err());
+    }
+}
@@ -1,27 +1,131 @@
 use curl::easy::{Easy, List};
-use std::io::{self, Read};
+use std::fmt;
 use std::time::Duration;
 use thiserror::Error;
 
-// 添加调试日志
-use rat_logger;
+/// libcurl错误的类别，用于判断是否值得重试，并让日志直接指出是哪一环出了问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurlErrorKind {
+    /// 上游或代理的域名解析失败
+    Dns,
+    /// TCP连接被拒绝或无法建立
+    Connect,
+    /// TLS握手、证书校验等错误
+    Tls,
+    /// 超过总超时或低速中止
+    Timeout,
+    /// 连接建立后收发数据中断（连接被重置、响应为空、内容不完整）
+    Transfer,
+    /// 其他错误（URL格式、回调中止等）
+    Other,
+}
+
+impl CurlErrorKind {
+    pub fn of(e: &curl::Error) -> Self {
+        if e.is_couldnt_resolve_host() || e.is_couldnt_resolve_proxy() {
+            CurlErrorKind::Dns
+        } else if e.is_couldnt_connect() {
+            CurlErrorKind::Connect
+        } else if e.is_operation_timedout() {
+            CurlErrorKind::Timeout
+        } else if e.is_ssl_connect_error()
+            || e.is_peer_failed_verification()
+            || e.is_ssl_certproblem()
+            || e.is_ssl_cipher()
+            || e.is_ssl_cacert()
+            || e.is_ssl_cacert_badfile()
+            || e.is_ssl_crl_badfile()
+            || e.is_ssl_issuer_error()
+            || e.is_ssl_shutdown_failed()
+            || e.is_ssl_engine_notfound()
+            || e.is_ssl_engine_setfailed()
+            || e.is_ssl_engine_initfailed()
+            || e.is_use_ssl_failed()
+        {
+            CurlErrorKind::Tls
+        } else if e.is_recv_error() || e.is_send_error() || e.is_got_nothing() || e.is_partial_file() || e.is_http2_stream_error() {
+            CurlErrorKind::Transfer
+        } else {
+            CurlErrorKind::Other
+        }
+    }
+}
+
+impl fmt::Display for CurlErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CurlErrorKind::Dns => "DNS解析失败",
+            CurlErrorKind::Connect => "连接失败",
+            CurlErrorKind::Tls => "TLS错误",
+            CurlErrorKind::Timeout => "请求超时",
+            CurlErrorKind::Transfer => "传输中断",
+            CurlErrorKind::Other => "curl错误",
+        })
+    }
+}
 
 #[derive(Debug, Error)]
 pub enum CurlError {
+    #[error("DNS解析失败: {0}")]
+    Dns(curl::Error),
+    #[error("连接失败: {0}")]
+    Connect(curl::Error),
+    #[error("TLS错误: {0}")]
+    Tls(curl::Error),
+    #[error("请求超时: {0}")]
+    Timeout(curl::Error),
+    #[error("传输中断: {0}")]
+    Transfer(curl::Error),
     #[error("curl错误: {0}")]
-    CurlError(#[from] curl::Error),
+    #[allow(clippy::enum_variant_names)]
+    CurlError(curl::Error),
     #[error("IO错误: {0}")]
     IoError(#[from] std::io::Error),
     #[error("HTTP错误: {0}")]
+    #[allow(dead_code)]
     HttpError(String),
-    #[error("超时错误")]
-    TimeoutError,
+}
+
+impl From<curl::Error> for CurlError {
+    fn from(e: curl::Error) -> Self {
+        match CurlErrorKind::of(&e) {
+            CurlErrorKind::Dns => CurlError::Dns(e),
+            CurlErrorKind::Connect => CurlError::Connect(e),
+            CurlErrorKind::Tls => CurlError::Tls(e),
+            CurlErrorKind::Timeout => CurlError::Timeout(e),
+            CurlErrorKind::Transfer => CurlError::Transfer(e),
+            CurlErrorKind::Other => CurlError::CurlError(e),
+        }
+    }
+}
+
+impl CurlError {
+    /// 是否是重试通常能恢复的临时故障（DNS、连接、超时、传输中断）。
+    /// TLS错误多为证书或配置问题，其他错误多为请求本身有误，重试无用
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CurlError::Dns(_) | CurlError::Connect(_) | CurlError::Timeout(_) | CurlError::Transfer(_)
+        )
+    }
+}
+
+/// 条件请求的响应
+#[derive(Debug, Clone)]
+pub struct ConditionalResponse {
+    /// HTTP状态码，304表示上游内容未变化
+    pub status: u32,
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
 }
 
 pub struct CurlClient {
     user_agent: String,
     proxy_url: Option<String>,
     timeout: Duration,
+    /// 低速中止阈值（字节/秒）和持续时间，None表示不启用
+    low_speed: Option<(u32, Duration)>,
 }
 
 impl CurlClient {
@@ -30,6 +134,7 @@ impl CurlClient {
             user_agent,
             proxy_url,
             timeout: Duration::from_secs(30),
+            low_speed: None,
         }
     }
 
@@ -38,6 +143,21 @@ impl CurlClient {
         self
     }
 
+    /// 速度低于 `limit` 字节/秒持续 `time` 时中止传输，limit为0时不启用
+    pub fn with_low_speed_limit(mut self, limit: u32, time: Duration) -> Self {
+        self.low_speed = (limit > 0).then_some((limit, time));
+        self
+    }
+
+    fn apply_low_speed(&self, handle: &mut Easy) -> Result<(), CurlError> {
+        if let Some((limit, time)) = self.low_speed {
+            handle.low_speed_limit(limit)?;
+            handle.low_speed_time(time)?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
     pub fn get(&self, url: &str) -> Result<Vec<u8>, CurlError> {
         rat_logger::info!("开始下载: {}", url);
         if let Some(ref proxy) = self.proxy_url {
@@ -55,6 +175,7 @@ impl CurlClient {
 
         handle.timeout(self.timeout)?;
         rat_logger::info!("设置超时: {:?}", self.timeout);
+        self.apply_low_speed(&mut handle)?;
 
         // 设置代理
         if let Some(ref proxy) = self.proxy_url {
@@ -83,7 +204,7 @@ impl CurlClient {
                 Ok(_) => rat_logger::info!("transfer执行成功"),
                 Err(e) => {
                     rat_logger::error!("transfer执行失败: {}", e);
-                    return Err(CurlError::CurlError(e));
+                    return Err(e.into());
                 }
             }
         }
@@ -101,11 +222,75 @@ impl CurlClient {
         Ok(buf)
     }
 
+    /// 带 If-None-Match / If-Modified-Since 的GET请求，返回状态码和缓存验证头，不把4xx/5xx当作错误
+    pub fn conditional_get(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<ConditionalResponse, CurlError> {
+        let mut handle = Easy::new();
+        handle.url(url)?;
+        handle.useragent(&self.user_agent)?;
+        handle.timeout(self.timeout)?;
+        self.apply_low_speed(&mut handle)?;
+
+        // 设置代理
+        if let Some(ref proxy) = self.proxy_url {
+            handle.proxy(proxy)?;
+        }
+
+        let mut header_list = List::new();
+        if let Some(etag) = etag {
+            header_list.append(&format!("If-None-Match: {}", etag))?;
+        }
+        if let Some(last_modified) = last_modified {
+            header_list.append(&format!("If-Modified-Since: {}", last_modified))?;
+        }
+        handle.http_headers(header_list)?;
+
+        // 设置重定向跟随
+        handle.follow_location(true)?;
+        handle.max_redirections(5)?;
+
+        let mut body = Vec::new();
+        let mut headers: Vec<(String, String)> = Vec::new();
+        {
+            let mut transfer = handle.transfer();
+            transfer.write_function(|data| {
+                body.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            transfer.header_function(|line| {
+                let line = String::from_utf8_lossy(line);
+                if line.starts_with("HTTP/") {
+                    // 发生重定向时只保留最后一个响应的头
+                    headers.clear();
+                } else if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+                true
+            })?;
+
+            transfer.perform()?;
+        }
+
+        let header = |name: &str| headers.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+        Ok(ConditionalResponse {
+            status: handle.response_code()?,
+            body,
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+        })
+    }
+
+    #[allow(dead_code)]
     pub fn download_file(&self, url: &str, output_path: &str) -> Result<(), CurlError> {
         let mut handle = Easy::new();
         handle.url(url)?;
         handle.useragent(&self.user_agent)?;
         handle.timeout(self.timeout)?;
+        self.apply_low_speed(&mut handle)?;
 
         // 设置代理
         if let Some(ref proxy) = self.proxy_url {
@@ -123,7 +308,7 @@ impl CurlClient {
             let mut transfer = handle.transfer();
             transfer.write_function(|data| {
                 use std::io::Write;
-                file.write_all(data).map_err(|e| {
+                file.write_all(data).map_err(|_| {
                     curl::easy::WriteError::Pause
                 })?;
                 Ok(data.len())
@@ -150,6 +335,7 @@ impl CurlClient {
         handle.url(url)?;
         handle.useragent(&self.user_agent)?;
         handle.timeout(self.timeout)?;
+        self.apply_low_speed(&mut handle)?;
         handle.nobody(true)?;
 
         // 设置代理
@@ -167,11 +353,13 @@ impl CurlClient {
         Ok(response_code)
     }
 
+    #[allow(dead_code)]
     pub fn set_headers(&self, url: &str, headers: &[(&str, &str)]) -> Result<Vec<u8>, CurlError> {
         let mut handle = Easy::new();
         handle.url(url)?;
         handle.useragent(&self.user_agent)?;
         handle.timeout(self.timeout)?;
+        self.apply_low_speed(&mut handle)?;
 
         // 设置代理
         if let Some(ref proxy) = self.proxy_url {
@@ -228,4 +416,57 @@ mod tests {
         assert_eq!(client.user_agent, "test-agent");
         assert_eq!(client.proxy_url, Some("http://proxy.example.com:8080".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_timeout_applies_to_index_requests() {
+        use crate::test_support::{MockResponse, MockServer};
+
+        let server = MockServer::start(|_| {
+            std::thread::sleep(Duration::from_millis(300));
+            MockResponse::ok("{}")
+        });
+        let url = format!("{}/config.json", server.url());
+
+        let client = CurlClient::new("test-agent".to_string(), None).with_timeout(Duration::from_millis(100));
+        let result = client.conditional_get(&url, None, None);
+        assert!(matches!(result, Err(CurlError::Timeout(_))));
+
+        let client = CurlClient::new("test-agent".to_string(), None).with_timeout(Duration::from_secs(5));
+        assert_eq!(client.conditional_get(&url, None, None).unwrap().status, 200);
+    }
+
+    #[test]
+    fn test_curl_error_categories() {
+        // libcurl的CURLcode
+        let cases = [
+            (5, CurlErrorKind::Dns),       // COULDNT_RESOLVE_PROXY
+            (6, CurlErrorKind::Dns),       // COULDNT_RESOLVE_HOST
+            (7, CurlErrorKind::Connect),   // COULDNT_CONNECT
+            (28, CurlErrorKind::Timeout),  // OPERATION_TIMEDOUT
+            (35, CurlErrorKind::Tls),      // SSL_CONNECT_ERROR
+            (60, CurlErrorKind::Tls),      // PEER_FAILED_VERIFICATION
+            (18, CurlErrorKind::Transfer), // PARTIAL_FILE
+            (52, CurlErrorKind::Transfer), // GOT_NOTHING
+            (56, CurlErrorKind::Transfer), // RECV_ERROR
+            (3, CurlErrorKind::Other),     // URL_MALFORMAT
+            (23, CurlErrorKind::Other),    // WRITE_ERROR
+        ];
+        for (code, kind) in cases {
+            let error = curl::Error::new(code);
+            assert_eq!(CurlErrorKind::of(&error), kind, "CURLcode {}", code);
+            let error = CurlError::from(error);
+            let transient = matches!(kind, CurlErrorKind::Dns | CurlErrorKind::Connect | CurlErrorKind::Timeout | CurlErrorKind::Transfer);
+            assert_eq!(error.is_transient(), transient, "CURLcode {}", code);
+            assert!(error.to_string().starts_with(&kind.to_string()), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_connection_refused_is_classified() {
+        // 绑定后立即关闭，得到一个没有监听的端口
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = CurlClient::new("test-agent".to_string(), None);
+        let result = client.conditional_get(&format!("http://127.0.0.1:{}/config.json", port), None, None);
+        assert!(matches!(result, Err(CurlError::Connect(_))), "{:?}", result.err());
+    }
+}
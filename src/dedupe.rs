@@ -0,0 +1,134 @@
+//! 合并只有连字符/下划线不同的重复包目录（如 `foo_bar` 与 `foo-bar`）
+//!
+//! crates.io 把 `-` 和 `_` 视为同一个包名，按请求路径原样建目录时同一个包可能缓存两份。
+//! 合并时以全部使用 `-` 的名称为规范名称，缓存文件和版本数据库记录都移到规范名称下。
+
+use crate::cache::{CacheError, CacheManager};
+use crate::version_manager::{VersionManager, VersionManagerError};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DedupeError {
+    #[error("缓存错误: {0}")]
+    CacheError(#[from] CacheError),
+    #[error("版本管理错误: {0}")]
+    VersionManagerError(#[from] VersionManagerError),
+}
+
+/// 一组被合并的包名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedCrate {
+    /// 合并后的规范名称
+    pub canonical: String,
+    /// 被合并进来的其他写法
+    pub merged_from: Vec<String>,
+    /// 移动的缓存文件数
+    pub files: usize,
+    /// 移动的版本记录数
+    pub versions: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    pub merged: Vec<MergedCrate>,
+}
+
+/// 包名的规范写法：下划线替换为连字符
+pub fn normalize_crate_name(name: &str) -> String {
+    name.replace('_', "-")
+}
+
+/// 找出缓存和版本数据库中只有连字符/下划线不同的包名，合并到规范名称下
+pub fn dedupe_cache(cache_manager: &CacheManager, version_manager: &VersionManager) -> Result<DedupeReport, DedupeError> {
+    let mut names = cache_manager.crate_names()?;
+    let dump = version_manager.dump()?;
+    names.extend(dump.versions.into_iter().map(|record| record.crate_name));
+    names.extend(dump.latest.into_iter().map(|mapping| mapping.crate_name));
+
+    let mut groups: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for name in names {
+        groups.entry(normalize_crate_name(&name)).or_default().insert(name);
+    }
+
+    let mut report = DedupeReport::default();
+    for (canonical, spellings) in groups {
+        if spellings.len() < 2 {
+            continue;
+        }
+
+        let mut merged = MergedCrate {
+            canonical: canonical.clone(),
+            merged_from: Vec::new(),
+            files: 0,
+            versions: 0,
+        };
+        for name in spellings.into_iter().filter(|name| *name != canonical) {
+            merged.files += cache_manager.merge_crate_dir(&name, &canonical)?;
+            merged.versions += version_manager.merge_crate(&name, &canonical)?;
+            merged.merged_from.push(name);
+        }
+        rat_logger::info!(
+            "合并重复包目录 {:?} -> {}: {} 个文件，{} 条版本信息",
+            merged.merged_from,
+            merged.canonical,
+            merged.files,
+            merged.versions
+        );
+        report.merged.push(merged);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::fake_crate_bytes;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_duplicate_spellings_collapse_to_canonical() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        let version_manager = VersionManager::new(&config).unwrap();
+
+        for (name, version) in [("foo-bar", "1.0.0"), ("foo_bar", "1.0.0"), ("foo_bar", "1.1.0"), ("serde", "1.0.0")] {
+            let filename = format!("{}-{}.crate", name, version);
+            cache_manager.save_to_cache(name, version, &filename, &fake_crate_bytes(name)).unwrap();
+            version_manager.create_version_info(name, version, "/dl", "abc", false).unwrap();
+        }
+        version_manager.record_download("foo_bar", "1.0.0").unwrap();
+        version_manager.record_download("foo-bar", "1.0.0").unwrap();
+        version_manager.set_latest_version("foo_bar", "1.1.0").unwrap();
+
+        let report = dedupe_cache(&cache_manager, &version_manager).unwrap();
+        assert_eq!(
+            report.merged,
+            vec![MergedCrate {
+                canonical: "foo-bar".into(),
+                merged_from: vec!["foo_bar".into()],
+                files: 1,
+                versions: 2,
+            }]
+        );
+
+        assert_eq!(cache_manager.crate_names().unwrap(), BTreeSet::from(["foo-bar".to_string(), "serde".to_string()]));
+        assert_eq!(cache_manager.cached_versions("foo-bar"), vec!["1.1.0", "1.0.0"]);
+        assert!(cache_manager.is_cached("foo-bar", "1.1.0", "foo-bar-1.1.0.crate"));
+
+        let mut versions: Vec<_> = version_manager.get_all_versions("foo-bar").unwrap().into_iter().map(|info| info.version).collect();
+        versions.sort();
+        assert_eq!(versions, vec!["1.0.0", "1.1.0"]);
+        assert!(version_manager.get_all_versions("foo_bar").unwrap().is_empty());
+        assert_eq!(version_manager.get_version_info("foo-bar", "1.0.0").unwrap().unwrap().download_count, 2);
+        assert_eq!(version_manager.get_latest_version("foo-bar").unwrap().as_deref(), Some("1.1.0"));
+        assert_eq!(version_manager.get_latest_version("foo_bar").unwrap(), None);
+
+        // 再次运行没有需要合并的包
+        assert!(dedupe_cache(&cache_manager, &version_manager).unwrap().merged.is_empty());
+    }
+}
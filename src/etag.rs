@@ -0,0 +1,110 @@
+//! 包文件响应的ETag：以sha256校验和作为强ETag，按RFC 9110处理 `If-None-Match`
+
+/// 把校验和格式化为带引号的强ETag，如 `"<sha256>"`
+pub fn from_checksum(checksum: &str) -> String {
+    format!("\"{}\"", checksum)
+}
+
+/// 一个实体标签
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityTag<'a> {
+    pub weak: bool,
+    /// 引号内的内容
+    pub tag: &'a str,
+}
+
+impl<'a> EntityTag<'a> {
+    /// 解析 `"xyz"` 或 `W/"xyz"`，格式不正确时返回None
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(Self { weak, tag })
+    }
+
+    /// 弱比较：忽略W/前缀，只比较引号内的内容
+    pub fn weak_eq(&self, other: &EntityTag<'_>) -> bool {
+        self.tag == other.tag
+    }
+}
+
+/// 拆分逗号分隔的ETag列表。引号内允许出现逗号，因此按引号而不是逗号切分，
+/// 遇到格式错误的部分时停止解析
+fn parse_list(value: &str) -> Vec<EntityTag<'_>> {
+    let mut tags = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            break;
+        }
+        let start = if rest.starts_with("W/\"") { 3 } else if rest.starts_with('"') { 1 } else { break };
+        let Some(len) = rest[start..].find('"') else { break };
+        let end = start + len + 1;
+        match EntityTag::parse(&rest[..end]) {
+            Some(tag) => tags.push(tag),
+            None => break,
+        }
+        rest = &rest[end..];
+    }
+    tags
+}
+
+/// `If-None-Match` 是否与当前ETag匹配（匹配时应返回304）。
+/// 按RFC 9110使用弱比较，`*` 匹配任何存在的资源
+pub fn if_none_match(header: &str, etag: &str) -> bool {
+    let Some(current) = EntityTag::parse(etag) else {
+        return false;
+    };
+    if header.trim() == "*" {
+        return true;
+    }
+    parse_list(header).iter().any(|tag| tag.weak_eq(&current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUM: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_etag_is_quoted() {
+        let etag = from_checksum(CHECKSUM);
+        assert_eq!(etag, format!("\"{}\"", CHECKSUM));
+        assert_eq!(EntityTag::parse(&etag), Some(EntityTag { weak: false, tag: CHECKSUM }));
+    }
+
+    #[test]
+    fn test_if_none_match_quoted_strong_match() {
+        let etag = from_checksum(CHECKSUM);
+        assert!(if_none_match(&format!("\"{}\"", CHECKSUM), &etag));
+        assert!(if_none_match(&format!("\"other\", \"{}\"", CHECKSUM), &etag));
+        // 未加引号的值不是合法的ETag
+        assert!(!if_none_match(CHECKSUM, &etag));
+        assert!(!if_none_match("\"other\"", &etag));
+    }
+
+    #[test]
+    fn test_if_none_match_weak_match() {
+        let etag = from_checksum(CHECKSUM);
+        assert!(if_none_match(&format!("W/\"{}\"", CHECKSUM), &etag));
+        assert!(if_none_match(&format!("W/\"a,b\",W/\"{}\"", CHECKSUM), &etag));
+
+        let weak = EntityTag::parse("W/\"v1\"").unwrap();
+        let strong = EntityTag::parse("\"v1\"").unwrap();
+        assert!(weak.weak_eq(&strong));
+    }
+
+    #[test]
+    fn test_if_none_match_wildcard() {
+        assert!(if_none_match("*", &from_checksum(CHECKSUM)));
+        assert!(if_none_match(" * ", &from_checksum(CHECKSUM)));
+    }
+}
@@ -0,0 +1,379 @@
+//! 缓存导出/导入：把缓存文件和版本数据库打包为tar归档，用于迁移或离线分发
+//!
+//! 归档依次包含 `manifest.json`、压缩后的版本数据库导出和 `cache/{crate}/{version}/{file}`，
+//! 清单记录数据库部分的压缩格式和sha256，导入时先校验再写入。
+
+use crate::cache::{CacheError, CacheManager};
+use crate::checksum::sha256_hex;
+use crate::clock;
+use crate::version_manager::{VersionDbDump, VersionManager, VersionManagerError};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// 归档格式版本
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const CACHE_DIR: &str = "cache";
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("IO错误: {0}")]
+    IoError(#[from] io::Error),
+    #[error("JSON错误: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("版本管理错误: {0}")]
+    VersionManagerError(#[from] VersionManagerError),
+    #[error("缓存错误: {0}")]
+    CacheError(#[from] CacheError),
+    #[error("版本数据库校验和不匹配: 期望 {0}，实际 {1}")]
+    ChecksumMismatch(String, String),
+    #[error("无效的导出文件: {0}")]
+    InvalidArchive(String),
+}
+
+/// 版本数据库部分的压缩方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DbCompression {
+    None,
+    Gzip,
+    #[default]
+    Zstd,
+}
+
+impl DbCompression {
+    fn file_name(self) -> &'static str {
+        match self {
+            DbCompression::None => "versions_db.json",
+            DbCompression::Gzip => "versions_db.json.gz",
+            DbCompression::Zstd => "versions_db.json.zst",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            DbCompression::None => Ok(data.to_vec()),
+            DbCompression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            DbCompression::Zstd => zstd::encode_all(data, 0),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            DbCompression::None => Ok(data.to_vec()),
+            DbCompression::Gzip => {
+                let mut decoded = Vec::new();
+                flate2::read::GzDecoder::new(data).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            DbCompression::Zstd => zstd::decode_all(data),
+        }
+    }
+}
+
+/// 导出清单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub format_version: u32,
+    /// 导出时间（Unix秒）
+    pub created_at: u64,
+    pub db: DbSection,
+    /// 缓存文件数量
+    pub cache_files: usize,
+}
+
+/// 清单中的版本数据库部分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbSection {
+    /// 归档中的文件名
+    pub file: String,
+    pub compression: DbCompression,
+    /// 压缩后数据的sha256
+    pub sha256: String,
+    /// 版本信息条数
+    pub versions: usize,
+    /// 最新版本映射条数
+    pub latest: usize,
+}
+
+/// 导出或导入的结果
+#[derive(Debug, Clone, Default)]
+pub struct ExportReport {
+    pub versions: usize,
+    pub latest: usize,
+    pub cache_files: usize,
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(clock::unix_secs(SystemTime::now()));
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+/// 导出缓存文件和版本数据库
+pub fn export_cache(
+    cache_manager: &CacheManager,
+    version_manager: &VersionManager,
+    output: &Path,
+    compression: DbCompression,
+) -> Result<ExportReport, ExportError> {
+    let dump = version_manager.dump()?;
+    let db_data = compression.compress(&serde_json::to_vec(&dump)?)?;
+    let cache_files = cache_manager.list_cache_files()?;
+
+    let manifest = ExportManifest {
+        format_version: FORMAT_VERSION,
+        created_at: clock::unix_secs(SystemTime::now()),
+        db: DbSection {
+            file: compression.file_name().to_string(),
+            compression,
+            sha256: sha256_hex(&db_data),
+            versions: dump.versions.len(),
+            latest: dump.latest.len(),
+        },
+        cache_files: cache_files.len(),
+    };
+
+    let mut builder = tar::Builder::new(File::create(output)?);
+    append_bytes(&mut builder, MANIFEST_FILE, &serde_json::to_vec_pretty(&manifest)?)?;
+    append_bytes(&mut builder, &manifest.db.file, &db_data)?;
+    for (relative, path) in &cache_files {
+        builder.append_path_with_name(path, Path::new(CACHE_DIR).join(relative))?;
+    }
+    builder.into_inner()?.sync_all()?;
+
+    rat_logger::info!(
+        "导出完成: {} 条版本信息，{} 条最新版本映射，{} 个缓存文件，数据库部分 {} 字节（{:?}）",
+        manifest.db.versions,
+        manifest.db.latest,
+        manifest.cache_files,
+        db_data.len(),
+        compression
+    );
+
+    Ok(ExportReport {
+        versions: manifest.db.versions,
+        latest: manifest.db.latest,
+        cache_files: manifest.cache_files,
+    })
+}
+
+/// 读取归档中的一个条目
+fn read_entry<R: Read>(entry: &mut tar::Entry<R>) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// 把归档中的 `cache/{crate}/{version}/{file}` 拆成三段，拒绝其他形式的路径
+fn cache_entry_parts(path: &Path) -> Option<(String, String, String)> {
+    let relative = path.strip_prefix(CACHE_DIR).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str().map(str::to_string),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    match <[String; 3]>::try_from(parts) {
+        Ok([crate_name, version, file]) => Some((crate_name, version, file)),
+        Err(_) => None,
+    }
+}
+
+/// 导入导出的归档。版本数据库部分在写入前校验sha256和条数，校验失败时不做任何修改
+pub fn import_cache(
+    cache_manager: &CacheManager,
+    version_manager: &VersionManager,
+    input: &Path,
+) -> Result<ExportReport, ExportError> {
+    let mut archive = tar::Archive::new(File::open(input)?);
+    let mut entries = archive.entries()?;
+
+    let mut next_entry = |expected: &str| -> Result<(PathBuf, Vec<u8>), ExportError> {
+        let mut entry = entries
+            .next()
+            .ok_or_else(|| ExportError::InvalidArchive(format!("缺少 {}", expected)))??;
+        let path = entry.path()?.into_owned();
+        Ok((path, read_entry(&mut entry)?))
+    };
+
+    let (path, data) = next_entry(MANIFEST_FILE)?;
+    if path != Path::new(MANIFEST_FILE) {
+        return Err(ExportError::InvalidArchive(format!("第一个条目应为 {}，实际为 {:?}", MANIFEST_FILE, path)));
+    }
+    let manifest: ExportManifest = serde_json::from_slice(&data)?;
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(ExportError::InvalidArchive(format!("不支持的格式版本 {}", manifest.format_version)));
+    }
+
+    let (path, db_data) = next_entry(&manifest.db.file)?;
+    if path != Path::new(&manifest.db.file) {
+        return Err(ExportError::InvalidArchive(format!("第二个条目应为 {}，实际为 {:?}", manifest.db.file, path)));
+    }
+
+    let actual = sha256_hex(&db_data);
+    if !actual.eq_ignore_ascii_case(&manifest.db.sha256) {
+        return Err(ExportError::ChecksumMismatch(manifest.db.sha256, actual));
+    }
+
+    let dump: VersionDbDump = serde_json::from_slice(&manifest.db.compression.decompress(&db_data)?)?;
+    if dump.versions.len() != manifest.db.versions || dump.latest.len() != manifest.db.latest {
+        return Err(ExportError::InvalidArchive(format!(
+            "版本数据库条数与清单不一致: {}/{}，清单 {}/{}",
+            dump.versions.len(),
+            dump.latest.len(),
+            manifest.db.versions,
+            manifest.db.latest
+        )));
+    }
+
+    version_manager.restore(&dump)?;
+    version_manager.flush()?;
+
+    let mut cache_files = 0;
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some((crate_name, version, file)) = cache_entry_parts(&path) else {
+            rat_logger::warn!("跳过无法识别的归档条目: {:?}", path);
+            continue;
+        };
+
+        let target = cache_manager.get_cache_path(&crate_name, &version, &file);
+        let partial = crate::crates_api::partial_path(&target);
+        io::copy(&mut entry, &mut File::create(&partial)?)?;
+        fs::rename(&partial, &target)?;
+        cache_files += 1;
+    }
+
+    rat_logger::info!(
+        "导入完成: {} 条版本信息，{} 条最新版本映射，{} 个缓存文件",
+        dump.versions.len(),
+        dump.latest.len(),
+        cache_files
+    );
+
+    Ok(ExportReport {
+        versions: dump.versions.len(),
+        latest: dump.latest.len(),
+        cache_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::fake_crate_bytes;
+    use tempfile::tempdir;
+
+    fn open_instance(dir: &Path) -> (CacheManager, VersionManager) {
+        let mut config = Config::default();
+        config.cache.storage_path = dir.display().to_string();
+        (CacheManager::from_config(&config).unwrap(), VersionManager::new(&config).unwrap())
+    }
+
+    #[test]
+    fn test_compressed_export_restores_fresh_instance() {
+        let source_dir = tempdir().unwrap();
+        let (source_cache, source_versions) = open_instance(source_dir.path());
+
+        for crate_name in ["serde", "tokio"] {
+            let infos: Vec<_> = (0..50)
+                .map(|i| {
+                    let version = format!("1.0.{}", i);
+                    source_versions
+                        .build_version_info(&version, &format!("/dl/{}", version), &format!("sum-{}", i), i % 7 == 0)
+                        .unwrap()
+                })
+                .collect();
+            source_versions.set_version_infos(crate_name, &infos).unwrap();
+            source_versions.set_latest_version(crate_name, "1.0.49").unwrap();
+        }
+        source_cache
+            .save_to_cache("serde", "1.0.49", "serde-1.0.49.crate", &fake_crate_bytes("serde"))
+            .unwrap();
+
+        let archive = source_dir.path().join("export.tar");
+        for compression in [DbCompression::Zstd, DbCompression::Gzip] {
+            let report = export_cache(&source_cache, &source_versions, &archive, compression).unwrap();
+            assert_eq!((report.versions, report.latest, report.cache_files), (100, 2, 1));
+
+            let target_dir = tempdir().unwrap();
+            let (target_cache, target_versions) = open_instance(target_dir.path());
+            let report = import_cache(&target_cache, &target_versions, &archive).unwrap();
+            assert_eq!((report.versions, report.latest, report.cache_files), (100, 2, 1));
+
+            let mut expected = source_versions.dump().unwrap();
+            let mut restored = target_versions.dump().unwrap();
+            expected.versions.sort_by_key(|r| (r.crate_name.clone(), r.info.version.clone()));
+            restored.versions.sort_by_key(|r| (r.crate_name.clone(), r.info.version.clone()));
+            assert_eq!(restored, expected);
+
+            assert_eq!(target_versions.get_latest_version("tokio").unwrap().as_deref(), Some("1.0.49"));
+            assert_eq!(
+                target_cache.get_cached_content("serde", "1.0.49", "serde-1.0.49.crate").unwrap(),
+                fake_crate_bytes("serde")
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_corrupted_db_section() {
+        let source_dir = tempdir().unwrap();
+        let (source_cache, source_versions) = open_instance(source_dir.path());
+        source_versions.create_version_info("serde", "1.0.0", "/dl", "abc", false).unwrap();
+
+        let archive = source_dir.path().join("export.tar");
+        export_cache(&source_cache, &source_versions, &archive, DbCompression::Zstd).unwrap();
+
+        // 篡改清单中记录的校验和
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut original = tar::Archive::new(File::open(&archive).unwrap());
+        for entry in original.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().display().to_string();
+            let mut data = read_entry(&mut entry).unwrap();
+            if path == MANIFEST_FILE {
+                let mut manifest: ExportManifest = serde_json::from_slice(&data).unwrap();
+                manifest.db.sha256 = "0".repeat(64);
+                data = serde_json::to_vec(&manifest).unwrap();
+            }
+            append_bytes(&mut builder, &path, &data).unwrap();
+        }
+        let tampered = source_dir.path().join("tampered.tar");
+        fs::write(&tampered, builder.into_inner().unwrap()).unwrap();
+
+        let target_dir = tempdir().unwrap();
+        let (target_cache, target_versions) = open_instance(target_dir.path());
+        assert!(matches!(
+            import_cache(&target_cache, &target_versions, &tampered),
+            Err(ExportError::ChecksumMismatch(_, _))
+        ));
+        assert!(target_versions.dump().unwrap().versions.is_empty());
+    }
+
+    #[test]
+    fn test_cache_entry_parts_rejects_traversal() {
+        assert_eq!(
+            cache_entry_parts(Path::new("cache/serde/1.0.0/serde-1.0.0.crate")),
+            Some(("serde".into(), "1.0.0".into(), "serde-1.0.0.crate".into()))
+        );
+        assert_eq!(cache_entry_parts(Path::new("cache/../etc/passwd")), None);
+        assert_eq!(cache_entry_parts(Path::new("cache/serde/serde.crate")), None);
+    }
+}
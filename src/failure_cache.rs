@@ -0,0 +1,136 @@
+//! 下载失败缓存：同一版本连续下载失败达到阈值后，在冷却时间内直接返回上一次的错误，
+//! 避免对上游永久拒绝的版本（如所有镜像都返回403）反复走完整的重试和回退流程
+
+use hyper::StatusCode;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// 记录的版本数上限，超过时丢弃未处于冷却中的记录
+const MAX_ENTRIES: usize = 10_000;
+
+/// 冷却中直接返回的错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedFailure {
+    pub status: StatusCode,
+    pub message: String,
+    /// 剩余的冷却时间
+    pub remaining: Duration,
+}
+
+pub struct FailureCache {
+    /// 开始冷却的连续失败次数，0表示不启用
+    threshold: u32,
+    cooldown: Duration,
+    entries: Mutex<HashMap<String, FailureEntry>>,
+}
+
+struct FailureEntry {
+    consecutive_failures: u32,
+    status: StatusCode,
+    message: String,
+    /// 冷却中时为恢复下载的时间
+    blocked_until: Option<Instant>,
+}
+
+impl FailureCache {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 该版本处于冷却中时返回上一次的错误，冷却结束后放行下载，再次连续失败达到阈值时重新冷却
+    pub fn check(&self, crate_name: &str, version: &str) -> Option<CachedFailure> {
+        if self.threshold == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = failure_key(crate_name, version);
+        let entry = entries.get(&key)?;
+        let blocked_until = entry.blocked_until?;
+        let now = Instant::now();
+        if now < blocked_until {
+            return Some(CachedFailure {
+                status: entry.status,
+                message: entry.message.clone(),
+                remaining: blocked_until - now,
+            });
+        }
+        entries.remove(&key);
+        None
+    }
+
+    pub fn record_success(&self, crate_name: &str, version: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&failure_key(crate_name, version));
+    }
+
+    pub fn record_failure(&self, crate_name: &str, version: &str, status: StatusCode, message: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.blocked_until.is_some());
+        }
+        let entry = entries.entry(failure_key(crate_name, version)).or_insert_with(|| FailureEntry {
+            consecutive_failures: 0,
+            status,
+            message: String::new(),
+            blocked_until: None,
+        });
+        entry.consecutive_failures += 1;
+        entry.status = status;
+        entry.message = message.to_string();
+        if entry.consecutive_failures >= self.threshold {
+            rat_logger::warn!(
+                "{}-{} 连续下载失败 {} 次，{:?} 内直接返回上一次的错误",
+                crate_name,
+                version,
+                entry.consecutive_failures,
+                self.cooldown
+            );
+            entry.consecutive_failures = 0;
+            entry.blocked_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+fn failure_key(crate_name: &str, version: &str) -> String {
+    format!("{}:{}", crate_name, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_after_threshold_until_cooldown_ends() {
+        let cache = FailureCache::new(2, Duration::from_millis(50));
+        cache.record_failure("foo", "1.0.0", StatusCode::BAD_GATEWAY, "403");
+        assert!(cache.check("foo", "1.0.0").is_none());
+
+        cache.record_failure("foo", "1.0.0", StatusCode::BAD_GATEWAY, "403");
+        let failure = cache.check("foo", "1.0.0").unwrap();
+        assert_eq!(failure.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(failure.message, "403");
+        assert!(cache.check("foo", "1.1.0").is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.check("foo", "1.0.0").is_none());
+
+        // 成功后重新计数
+        cache.record_failure("foo", "1.0.0", StatusCode::BAD_GATEWAY, "403");
+        cache.record_success("foo", "1.0.0");
+        cache.record_failure("foo", "1.0.0", StatusCode::BAD_GATEWAY, "403");
+        assert!(cache.check("foo", "1.0.0").is_none());
+    }
+}
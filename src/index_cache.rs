@@ -0,0 +1,342 @@
+//! 稀疏索引缓存：保存上游索引文件及其 ETag / Last-Modified，过期后通过条件请求重新验证。
+//! 转发的包子接口（如 `owners`）的响应使用同样的方式缓存在单独的目录中。
+//! 条目可以gzip压缩保存，存储格式按文件开头的魔数识别，与返回给客户端的编码无关
+
+use crate::clock;
+use crate::index_snapshot::index_relative_path;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// 索引缓存在 `storage_path` 下的目录名
+pub const INDEX_CACHE_DIR: &str = "index_cache";
+
+/// 包子接口转发缓存在 `storage_path` 下的目录名
+pub const API_CACHE_DIR: &str = "api_cache";
+
+/// 元数据文件后缀，包名不允许包含 `.`，不会与索引文件冲突
+const META_SUFFIX: &str = ".meta";
+
+/// 写入中的临时文件后缀
+const PART_SUFFIX: &str = ".part";
+
+/// gzip数据的魔数，索引文件和接口响应都是JSON，不会以此开头
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Error)]
+pub enum IndexCacheError {
+    #[error("IO错误: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("元数据错误: {0}")]
+    MetadataError(#[from] serde_json::Error),
+    #[error("无效的索引路径: {0}")]
+    InvalidPath(String),
+}
+
+/// 索引文件的缓存元数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntryMeta {
+    /// 上游返回的ETag
+    pub etag: Option<String>,
+    /// 上游返回的Last-Modified
+    pub last_modified: Option<String>,
+    /// 最近一次从上游获取或验证的时间（Unix秒）
+    pub fetched_at: u64,
+    /// 过期时间（Unix秒）
+    pub expires_at: u64,
+}
+
+pub struct IndexCache {
+    root: PathBuf,
+    ttl: AtomicU64,
+    clock_skew_tolerance: u64,
+    /// 新写入的条目是否gzip压缩保存，已有条目按各自的格式读取
+    gzip: AtomicBool,
+    /// 相对路径检查，决定哪些路径可以缓存
+    validate_path: fn(&str) -> Result<(), IndexCacheError>,
+}
+
+/// 检查索引相对路径：只允许由包名字符组成的路径段，防止路径穿越；除 `config.json` 外必须是包在稀疏索引中的
+/// 规范路径（`1/a`、`2/ab`、`3/a/abc`、`ab/cd/abcd...`），缓存以该路径为键，共享前缀目录的包互不影响，
+/// 前缀目录本身或前缀与包名不符的路径不会被缓存
+pub fn validate_index_path(rel_path: &str) -> Result<(), IndexCacheError> {
+    let valid = !rel_path.is_empty()
+        && rel_path.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && !segment.ends_with(META_SUFFIX)
+                && !segment.ends_with(PART_SUFFIX)
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        && (rel_path == "config.json" || is_sparse_crate_path(rel_path));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(IndexCacheError::InvalidPath(rel_path.to_string()))
+    }
+}
+
+/// 检查包子接口缓存的相对路径：必须是 `{crate}/{endpoint}` 两段，只包含包名字符，不含 `.`
+pub fn validate_api_path(rel_path: &str) -> Result<(), IndexCacheError> {
+    let segments: Vec<&str> = rel_path.split('/').collect();
+    let valid = segments.len() == 2
+        && segments.iter().all(|segment| {
+            !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(IndexCacheError::InvalidPath(rel_path.to_string()))
+    }
+}
+
+/// 路径是否与其最后一段包名在稀疏索引中的路径完全一致
+fn is_sparse_crate_path(rel_path: &str) -> bool {
+    let crate_name = rel_path.rsplit('/').next().unwrap_or_default();
+    index_relative_path(crate_name).is_some_and(|expected| expected == Path::new(rel_path))
+}
+
+impl IndexCache {
+    pub fn new<P: AsRef<Path>>(storage_path: P, ttl: u64, clock_skew_tolerance: u64) -> Self {
+        Self {
+            root: storage_path.as_ref().join(INDEX_CACHE_DIR),
+            ttl: AtomicU64::new(ttl),
+            clock_skew_tolerance,
+            gzip: AtomicBool::new(false),
+            validate_path: validate_index_path,
+        }
+    }
+
+    /// 包子接口响应的缓存，路径为 `{crate}/{endpoint}`
+    pub fn for_api<P: AsRef<Path>>(storage_path: P, ttl: u64, clock_skew_tolerance: u64) -> Self {
+        Self {
+            root: storage_path.as_ref().join(API_CACHE_DIR),
+            ttl: AtomicU64::new(ttl),
+            clock_skew_tolerance,
+            gzip: AtomicBool::new(false),
+            validate_path: validate_api_path,
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: u64) {
+        self.ttl.store(ttl, Ordering::Relaxed);
+    }
+
+    /// 新写入的条目gzip压缩保存
+    pub fn with_gzip(self, gzip: bool) -> Self {
+        self.set_gzip(gzip);
+        self
+    }
+
+    pub fn set_gzip(&self, gzip: bool) {
+        self.gzip.store(gzip, Ordering::Relaxed);
+    }
+
+    fn body_path(&self, rel_path: &str) -> Result<PathBuf, IndexCacheError> {
+        (self.validate_path)(rel_path)?;
+        Ok(self.root.join(rel_path))
+    }
+
+    fn meta_path(&self, rel_path: &str) -> Result<PathBuf, IndexCacheError> {
+        (self.validate_path)(rel_path)?;
+        Ok(self.root.join(format!("{}{}", rel_path, META_SUFFIX)))
+    }
+
+    /// 读取缓存索引文件的元数据，索引文件或元数据任一缺失时返回None
+    pub fn get(&self, rel_path: &str) -> Result<Option<IndexEntryMeta>, IndexCacheError> {
+        let body_path = self.body_path(rel_path)?;
+        let meta_path = self.meta_path(rel_path)?;
+        if !body_path.exists() || !meta_path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs::read(&meta_path)?)?))
+    }
+
+    /// 读取完整的索引文件内容，压缩保存的条目解压后返回
+    pub fn read_body(&self, rel_path: &str) -> Result<Vec<u8>, IndexCacheError> {
+        let data = fs::read(self.body_path(rel_path)?)?;
+        if !data.starts_with(&GZIP_MAGIC) {
+            return Ok(data);
+        }
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(data.as_slice()).read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    /// 打开索引文件用于分块读取，同时返回文件大小和是否gzip压缩保存
+    pub fn open_body(&self, rel_path: &str) -> Result<(fs::File, u64, bool), IndexCacheError> {
+        let mut file = fs::File::open(self.body_path(rel_path)?)?;
+        let len = file.metadata()?.len();
+        let mut magic = [0u8; 2];
+        let gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(0))?;
+        Ok((file, len, gzip))
+    }
+
+    /// 保存上游返回的索引文件，并以当前时间开始计算TTL
+    pub fn store(
+        &self,
+        rel_path: &str,
+        body: &[u8],
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Result<IndexEntryMeta, IndexCacheError> {
+        let body_path = self.body_path(rel_path)?;
+        if let Some(parent) = body_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // 先写临时文件再重命名，避免并发读取到写了一半的文件
+        let temp_path = self.root.join(format!("{}{}", rel_path, PART_SUFFIX));
+        if self.gzip.load(Ordering::Relaxed) {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body)?;
+            fs::write(&temp_path, encoder.finish()?)?;
+        } else {
+            fs::write(&temp_path, body)?;
+        }
+        fs::rename(&temp_path, &body_path)?;
+
+        let meta = self.new_meta(etag, last_modified);
+        self.save_meta(rel_path, &meta)?;
+        Ok(meta)
+    }
+
+    /// 上游返回304时刷新过期时间；响应中带有新的验证头时一并更新
+    pub fn refresh(
+        &self,
+        rel_path: &str,
+        previous: &IndexEntryMeta,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Result<IndexEntryMeta, IndexCacheError> {
+        let meta = self.new_meta(
+            etag.or_else(|| previous.etag.clone()),
+            last_modified.or_else(|| previous.last_modified.clone()),
+        );
+        self.save_meta(rel_path, &meta)?;
+        Ok(meta)
+    }
+
+    pub fn save_meta(&self, rel_path: &str, meta: &IndexEntryMeta) -> Result<(), IndexCacheError> {
+        let meta_path = self.meta_path(rel_path)?;
+        if let Some(parent) = meta_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(meta_path, serde_json::to_vec(meta)?)?;
+        Ok(())
+    }
+
+    /// 删除上游已不存在的索引文件
+    pub fn remove(&self, rel_path: &str) -> Result<(), IndexCacheError> {
+        for path in [self.body_path(rel_path)?, self.meta_path(rel_path)?] {
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_expired(&self, meta: &IndexEntryMeta) -> bool {
+        let now = clock::unix_secs(SystemTime::now());
+        clock::is_expired(now, meta.fetched_at, meta.expires_at, self.clock_skew_tolerance)
+    }
+
+    fn new_meta(&self, etag: Option<String>, last_modified: Option<String>) -> IndexEntryMeta {
+        let now = clock::unix_secs(SystemTime::now());
+        IndexEntryMeta {
+            etag,
+            last_modified,
+            fetched_at: now,
+            expires_at: now.saturating_add(self.ttl.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_validate_index_path() {
+        assert!(validate_index_path("config.json").is_ok());
+        assert!(validate_index_path("se/rd/serde").is_ok());
+        assert!(validate_index_path("3/s/syn").is_ok());
+
+        assert!(validate_index_path("").is_err());
+        assert!(validate_index_path("../etc/passwd").is_err());
+        assert!(validate_index_path("se//serde").is_err());
+        assert!(validate_index_path("se/rd/serde.meta").is_err());
+
+        // 只接受包的规范稀疏路径
+        assert!(validate_index_path("1/a").is_ok());
+        assert!(validate_index_path("2/ab").is_ok());
+        assert!(validate_index_path("se/rd").is_err());
+        assert!(validate_index_path("se/rd/tokio").is_err());
+        assert!(validate_index_path("fo/o/foo").is_err());
+        assert!(validate_index_path("se/rd/Serde").is_err());
+    }
+
+    #[test]
+    fn test_store_and_refresh() {
+        let dir = tempdir().unwrap();
+        let cache = IndexCache::new(dir.path(), 60, 0);
+        assert!(cache.get("se/rd/serde").unwrap().is_none());
+
+        let stored = cache
+            .store("se/rd/serde", b"{\"name\":\"serde\"}\n", Some("\"v1\"".to_string()), None)
+            .unwrap();
+        assert_eq!(stored.expires_at, stored.fetched_at + 60);
+        assert!(!cache.is_expired(&stored));
+
+        let meta = cache.get("se/rd/serde").unwrap().unwrap();
+        assert_eq!(meta, stored);
+        assert_eq!(cache.read_body("se/rd/serde").unwrap(), b"{\"name\":\"serde\"}\n");
+        assert_eq!(cache.open_body("se/rd/serde").unwrap().1, 17);
+
+        // 304未带验证头时沿用原有的ETag
+        let refreshed = cache
+            .refresh("se/rd/serde", &meta, None, Some("Wed, 01 Jan 2025 00:00:00 GMT".to_string()))
+            .unwrap();
+        assert_eq!(refreshed.etag.as_deref(), Some("\"v1\""));
+        assert!(refreshed.last_modified.is_some());
+
+        cache.remove("se/rd/serde").unwrap();
+        assert!(cache.get("se/rd/serde").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_gzip_storage_is_transparent_to_readers() {
+        let dir = tempdir().unwrap();
+        let cache = IndexCache::new(dir.path(), 60, 0);
+        let body = "{\"name\":\"serde\"}\n".repeat(100);
+        cache.store("se/rd/serde", body.as_bytes(), None, None).unwrap();
+
+        // 开启压缩后新写入的条目压缩保存，之前的条目仍可读取
+        cache.set_gzip(true);
+        cache.store("3/s/syn", body.as_bytes(), None, None).unwrap();
+
+        let (_, len, gzip) = cache.open_body("se/rd/serde").unwrap();
+        assert_eq!((len, gzip), (body.len() as u64, false));
+        let (_, len, gzip) = cache.open_body("3/s/syn").unwrap();
+        assert!(gzip && len < body.len() as u64);
+        assert!(fs::read(dir.path().join(INDEX_CACHE_DIR).join("3/s/syn")).unwrap().starts_with(&GZIP_MAGIC));
+
+        for rel_path in ["se/rd/serde", "3/s/syn"] {
+            assert_eq!(cache.read_body(rel_path).unwrap(), body.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! 本地crates.io索引快照：定期同步的索引目录或git检出，解析版本和校验和时优先于在线API查询
+//!
+//! 每次查询都直接读取快照中的文件，快照更新后无需重启即可生效。
+
+use crate::crates_api::CrateVersion;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 索引文件中的一行，只取解析版本需要的字段
+#[derive(Debug, Deserialize)]
+struct IndexLine {
+    vers: String,
+    cksum: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// 包在索引中的相对路径：`1/a`、`2/ab`、`3/a/abc`、`ab/cd/abcd...`，包名小写。
+/// 包名含有非法字符时返回None
+pub fn index_relative_path(crate_name: &str) -> Option<PathBuf> {
+    let name = crate_name.to_ascii_lowercase();
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+        return None;
+    }
+
+    let path = match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    };
+    Some(path)
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexSnapshot {
+    root: PathBuf,
+}
+
+impl IndexSnapshot {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// 读取包的全部版本，快照中没有该包时返回None。无法解析的行记录警告后跳过
+    pub fn versions(&self, crate_name: &str) -> io::Result<Option<Vec<CrateVersion>>> {
+        let Some(relative) = index_relative_path(crate_name) else {
+            return Ok(None);
+        };
+        let content = match fs::read_to_string(self.root.join(relative)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut versions = Vec::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<IndexLine>(line) {
+                Ok(entry) => versions.push(CrateVersion {
+                    dl_path: format!("/api/v1/crates/{}/{}/download", crate_name, entry.vers),
+                    num: entry.vers,
+                    checksum: entry.cksum,
+                    yanked: entry.yanked,
+                }),
+                Err(e) => rat_logger::warn!("跳过索引快照中无法解析的行 {}: {}", crate_name, e),
+            }
+        }
+        Ok(Some(versions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_index_relative_path() {
+        assert_eq!(index_relative_path("a"), Some(PathBuf::from("1/a")));
+        assert_eq!(index_relative_path("ab"), Some(PathBuf::from("2/ab")));
+        assert_eq!(index_relative_path("abc"), Some(PathBuf::from("3/a/abc")));
+        assert_eq!(index_relative_path("Serde"), Some(PathBuf::from("se/rd/serde")));
+        assert_eq!(index_relative_path("../etc"), None);
+    }
+
+    #[test]
+    fn test_versions_from_snapshot() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("3/f")).unwrap();
+        fs::write(
+            dir.path().join("3/f/foo"),
+            "{\"name\":\"foo\",\"vers\":\"1.0.0\",\"cksum\":\"aa\",\"yanked\":false}\nnot json\n{\"name\":\"foo\",\"vers\":\"1.1.0\",\"cksum\":\"bb\",\"yanked\":true}\n",
+        )
+        .unwrap();
+
+        let snapshot = IndexSnapshot::new(dir.path());
+        let versions = snapshot.versions("foo").unwrap().unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!((versions[0].num.as_str(), versions[0].checksum.as_str(), versions[0].yanked), ("1.0.0", "aa", false));
+        assert_eq!(versions[1].dl_path, "/api/v1/crates/foo/1.1.0/download");
+        assert!(versions[1].yanked);
+        assert!(snapshot.versions("bar").unwrap().is_none());
+    }
+}
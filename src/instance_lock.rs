@@ -0,0 +1,143 @@
+use crate::cache::VERSIONS_DB_DIR;
+use fs2::FileExt;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// 实例PID锁文件名，位于缓存目录下
+pub const INSTANCE_LOCK_FILE: &str = "crates_proxy.pid";
+
+#[derive(Debug, Error)]
+pub enum InstanceLockError {
+    #[error("IO错误: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("另一个实例正在运行 (PID {0})")]
+    AlreadyRunning(u32),
+}
+
+/// 当前进程持有的实例锁，释放时删除PID文件
+#[derive(Debug)]
+pub struct InstanceLock {
+    storage_path: PathBuf,
+    path: PathBuf,
+    /// 持有独占文件锁的PID文件，进程退出时由内核释放锁
+    file: fs::File,
+}
+
+impl InstanceLock {
+    /// 对PID文件加独占文件锁并写入当前进程PID，锁被其他实例持有时返回错误。
+    /// 加锁是原子的，两个实例同时启动时只有一个能成功
+    pub fn acquire<P: AsRef<Path>>(storage_path: P) -> Result<Self, InstanceLockError> {
+        let storage_path = storage_path.as_ref().to_path_buf();
+        fs::create_dir_all(&storage_path)?;
+        let path = storage_path.join(INSTANCE_LOCK_FILE);
+
+        loop {
+            let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            if let Err(e) = file.try_lock_exclusive() {
+                if e.kind() != std::io::ErrorKind::WouldBlock {
+                    return Err(e.into());
+                }
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                return Err(InstanceLockError::AlreadyRunning(content.trim().parse().unwrap_or(0)));
+            }
+
+            // 上一个实例退出时先删除文件再释放锁，加锁成功的可能是已被删除的旧文件，重新打开
+            let locked = file.metadata()?;
+            match fs::metadata(&path) {
+                Ok(current) if current.dev() == locked.dev() && current.ino() == locked.ino() => {}
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            file.set_len(0)?;
+            file.write_all(std::process::id().to_string().as_bytes())?;
+            return Ok(Self { storage_path, path, file });
+        }
+    }
+
+    /// 清理melange_db残留的锁文件。持有实例锁说明没有其他实例在使用数据库，残留的锁文件都来自异常退出的进程
+    pub fn cleanup_db_locks(&self) -> std::io::Result<usize> {
+        let count = melange_db::cleanup_lock_files(self.storage_path.join(VERSIONS_DB_DIR))?;
+        if count > 0 {
+            rat_logger::info!("已清理 {} 个melange_db残留锁文件", count);
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for InstanceLock {
+    /// 先删除文件再释放锁，等待中的实例加锁后会发现文件已被删除
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            rat_logger::warn!("删除实例锁文件失败 {:?}: {}", self.path, e);
+        }
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn seed_db_lock(storage_path: &Path) -> PathBuf {
+        let db_lock = storage_path.join(VERSIONS_DB_DIR).join(".lock");
+        fs::create_dir_all(db_lock.parent().unwrap()).unwrap();
+        fs::write(&db_lock, "").unwrap();
+        db_lock
+    }
+
+    /// 获取一个已经退出的进程的PID
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    #[test]
+    fn test_db_locks_are_cleaned_while_holding_lock() {
+        let dir = tempdir().unwrap();
+        let db_lock = seed_db_lock(dir.path());
+        // 异常退出的实例留下的PID文件没有文件锁
+        fs::write(dir.path().join(INSTANCE_LOCK_FILE), dead_pid().to_string()).unwrap();
+
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        assert_eq!(lock.cleanup_db_locks().unwrap(), 1);
+        assert!(!db_lock.exists());
+        assert_eq!(fs::read_to_string(dir.path().join(INSTANCE_LOCK_FILE)).unwrap(), std::process::id().to_string());
+    }
+
+    #[test]
+    fn test_instance_lock_released_on_drop() {
+        let dir = tempdir().unwrap();
+        let lock_path = dir.path().join(INSTANCE_LOCK_FILE);
+
+        {
+            let _lock = InstanceLock::acquire(dir.path()).unwrap();
+            assert!(lock_path.exists());
+        }
+
+        assert!(!lock_path.exists());
+        let _lock = InstanceLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn test_second_instance_is_rejected() {
+        let dir = tempdir().unwrap();
+        let db_lock = seed_db_lock(dir.path());
+        let _lock = InstanceLock::acquire(dir.path()).unwrap();
+
+        // 第二个实例拿不到锁，也就不会清理数据库锁文件
+        match InstanceLock::acquire(dir.path()) {
+            Err(InstanceLockError::AlreadyRunning(pid)) => assert_eq!(pid, std::process::id()),
+            other => panic!("第二个实例不应获得锁: {:?}", other),
+        }
+        assert!(db_lock.exists());
+    }
+}
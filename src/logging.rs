@@ -0,0 +1,140 @@
+use crate::config::LoggingConfig;
+use rat_logger::core::SetLoggerError;
+use rat_logger::producer_consumer::BatchConfig;
+use rat_logger::{FileConfig, FormatConfig, LevelFilter};
+use std::path::PathBuf;
+
+/// 文件日志输出配置，轮转参数来自 `[logging]`
+pub fn file_config(config: &LoggingConfig) -> FileConfig {
+    // 文件输出始终使用简洁格式
+    let file_format = FormatConfig {
+        timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
+        level_style: rat_logger::LevelStyle {
+            error: "ERROR".to_string(),
+            warn: "WARN ".to_string(),
+            info: "INFO ".to_string(),
+            debug: "DEBUG".to_string(),
+            trace: "TRACE".to_string(),
+        },
+        format_template: "{timestamp} [{level}] {message}".to_string(),
+    };
+
+    FileConfig {
+        log_dir: PathBuf::from("./logs"),
+        max_file_size: config.max_file_size,
+        max_compressed_files: config.max_compressed_files,
+        compression_level: config.compression_level,
+        min_compress_threads: 1,
+        skip_server_logs: false,
+        is_raw: false,
+        compress_on_drop: true,
+        format: Some(file_format),
+    }
+}
+
+/// 初始化全局日志器，重复调用时会替换已有的日志器（用于运行时调整日志级别和轮转参数）
+pub fn setup_logging(config: &LoggingConfig) -> Result<(), SetLoggerError> {
+    // 转换日志级别
+    let log_level = match config.level.as_str() {
+        "error" => LevelFilter::Error,
+        "warn" => LevelFilter::Warn,
+        "info" => LevelFilter::Info,
+        "debug" => LevelFilter::Debug,
+        "trace" => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    };
+
+    // 根据日志级别决定是否启用开发模式
+    let dev_mode = matches!(log_level, LevelFilter::Debug | LevelFilter::Trace);
+
+    // 小负载服务器的文件日志配置：在性能和可靠性之间取得平衡
+    let mut builder = rat_logger::LoggerBuilder::new()
+        .add_file(file_config(config))
+        .with_batch_config(BatchConfig {
+            batch_size: 512,        // 512字节批量大小，适中的批量处理
+            batch_interval_ms: 10,  // 10ms刷新间隔，确保及时写入
+            buffer_size: 1024,      // 1KB缓冲区，足够的缓冲空间
+        })
+        .with_level(log_level);
+
+    // 根据是否为开发模式配置终端输出格式
+    if dev_mode {
+        // 开发模式：保留详细格式便于调试
+        let dev_format = FormatConfig {
+            timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
+            level_style: rat_logger::LevelStyle {
+                error: "ERROR".to_string(),
+                warn: "WARN ".to_string(),
+                info: "INFO ".to_string(),
+                debug: "DEBUG".to_string(),
+                trace: "TRACE".to_string(),
+            },
+            format_template: "{timestamp} [{level}] {target}:{line} - {message}".to_string(),
+        };
+
+        builder = builder
+            .add_terminal_with_config(rat_logger::handler::term::TermConfig {
+                enable_color: true,
+                enable_async: false, // 开发模式禁用异步确保立即输出
+                batch_size: 1,
+                flush_interval_ms: 1,
+                format: Some(dev_format),
+                color: None, // 使用默认颜色
+            })
+            .with_dev_mode(true); // 确保日志立即输出
+    } else {
+        // 生产模式：简洁格式，只显示时间、级别和消息
+        let prod_format = FormatConfig {
+            timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
+            level_style: rat_logger::LevelStyle {
+                error: "ERROR".to_string(),
+                warn: "WARN ".to_string(),
+                info: "INFO ".to_string(),
+                debug: "DEBUG".to_string(),
+                trace: "TRACE".to_string(),
+            },
+            format_template: "{timestamp} [{level}] {message}".to_string(),
+        };
+
+        builder = builder
+            .add_terminal_with_config(rat_logger::handler::term::TermConfig {
+                enable_color: true,
+                enable_async: false, // 改为同步输出确保日志立即显示
+                batch_size: 1,
+                flush_interval_ms: 1,
+                format: Some(prod_format),
+                color: None, // 使用默认颜色
+            });
+    }
+
+    builder.init_global_logger()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_settings_flow_into_file_config() {
+        let config: LoggingConfig = toml::from_str(
+            r#"
+            level = "warn"
+            max_file_size = 52428800
+            max_compressed_files = 30
+            compression_level = 9
+            "#,
+        )
+        .unwrap();
+
+        let custom = file_config(&config);
+        assert_eq!(custom.max_file_size, 50 * 1024 * 1024);
+        assert_eq!(custom.max_compressed_files, 30);
+        assert_eq!(custom.compression_level, 9);
+        assert!(custom.validate().is_ok());
+
+        // 未设置时保持原来的默认值
+        let defaults: LoggingConfig = toml::from_str("level = \"info\"").unwrap();
+        let defaults = file_config(&defaults);
+        assert_eq!((defaults.max_file_size, defaults.max_compressed_files, defaults.compression_level), (10 * 1024 * 1024, 5, 6));
+    }
+}
@@ -1,22 +1,45 @@
+mod audit;
+mod benchmark;
 mod cache;
+mod checksum;
+mod circuit_breaker;
+mod clock;
 mod config;
+mod connection_limiter;
+mod consistency;
+mod crate_limiter;
 mod crates_api;
 mod curl_client;
+mod dedupe;
+mod etag;
+mod export;
+mod failure_cache;
+mod index_cache;
+mod index_snapshot;
+mod instance_lock;
+mod logging;
+mod openapi;
 mod proxy;
+mod rebuild_index;
+mod self_test;
+mod single_flight;
+mod stats;
+mod throttle;
+#[cfg(test)]
+mod test_support;
 mod version_manager;
+mod warmup;
 
 use clap::Parser;
 use config::{Config, ConfigError};
 use proxy::run_server;
-use rat_logger::{self, LevelFilter, FileConfig, FormatConfig};
-use rat_logger::producer_consumer::BatchConfig;
-use std::process;
 use std::path::PathBuf;
+use std::process;
 
 #[derive(Parser)]
 #[command(name = "crates-proxy")]
 #[command(about = "Rust crates缓存代理服务器")]
-#[command(version = "0.1.0")]
+#[command(version = config::VERSION)]
 struct Args {
     #[arg(short = 'f', long, help = "配置文件路径")]
     config: Option<String>,
@@ -26,111 +49,39 @@ struct Args {
 
     #[arg(short, long, help = "显示缓存统计")]
     stats: bool,
-}
 
-fn setup_logging(level: &str) {
-    // 转换日志级别
-    let log_level = match level {
-        "error" => LevelFilter::Error,
-        "warn" => LevelFilter::Warn,
-        "info" => LevelFilter::Info,
-        "debug" => LevelFilter::Debug,
-        "trace" => LevelFilter::Trace,
-        _ => LevelFilter::Info,
-    };
+    #[arg(long, value_name = "N", help = "预热下载量最高的N个包的最新版本")]
+    warm_popular: Option<usize>,
 
-    // 根据日志级别决定是否启用开发模式
-    let dev_mode = matches!(log_level, LevelFilter::Debug | LevelFilter::Trace);
-
-    // 配置文件输出（始终使用简洁格式）
-    let file_format = FormatConfig {
-        timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
-        level_style: rat_logger::LevelStyle {
-            error: "ERROR".to_string(),
-            warn: "WARN ".to_string(),
-            info: "INFO ".to_string(),
-            debug: "DEBUG".to_string(),
-            trace: "TRACE".to_string(),
-        },
-        format_template: "{timestamp} [{level}] {message}".to_string(),
-    };
+    #[arg(long, help = "运行基准测试，统计缓存命中和未命中的延迟与吞吐量")]
+    benchmark: bool,
 
-    let file_config = FileConfig {
-        log_dir: PathBuf::from("./logs"),
-        max_file_size: 10 * 1024 * 1024, // 10MB
-        max_compressed_files: 5,
-        compression_level: 6,
-        min_compress_threads: 1,
-        skip_server_logs: false,
-        is_raw: false,
-        compress_on_drop: true,
-        format: Some(file_format),
-    };
+    #[arg(long, value_name = "N", default_value_t = 200, help = "基准测试每个阶段的请求数")]
+    bench_requests: usize,
 
-    // 小负载服务器的文件日志配置：在性能和可靠性之间取得平衡
-    let mut builder = rat_logger::LoggerBuilder::new()
-        .add_file(file_config)
-        .with_batch_config(BatchConfig {
-            batch_size: 512,        // 512字节批量大小，适中的批量处理
-            batch_interval_ms: 10,  // 10ms刷新间隔，确保及时写入
-            buffer_size: 1024,      // 1KB缓冲区，足够的缓冲空间
-        })
-        .with_level(log_level);
-
-    // 根据是否为开发模式配置终端输出格式
-    if dev_mode {
-        // 开发模式：保留详细格式便于调试
-        let dev_format = FormatConfig {
-            timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
-            level_style: rat_logger::LevelStyle {
-                error: "ERROR".to_string(),
-                warn: "WARN ".to_string(),
-                info: "INFO ".to_string(),
-                debug: "DEBUG".to_string(),
-                trace: "TRACE".to_string(),
-            },
-            format_template: "{timestamp} [{level}] {target}:{line} - {message}".to_string(),
-        };
+    #[arg(long, value_name = "N", default_value_t = 16, help = "基准测试的并发请求数")]
+    bench_concurrency: usize,
 
-        builder = builder
-            .add_terminal_with_config(rat_logger::handler::term::TermConfig {
-                enable_color: true,
-                enable_async: false, // 开发模式禁用异步确保立即输出
-                batch_size: 1,
-                flush_interval_ms: 1,
-                format: Some(dev_format),
-                color: None, // 使用默认颜色
-            })
-            .with_dev_mode(true); // 确保日志立即输出
-    } else {
-        // 生产模式：简洁格式，只显示时间、级别和消息
-        let prod_format = FormatConfig {
-            timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
-            level_style: rat_logger::LevelStyle {
-                error: "ERROR".to_string(),
-                warn: "WARN ".to_string(),
-                info: "INFO ".to_string(),
-                debug: "DEBUG".to_string(),
-                trace: "TRACE".to_string(),
-            },
-            format_template: "{timestamp} [{level}] {message}".to_string(),
-        };
+    #[arg(long, help = "自检：通过配置的上游下载一个小包，确认能缓存并从缓存返回，结果以退出码表示")]
+    self_test: bool,
 
-        builder = builder
-            .add_terminal_with_config(rat_logger::handler::term::TermConfig {
-                enable_color: true,
-                enable_async: false, // 改为同步输出确保日志立即显示
-                batch_size: 1,
-                flush_interval_ms: 1,
-                format: Some(prod_format),
-                color: None, // 使用默认颜色
-            });
-    }
+    #[arg(long, value_name = "CRATE", default_value = self_test::DEFAULT_SELF_TEST_CRATE, help = "自检使用的包名")]
+    self_test_crate: String,
 
-    if let Err(e) = builder.init() {
-        eprintln!("日志初始化失败: {}", e);
-        process::exit(1);
-    }
+    #[arg(long, value_name = "FILE", conflicts_with = "import", help = "导出缓存文件和版本数据库到tar归档")]
+    export: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = export::DbCompression::Zstd, help = "导出时版本数据库部分的压缩方式")]
+    export_compression: export::DbCompression,
+
+    #[arg(long, value_name = "FILE", help = "从导出的tar归档导入缓存文件和版本数据库")]
+    import: Option<PathBuf>,
+
+    #[arg(long, help = "合并只有连字符/下划线不同的重复包目录及其版本记录")]
+    dedupe_cache: bool,
+
+    #[arg(long, help = "扫描缓存文件，按上游（或索引快照）的校验和与撤销状态重建版本数据库")]
+    rebuild_index: bool,
 }
 
 fn load_config(config_path: Option<String>) -> Result<Config, ConfigError> {
@@ -140,28 +91,42 @@ fn load_config(config_path: Option<String>) -> Result<Config, ConfigError> {
     }
 }
 
-/// 清理melange_db锁文件
-fn cleanup_melange_db_locks(config: &Config) {
-    use std::path::Path;
-
-    // 版本管理器数据库路径
-    let versions_db_path = Path::new(&config.cache.storage_path).join("versions_db");
+fn open_managers(config: &Config) -> (cache::CacheManager, version_manager::VersionManager) {
+    let cache_manager = match cache::CacheManager::from_config(config) {
+        Ok(cache_manager) => cache_manager,
+        Err(e) => {
+            eprintln!("创建缓存管理器失败: {}", e);
+            process::exit(1);
+        }
+    };
+    let version_manager = match version_manager::VersionManager::new(config) {
+        Ok(version_manager) => version_manager,
+        Err(e) => {
+            eprintln!("创建版本管理器失败: {}", e);
+            process::exit(1);
+        }
+    };
+    (cache_manager, version_manager)
+}
 
-    // 清理版本管理器数据库锁文件
-    if let Err(e) = melange_db::cleanup_lock_files(&versions_db_path) {
-        rat_logger::warn!("清理版本管理器锁文件失败: {}", e);
-    } else {
-        rat_logger::info!("已检查版本管理器锁文件");
+fn print_transfer_report(result: Result<export::ExportReport, export::ExportError>) {
+    match result {
+        Ok(report) => println!(
+            "完成: {} 条版本信息，{} 条最新版本映射，{} 个缓存文件",
+            report.versions, report.latest, report.cache_files
+        ),
+        Err(e) => {
+            eprintln!("导出/导入失败: {}", e);
+            process::exit(1);
+        }
     }
-
-    // 检查是否还有其他可能的melange_db实例
-    // 这里可以添加更多数据库路径的检查
 }
 
 fn main() {
     let args = Args::parse();
 
     // 加载配置
+    let config_path = args.config.clone().map(PathBuf::from);
     let config = match load_config(args.config) {
         Ok(config) => {
             if let Err(e) = config.validate() {
@@ -177,17 +142,36 @@ fn main() {
     };
 
     // 设置日志
-    setup_logging(&config.logging.level);
+    if let Err(e) = logging::setup_logging(&config.logging) {
+        eprintln!("日志初始化失败: {}", e);
+        process::exit(1);
+    }
 
-    // 清理melange_db锁文件
-    cleanup_melange_db_locks(&config);
+    // 持有实例锁直到进程退出，防止多个实例同时使用同一个缓存目录，并在持有锁时清理melange_db残留锁文件。
+    // 只读模式下数据库在临时副本中打开，多个实例可以共用；基准测试和自检使用临时目录，不需要实例锁
+    let _instance_lock = if config.cache.read_only || args.benchmark || args.self_test {
+        None
+    } else {
+        match instance_lock::InstanceLock::acquire(&config.cache.storage_path) {
+            Ok(lock) => {
+                if let Err(e) = lock.cleanup_db_locks() {
+                    rat_logger::warn!("清理melange_db锁文件失败: {}", e);
+                }
+                Some(lock)
+            }
+            Err(e) => {
+                eprintln!("无法启动服务器: {}", e);
+                process::exit(1);
+            }
+        }
+    };
 
     // 处理清理缓存命令
     if args.clean {
         println!("正在清理过期缓存...");
 
         // 清理文件缓存
-        match cache::CacheManager::new(&config.cache.storage_path, config.cache.default_ttl) {
+        match cache::CacheManager::from_config(&config) {
             Ok(cache_manager) => {
                 if let Err(e) = cache_manager.clear_expired_cache() {
                     eprintln!("清理文件缓存失败: {}", e);
@@ -224,7 +208,7 @@ fn main() {
     // 处理显示统计信息
     if args.stats {
         println!("缓存统计信息:");
-        match cache::CacheManager::new(&config.cache.storage_path, config.cache.default_ttl) {
+        match cache::CacheManager::from_config(&config) {
             Ok(cache_manager) => {
                 match cache_manager.get_cache_stats() {
                     Ok(stats) => {
@@ -232,6 +216,14 @@ fn main() {
                         println!("  有效文件数: {}", stats.valid_files);
                         println!("  过期文件数: {}", stats.expired_files);
                         println!("  总大小: {} 字节", stats.total_size);
+                        println!("  磁盘总空间: {} 字节", stats.disk_total_bytes);
+                        println!("  磁盘可用空间: {} 字节", stats.disk_available_bytes);
+                        if stats.disk_low_space {
+                            println!(
+                                "  警告: 磁盘可用空间低于阈值 {} 字节",
+                                config.cache.min_free_space_bytes
+                            );
+                        }
                     }
                     Err(e) => {
                         eprintln!("获取缓存统计失败: {}", e);
@@ -247,16 +239,126 @@ fn main() {
         return;
     }
 
-    // 启动服务器
-    println!("启动crates代理服务器...");
-    println!("监听地址: {}", config.server.bind_addr);
-    println!("缓存路径: {}", config.cache.storage_path);
-    println!("默认TTL: {} 秒", config.cache.default_ttl);
+    // 处理基准测试命令，使用临时目录和合成上游，不需要实例锁
+    if args.benchmark {
+        let options = benchmark::BenchmarkOptions {
+            requests: args.bench_requests,
+            concurrency: args.bench_concurrency,
+            ..Default::default()
+        };
 
-    if let Some(upstream) = &config.upstream {
-        if let Some(proxy_url) = &upstream.proxy_url {
-            println!("上游代理: {}", proxy_url);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            match benchmark::run_benchmark(&config, &options).await {
+                Ok(report) => report.print(),
+                Err(e) => {
+                    eprintln!("基准测试失败: {}", e);
+                    process::exit(1);
+                }
+            }
+        });
+        return;
+    }
+
+    // 处理自检命令，使用临时缓存目录，不需要实例锁
+    if args.self_test {
+        println!("正在自检: 通过 {} 请求 {}...", config.upstream.api_url, args.self_test_crate);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap();
+        let passed = runtime.block_on(async {
+            match self_test::run_self_test(&config, &args.self_test_crate).await {
+                Ok(report) => {
+                    report.print();
+                    report.passed()
+                }
+                Err(e) => {
+                    eprintln!("自检失败: {}", e);
+                    false
+                }
+            }
+        });
+        process::exit(if passed { 0 } else { 1 });
+    }
+
+    // 处理导出/导入命令，持有实例锁保证数据库不被运行中的服务修改
+    if let Some(path) = &args.export {
+        println!("正在导出到 {:?}...", path);
+        let (cache_manager, version_manager) = open_managers(&config);
+        print_transfer_report(export::export_cache(&cache_manager, &version_manager, path, args.export_compression));
+        return;
+    }
+
+    if let Some(path) = &args.import {
+        println!("正在从 {:?} 导入...", path);
+        let (cache_manager, version_manager) = open_managers(&config);
+        print_transfer_report(export::import_cache(&cache_manager, &version_manager, path));
+        return;
+    }
+
+    if args.dedupe_cache {
+        println!("正在查找重复的包目录...");
+        let (cache_manager, version_manager) = open_managers(&config);
+        match dedupe::dedupe_cache(&cache_manager, &version_manager) {
+            Ok(report) => {
+                for merged in &report.merged {
+                    println!(
+                        "  {} <- {}: {} 个文件，{} 条版本信息",
+                        merged.canonical,
+                        merged.merged_from.join(", "),
+                        merged.files,
+                        merged.versions
+                    );
+                }
+                println!("完成: 合并了 {} 组重复的包", report.merged.len());
+            }
+            Err(e) => {
+                eprintln!("合并重复包目录失败: {}", e);
+                process::exit(1);
+            }
         }
+        return;
+    }
+
+    if args.rebuild_index {
+        println!("正在从缓存文件重建版本数据库...");
+        let (cache_manager, version_manager) = open_managers(&config);
+        let api_client = crates_api::CratesApiClient::new(&config);
+        match rebuild_index::rebuild_index(&cache_manager, &version_manager, &api_client) {
+            Ok(report) => {
+                for mismatch in &report.mismatches {
+                    println!(
+                        "  校验和不一致 {}@{}: 缓存 {}，上游 {}",
+                        mismatch.crate_name, mismatch.version, mismatch.cached, mismatch.upstream
+                    );
+                }
+                for name in &report.unverified {
+                    println!("  未经上游确认: {}", name);
+                }
+                for (name, reason) in &report.failed {
+                    println!("  读取失败 {}: {}", name, reason);
+                }
+                println!(
+                    "完成: {} 个包，{} 条版本信息，{} 个校验和不一致，{} 个未经上游确认，{} 个读取失败",
+                    report.crates,
+                    report.versions,
+                    report.mismatches.len(),
+                    report.unverified.len(),
+                    report.failed.len()
+                );
+            }
+            Err(e) => {
+                eprintln!("重建版本数据库失败: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
     }
 
     // 设置tokio运行时
@@ -266,8 +368,47 @@ fn main() {
         .build()
         .unwrap();
 
+    // 处理缓存预热命令
+    if let Some(count) = args.warm_popular {
+        println!("正在预热下载量最高的 {} 个包...", count);
+
+        runtime.block_on(async {
+            let service = match proxy::ProxyService::new(&config) {
+                Ok(service) => service,
+                Err(e) => {
+                    eprintln!("创建代理服务失败: {}", e);
+                    process::exit(1);
+                }
+            };
+
+            match warmup::warm_popular(&config, &service, count, &warmup::WarmupOptions::default()).await {
+                Ok(report) => {
+                    println!("预热完成: 成功 {} 个，失败 {} 个", report.cached.len(), report.failed.len());
+                    for (name, reason) in &report.failed {
+                        println!("  {}: {}", name, reason);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("预热失败: {}", e);
+                    process::exit(1);
+                }
+            }
+        });
+        return;
+    }
+
+    // 启动服务器
+    println!("启动crates代理服务器...");
+    println!("监听地址: {}", config.server.bind_addr);
+    println!("缓存路径: {}", config.cache.storage_path);
+    println!("默认TTL: {} 秒", config.cache.default_ttl);
+
+    if let Some(proxy_url) = &config.upstream.proxy_url {
+        println!("上游代理: {}", proxy_url);
+    }
+
     runtime.block_on(async {
-        if let Err(e) = run_server(&config).await {
+        if let Err(e) = run_server(&config, config_path).await {
             eprintln!("服务器运行错误: {}", e);
             process::exit(1);
         }
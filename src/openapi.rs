@@ -0,0 +1,216 @@
+//! `/openapi.json`：根据路由表生成的接口描述文档（OpenAPI 3.0）
+
+use serde_json::{json, Map, Value};
+
+/// 一个对外提供的接口
+pub struct RouteDoc {
+    pub method: &'static str,
+    /// 路径模板，`{name}` 形式的路径段为路径参数
+    pub path: &'static str,
+    pub summary: &'static str,
+    /// 是否需要 `server.admin_token`
+    pub admin: bool,
+    /// 成功时的响应类型
+    pub content_type: &'static str,
+}
+
+/// 代理支持的全部接口，新增路由时同步更新
+pub const ROUTES: &[RouteDoc] = &[
+    RouteDoc {
+        method: "get",
+        path: "/api/v1/crates/{crate}/{version}/download",
+        summary: "下载指定版本的 .crate 文件，version 为 latest 时下载最新的未撤销版本",
+        admin: false,
+        content_type: "application/octet-stream",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/api/v1/crates/{crate}/versions/{version_id}/download",
+        summary: "按数字版本ID下载 .crate 文件",
+        admin: false,
+        content_type: "application/octet-stream",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/api/v1/crates/{crate}/versions",
+        summary: "包的版本列表（支持 ?page=&per_page= 分页）：版本号、是否撤销和校验和，优先读取版本数据库",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/api/v1/crates/{crate}/{endpoint}",
+        summary: "转发 upstream.passthrough_endpoints 中的包子接口（如 owners、reverse_dependencies），其余返回404",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "post",
+        path: "/api/v1/crates/new",
+        summary: "本地发布：上传 cargo publish 格式的 .crate 及元数据，之后按普通下载路径提供",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "put",
+        path: "/api/v1/crates/new",
+        summary: "同 POST，供 cargo publish 使用",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/index/{path}",
+        summary: "稀疏索引（sparse registry），包括 config.json",
+        admin: false,
+        content_type: "text/plain",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/resolve/{crate}/{req}",
+        summary: "返回满足semver版本要求（URL编码）的最高未撤销版本",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/info/{crate}",
+        summary: "包的简要信息：latest 版本、已知的sha256校验和以及缓存文件大小，只读取本地数据",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/healthz",
+        summary: "健康检查：服务状态、维护模式和缓存磁盘空间",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/metrics",
+        summary: "Prometheus格式的运行指标：请求数、缓存命中、合并的并发下载等",
+        admin: false,
+        content_type: "text/plain",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/favicon.ico",
+        summary: "返回204空响应，避免浏览器访问时产生无法解析的请求",
+        admin: false,
+        content_type: "image/x-icon",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/openapi.json",
+        summary: "本接口描述文档",
+        admin: false,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/admin/maintenance",
+        summary: "查询维护模式状态",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "post",
+        path: "/admin/maintenance",
+        summary: "切换维护模式，参数 enabled=true|false",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/admin/config",
+        summary: "查询当前生效的配置，敏感信息已脱敏",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "post",
+        path: "/admin/repair/{crate}/{version}",
+        summary: "删除缓存文件并从上游重新下载、校验",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "get",
+        path: "/admin/tombstone/{crate}",
+        summary: "查询包是否已标记为删除",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "post",
+        path: "/admin/tombstone/{crate}",
+        summary: "标记包已从注册表删除，之后的请求返回410，参数 purge=true 同时清除缓存",
+        admin: true,
+        content_type: "application/json",
+    },
+    RouteDoc {
+        method: "delete",
+        path: "/admin/tombstone/{crate}",
+        summary: "取消包的删除标记",
+        admin: true,
+        content_type: "application/json",
+    },
+];
+
+/// 路径模板中的参数名
+fn path_params(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')))
+}
+
+/// 由路由表生成OpenAPI文档
+pub fn document() -> Value {
+    let mut paths = Map::new();
+    for route in ROUTES {
+        let parameters: Vec<Value> = path_params(route.path)
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+
+        let mut operation = json!({
+            "summary": route.summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "成功",
+                    "content": { route.content_type: {} },
+                },
+            },
+        });
+        if route.admin {
+            operation["security"] = json!([{ "adminToken": [] }]);
+            operation["responses"]["401"] = json!({ "description": "令牌错误" });
+            operation["responses"]["403"] = json!({ "description": "未配置 server.admin_token" });
+        }
+
+        let item = paths.entry(route.path).or_insert_with(|| json!({}));
+        item[route.method] = operation;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "crates_proxy",
+            "version": crate::config::VERSION,
+            "description": "crates.io 缓存代理",
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "adminToken": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
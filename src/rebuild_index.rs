@@ -0,0 +1,210 @@
+//! 从缓存文件重建版本数据库
+//!
+//! 版本数据库丢失而缓存文件还在时，代理不知道哪些版本已经缓存、校验和是多少。
+//! 重建时扫描缓存目录，重新计算每个包文件的sha256，并向上游（或配置的索引快照）查询校验和与撤销状态，
+//! 把结果写回 `VersionManager`。本地发布的包不查询上游，直接按本地版本记录。
+
+use crate::cache::{CacheError, CacheManager};
+use crate::checksum::sha256_hex;
+use crate::crates_api::{ApiError, CratesApiClient};
+use crate::version_manager::{VersionManager, VersionManagerError};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RebuildError {
+    #[error("缓存错误: {0}")]
+    CacheError(#[from] CacheError),
+    #[error("版本管理错误: {0}")]
+    VersionManagerError(#[from] VersionManagerError),
+}
+
+/// 缓存文件的sha256与上游记录的校验和不一致
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub crate_name: String,
+    pub version: String,
+    /// 缓存文件实际的sha256
+    pub cached: String,
+    /// 上游记录的校验和，写入数据库的是这个值
+    pub upstream: String,
+}
+
+#[derive(Debug, Default)]
+pub struct RebuildReport {
+    /// 扫描到的包数
+    pub crates: usize,
+    /// 写入数据库的版本记录数
+    pub versions: usize,
+    /// 与上游校验和不一致的缓存文件，可通过 `/admin/repair` 重新下载
+    pub mismatches: Vec<ChecksumMismatch>,
+    /// 上游不可用或没有该版本，只按缓存文件的sha256记录的版本（`crate@version`）
+    pub unverified: Vec<String>,
+    /// 无法读取的缓存文件及原因
+    pub failed: Vec<(String, String)>,
+}
+
+/// 扫描缓存目录，按缓存文件和上游信息重建版本数据库
+pub fn rebuild_index(
+    cache_manager: &CacheManager,
+    version_manager: &VersionManager,
+    api_client: &CratesApiClient,
+) -> Result<RebuildReport, RebuildError> {
+    let names = cache_manager.crate_names()?;
+    let total = names.len();
+    let mut report = RebuildReport::default();
+
+    for (index, crate_name) in names.iter().enumerate() {
+        rat_logger::info!("重建索引 [{}/{}]: {}", index + 1, total, crate_name);
+        report.crates += 1;
+
+        // 本地发布的版本不在上游，直接按本地版本记录
+        for version in cache_manager.local_crate_versions(crate_name) {
+            match std::fs::read(cache_manager.local_crate_path(crate_name, &version)) {
+                Ok(content) => {
+                    version_manager.publish_local_version(crate_name, &version, &sha256_hex(&content))?;
+                    report.versions += 1;
+                }
+                Err(e) => report.failed.push((format!("{}@{}", crate_name, version), e.to_string())),
+            }
+        }
+
+        let cached_versions = cache_manager.cached_versions(crate_name);
+        if cached_versions.is_empty() {
+            continue;
+        }
+
+        let upstream: HashMap<String, _> = match api_client.get_available_versions(crate_name) {
+            Ok(versions) => versions.into_iter().map(|version| (version.num.clone(), version)).collect(),
+            Err(ApiError::HttpError(404, _)) => {
+                rat_logger::warn!("上游不存在包 {}，只按缓存文件记录", crate_name);
+                HashMap::new()
+            }
+            Err(e) => {
+                rat_logger::warn!("查询 {} 的版本失败: {}，只按缓存文件记录", crate_name, e);
+                HashMap::new()
+            }
+        };
+
+        let mut version_infos = Vec::with_capacity(cached_versions.len());
+        for version in cached_versions {
+            let filename = cache_manager.crate_filename(crate_name, &version);
+            let content = match cache_manager.get_cached_content(crate_name, &version, &filename) {
+                Ok(content) => content,
+                Err(e) => {
+                    report.failed.push((format!("{}@{}", crate_name, version), e.to_string()));
+                    continue;
+                }
+            };
+            let cached = sha256_hex(&content);
+
+            let (download_path, checksum, yanked) = match upstream.get(&version) {
+                Some(upstream) => {
+                    if !upstream.checksum.is_empty() && upstream.checksum != cached {
+                        rat_logger::warn!(
+                            "校验和不一致 {}@{}: 缓存 {}，上游 {}",
+                            crate_name,
+                            version,
+                            cached,
+                            upstream.checksum
+                        );
+                        report.mismatches.push(ChecksumMismatch {
+                            crate_name: crate_name.clone(),
+                            version: version.clone(),
+                            cached: cached.clone(),
+                            upstream: upstream.checksum.clone(),
+                        });
+                    }
+                    let checksum = if upstream.checksum.is_empty() { cached } else { upstream.checksum.clone() };
+                    (upstream.dl_path.clone(), checksum, upstream.yanked)
+                }
+                None => {
+                    report.unverified.push(format!("{}@{}", crate_name, version));
+                    (format!("/api/v1/crates/{}/{}/download", crate_name, version), cached, false)
+                }
+            };
+            version_infos.push(version_manager.build_version_info(&version, &download_path, &checksum, yanked)?);
+        }
+        report.versions += version_manager.set_version_infos(crate_name, &version_infos)?;
+    }
+
+    version_manager.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_support::{crate_versions_json, fake_crate_bytes, MockResponse, MockServer};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rebuild_repopulates_version_db_from_cache_files() {
+        let good = fake_crate_bytes("foo 1.0.0");
+        let yanked = fake_crate_bytes("foo 1.1.0");
+        let corrupt = fake_crate_bytes("foo 1.2.0 corrupt");
+        let checksums = [
+            ("1.2.0", sha256_hex(&fake_crate_bytes("foo 1.2.0"))),
+            ("1.1.0", sha256_hex(&yanked)),
+            ("1.0.0", sha256_hex(&good)),
+        ];
+        let listed = checksums.clone();
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/api/v1/crates/foo" => {
+                let mut json: serde_json::Value =
+                    serde_json::from_str(&crate_versions_json("foo", &[("1.2.0", false), ("1.1.0", true), ("1.0.0", false)])).unwrap();
+                for (index, (_, checksum)) in listed.iter().enumerate() {
+                    json["versions"][index]["checksum"] = checksum.clone().into();
+                }
+                MockResponse::ok(json.to_string())
+            }
+            _ => MockResponse::status(404),
+        });
+
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.upstream.api_url = server.url();
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        let version_manager = VersionManager::new(&config).unwrap();
+        let api_client = CratesApiClient::new(&config);
+
+        for (version, content) in [("1.0.0", &good), ("1.1.0", &yanked), ("1.2.0", &corrupt)] {
+            cache_manager.save_to_cache("foo", version, &format!("foo-{}.crate", version), content).unwrap();
+        }
+        let orphan = fake_crate_bytes("gone 0.1.0");
+        cache_manager.save_to_cache("gone", "0.1.0", "gone-0.1.0.crate", &orphan).unwrap();
+        let private = fake_crate_bytes("private 0.1.0");
+        cache_manager.save_local_crate("private", "0.1.0", &private).unwrap();
+        assert!(version_manager.get_all_versions("foo").unwrap().is_empty());
+
+        let report = rebuild_index(&cache_manager, &version_manager, &api_client).unwrap();
+        assert_eq!(report.crates, 3);
+        assert_eq!(report.versions, 5);
+        assert_eq!(
+            report.mismatches,
+            vec![ChecksumMismatch {
+                crate_name: "foo".into(),
+                version: "1.2.0".into(),
+                cached: sha256_hex(&corrupt),
+                upstream: checksums[0].1.clone(),
+            }]
+        );
+        assert_eq!(report.unverified, vec!["gone@0.1.0".to_string()]);
+        assert!(report.failed.is_empty());
+
+        // 校验和与撤销状态来自上游，上游没有的版本按缓存文件的sha256记录
+        for (version, checksum) in &checksums {
+            let info = version_manager.get_version_info("foo", version).unwrap().unwrap();
+            assert_eq!(&info.checksum, checksum);
+            assert_eq!(info.yanked, *version == "1.1.0");
+        }
+        assert_eq!(version_manager.get_version_info("gone", "0.1.0").unwrap().unwrap().checksum, sha256_hex(&orphan));
+        let local = version_manager.get_local_versions("private").unwrap();
+        assert_eq!(local.len(), 1);
+        assert_eq!(local[0].checksum, sha256_hex(&private));
+        assert_eq!(server.hits("/api/v1/crates/foo"), 1);
+        assert_eq!(server.hits("/api/v1/crates/private"), 0);
+    }
+}
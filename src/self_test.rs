@@ -0,0 +1,164 @@
+//! 部署后自检：在进程内通过 ProxyService 向配置的上游请求一个小包，
+//! 确认能下载、写入缓存并从缓存再次返回
+
+use crate::audit::DownloadRecord;
+use crate::cache::CacheManager;
+use crate::config::Config;
+use crate::proxy::{ProxyError, ProxyService};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::{Request, StatusCode};
+use std::path::Path;
+
+/// 默认用于自检的包，体积小且长期存在
+pub const DEFAULT_SELF_TEST_CRATE: &str = "cfg-if";
+
+/// 自检的一个步骤
+#[derive(Debug, Clone)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SelfTestReport {
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// 所有步骤都通过
+    pub fn passed(&self) -> bool {
+        !self.steps.is_empty() && self.steps.iter().all(|step| step.passed)
+    }
+
+    pub fn print(&self) {
+        for step in &self.steps {
+            println!("  [{}] {}: {}", if step.passed { "通过" } else { "失败" }, step.name, step.detail);
+        }
+        println!("自检{}", if self.passed() { "通过" } else { "失败" });
+    }
+
+    fn push(&mut self, name: &'static str, passed: bool, detail: impl Into<String>) -> bool {
+        self.steps.push(SelfTestStep {
+            name,
+            passed,
+            detail: detail.into(),
+        });
+        passed
+    }
+}
+
+/// 一次请求的结果：状态码、X-Cache、实际返回的版本和响应体
+struct Fetched {
+    status: StatusCode,
+    x_cache: Option<String>,
+    version: Option<String>,
+    body: Bytes,
+}
+
+async fn fetch(service: &ProxyService, path: &str) -> Result<Fetched, ProxyError> {
+    let request = Request::builder().uri(path).body(Empty::<Bytes>::new())?;
+    let response = service.handle_request(request).await?;
+    let status = response.status();
+    let x_cache = response
+        .headers()
+        .get("X-Cache")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let version = response.extensions().get::<DownloadRecord>().map(|record| record.version.clone());
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok(Fetched {
+        status,
+        x_cache,
+        version,
+        body,
+    })
+}
+
+/// 运行自检。使用独立的临时缓存目录，不影响正式缓存
+pub async fn run_self_test(config: &Config, crate_name: &str) -> Result<SelfTestReport, ProxyError> {
+    let storage_path = std::env::temp_dir().join(format!("crates_proxy_self_test_{}", std::process::id()));
+    let mut test_config = config.clone();
+    test_config.cache.storage_path = storage_path.display().to_string();
+    test_config.cache.hot_path = None;
+    test_config.cache.cold_path = None;
+    test_config.cache.readonly_fallback_paths.clear();
+    test_config.server.maintenance = false;
+    test_config.logging.audit_path = None;
+
+    let result = run_with_service(&test_config, crate_name).await;
+    remove_storage(&storage_path);
+    result
+}
+
+async fn run_with_service(config: &Config, crate_name: &str) -> Result<SelfTestReport, ProxyError> {
+    let service = ProxyService::new(config)?;
+    let mut report = SelfTestReport::default();
+
+    // 1. 首次请求从上游下载
+    let first = fetch(&service, &format!("/api/v1/crates/{}/latest/download", crate_name)).await?;
+    let downloaded = first.status == StatusCode::OK && !first.body.is_empty() && first.x_cache.as_deref() == Some("MISS");
+    let detail = format!("HTTP {}，{} 字节，X-Cache: {}", first.status, first.body.len(), first.x_cache.as_deref().unwrap_or("-"));
+    if !report.push("从上游下载", downloaded, detail) {
+        return Ok(report);
+    }
+    let Some(version) = first.version else {
+        report.push("从上游下载", false, "响应中缺少版本信息");
+        return Ok(report);
+    };
+
+    // 2. 文件已写入缓存（等待后台写入完成）
+    service.flush_cache_writes().await;
+    let cache_manager = CacheManager::from_config(config)?;
+    let filename = cache_manager.crate_filename(crate_name, &version);
+    let cached = cache_manager.is_cached(crate_name, &version, &filename);
+    let detail = format!("{} {}", filename, if cached { "已缓存" } else { "不在缓存中" });
+    if !report.push("写入缓存", cached, detail) {
+        return Ok(report);
+    }
+
+    // 3. 再次请求由缓存返回，内容一致
+    let second = fetch(&service, &format!("/api/v1/crates/{}/{}/download", crate_name, version)).await?;
+    let served = second.status == StatusCode::OK && second.x_cache.as_deref() == Some("HIT") && second.body == first.body;
+    let detail = format!(
+        "HTTP {}，X-Cache: {}，内容{}",
+        second.status,
+        second.x_cache.as_deref().unwrap_or("-"),
+        if second.body == first.body { "一致" } else { "不一致" }
+    );
+    report.push("从缓存返回", served, detail);
+
+    Ok(report)
+}
+
+fn remove_storage(path: &Path) {
+    if let Err(e) = std::fs::remove_dir_all(path) {
+        rat_logger::warn!("删除自检临时目录失败 {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{crate_versions_json, fake_crate_bytes, MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_self_test_passes_against_mock_upstream() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v1/crates/foo" => MockResponse::ok(crate_versions_json("foo", &[("1.0.0", false)])),
+            "/api/v1/crates/foo/1.0.0/download" => MockResponse::ok(fake_crate_bytes("foo")),
+            _ => MockResponse::status(404),
+        });
+        let mut config = Config::default();
+        config.upstream.api_url = server.url();
+
+        let report = run_self_test(&config, "foo").await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.steps.len(), 3);
+        assert_eq!(server.hits("/api/v1/crates/foo/1.0.0/download"), 1);
+
+        let report = run_self_test(&config, "missing").await.unwrap();
+        assert!(!report.passed());
+    }
+}
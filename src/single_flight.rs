@@ -0,0 +1,69 @@
+//! 同一个包文件的并发下载合并：第一个请求负责下载，其余请求等待其完成（包括后台写入缓存）后直接读取缓存
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+#[derive(Default)]
+pub struct DownloadGate {
+    /// 正在下载的文件，没有持有者时删除
+    in_flight: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+/// 进入下载的许可，释放后下一个等待者继续。可移入后台任务，在缓存写入完成后再释放
+pub struct DownloadTurn {
+    gate: Arc<DownloadGate>,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+    /// 是否等待过其他请求的下载
+    waited: bool,
+}
+
+impl DownloadGate {
+    /// 进入指定文件的下载，同一个文件已有下载进行中时等待其结束
+    pub async fn enter(self: &Arc<Self>, key: &str) -> DownloadTurn {
+        let lock = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let (guard, waited) = match lock.clone().try_lock_owned() {
+            Ok(guard) => (guard, false),
+            Err(_) => (lock.lock_owned().await, true),
+        };
+
+        DownloadTurn {
+            gate: self.clone(),
+            key: key.to_string(),
+            guard: Some(guard),
+            waited,
+        }
+    }
+
+    /// 等待当前所有进行中的下载（包括后台的缓存写入）结束
+    pub async fn settle(&self) {
+        let locks: Vec<_> = self.in_flight.lock().unwrap_or_else(PoisonError::into_inner).values().cloned().collect();
+        for lock in locks {
+            drop(lock.lock().await);
+        }
+    }
+}
+
+impl DownloadTurn {
+    pub fn waited(&self) -> bool {
+        self.waited
+    }
+}
+
+impl Drop for DownloadTurn {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        let mut in_flight = self.gate.in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+        // 只剩表中的引用时说明没有其他等待者
+        if in_flight.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
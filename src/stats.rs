@@ -0,0 +1,404 @@
+//! 服务运行统计：请求数（总数和按接口类别）、缓存命中率、发送字节数、并发峰值、合并的请求数、无法解析的请求数，
+//! 以及包文件大小和请求耗时的直方图，关闭时输出汇总
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 包文件大小直方图的桶上界（字节）：1KiB ~ 100MiB
+const DOWNLOAD_SIZE_BUCKETS: &[u64] = &[1 << 10, 10 << 10, 100 << 10, 1 << 20, 10 << 20, 100 << 20];
+
+/// 请求耗时直方图的桶上界（微秒）：5ms ~ 10s
+const REQUEST_DURATION_BUCKETS: &[u64] = &[5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000, 10_000_000];
+
+/// 请求按路径划分的接口类别，分别计数以了解流量构成
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    /// 包文件下载
+    Download,
+    /// 稀疏索引
+    Index,
+    /// 包元数据、版本列表、子接口转发和本地发布
+    Api,
+    /// `/info/` 和 `/resolve/`
+    Info,
+    /// 管理接口
+    Admin,
+    /// 健康检查、运行指标、OpenAPI文档和图标
+    Service,
+    /// 无法归类的路径
+    Other,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 7] = [
+        Endpoint::Download,
+        Endpoint::Index,
+        Endpoint::Api,
+        Endpoint::Info,
+        Endpoint::Admin,
+        Endpoint::Service,
+        Endpoint::Other,
+    ];
+
+    /// 汇总行和指标标签中使用的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            Endpoint::Download => "download",
+            Endpoint::Index => "index",
+            Endpoint::Api => "api",
+            Endpoint::Info => "info",
+            Endpoint::Admin => "admin",
+            Endpoint::Service => "service",
+            Endpoint::Other => "other",
+        }
+    }
+}
+
+/// 固定分桶的直方图，观测值为整数（字节或微秒）
+#[derive(Debug)]
+struct Histogram {
+    /// 各桶的上界（含），升序排列
+    bounds: &'static [u64],
+    /// 落入各桶的观测数（不累计），最后一项为超出所有上界的观测数
+    buckets: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: u64) {
+        let index = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let counts = self
+            .buckets
+            .iter()
+            .map(|bucket| {
+                cumulative += bucket.load(Ordering::Relaxed);
+                cumulative
+            })
+            .collect();
+        HistogramSnapshot {
+            bounds: self.bounds.to_vec(),
+            counts,
+            sum: self.sum.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 直方图快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// 各桶的上界
+    pub bounds: Vec<u64>,
+    /// 小于等于各上界的累计观测数，比 `bounds` 多一项（`+Inf`，即总观测数）
+    pub counts: Vec<u64>,
+    /// 观测值之和
+    pub sum: u64,
+}
+
+impl HistogramSnapshot {
+    /// 总观测数
+    pub fn count(&self) -> u64 {
+        self.counts.last().copied().unwrap_or(0)
+    }
+
+    /// 直方图格式的指标文本，`scale` 为观测值与输出单位之比（如微秒输出为秒时为1e6）
+    fn prometheus_text(&self, name: &str, help: &str, scale: f64) -> String {
+        let mut text = format!("# HELP {} {}\n# TYPE {} histogram\n", name, help, name);
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            text.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, *bound as f64 / scale, count));
+        }
+        text.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, self.count()));
+        text.push_str(&format!("{}_sum {}\n{}_count {}\n", name, self.sum as f64 / scale, name, self.count()));
+        text
+    }
+}
+
+/// 运行期间累计的计数器
+#[derive(Debug)]
+pub struct ServiceStats {
+    started_at: Instant,
+    requests: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    bytes_served: AtomicU64,
+    in_flight: AtomicU64,
+    peak_concurrency: AtomicU64,
+    coalesced_requests: AtomicU64,
+    bad_requests: AtomicU64,
+    shed_requests: AtomicU64,
+    /// 按 `Endpoint::ALL` 的顺序排列的各类别请求数
+    endpoint_requests: [AtomicU64; Endpoint::ALL.len()],
+    download_size: Histogram,
+    request_duration: Histogram,
+}
+
+/// 某一时刻的统计快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsSnapshot {
+    pub requests: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub bytes_served: u64,
+    pub uptime: Duration,
+    pub peak_concurrency: u64,
+    /// 等待同一文件进行中的下载、没有自己回源的请求数
+    pub coalesced_requests: u64,
+    /// 路径无法解析、按错误记录日志的请求数（不含浏览器自动发出的探测请求）
+    pub bad_requests: u64,
+    /// 排队已满、直接返回503的请求数
+    pub shed_requests: u64,
+    /// 各接口类别的请求数，按 `Endpoint::ALL` 的顺序排列
+    pub endpoint_requests: [u64; Endpoint::ALL.len()],
+    /// 返回的包文件大小（字节）分布
+    pub download_size: HistogramSnapshot,
+    /// 请求耗时（微秒，到响应头准备好为止）分布
+    pub request_duration: HistogramSnapshot,
+}
+
+/// 进行中的请求，释放时减少并发计数
+pub struct InFlightGuard<'a> {
+    stats: &'a ServiceStats,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for ServiceStats {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            requests: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            bytes_served: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            peak_concurrency: AtomicU64::new(0),
+            coalesced_requests: AtomicU64::new(0),
+            bad_requests: AtomicU64::new(0),
+            shed_requests: AtomicU64::new(0),
+            endpoint_requests: std::array::from_fn(|_| AtomicU64::new(0)),
+            download_size: Histogram::new(DOWNLOAD_SIZE_BUCKETS),
+            request_duration: Histogram::new(REQUEST_DURATION_BUCKETS),
+        }
+    }
+}
+
+impl ServiceStats {
+    /// 记录一个新请求，返回的guard在请求结束时释放
+    pub fn begin_request(&self) -> InFlightGuard<'_> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_concurrency.fetch_max(in_flight, Ordering::Relaxed);
+        InFlightGuard { stats: self }
+    }
+
+    pub fn record_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced(&self) {
+        self.coalesced_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_bad_request(&self) {
+        self.bad_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_shed(&self) {
+        self.shed_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一个请求所属的接口类别
+    pub fn record_endpoint(&self, endpoint: Endpoint) {
+        self.endpoint_requests[endpoint as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次返回的包文件大小（字节）
+    pub fn record_download_size(&self, bytes: u64) {
+        self.download_size.observe(bytes);
+    }
+
+    /// 记录一次请求的耗时
+    pub fn record_request_duration(&self, duration: Duration) {
+        self.request_duration.observe(duration.as_micros().min(u64::MAX as u128) as u64);
+    }
+
+    pub fn record_bytes(&self, bytes: u64) {
+        self.bytes_served.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            bytes_served: self.bytes_served.load(Ordering::Relaxed),
+            uptime: self.started_at.elapsed(),
+            peak_concurrency: self.peak_concurrency.load(Ordering::Relaxed),
+            coalesced_requests: self.coalesced_requests.load(Ordering::Relaxed),
+            bad_requests: self.bad_requests.load(Ordering::Relaxed),
+            shed_requests: self.shed_requests.load(Ordering::Relaxed),
+            endpoint_requests: std::array::from_fn(|i| self.endpoint_requests[i].load(Ordering::Relaxed)),
+            download_size: self.download_size.snapshot(),
+            request_duration: self.request_duration.snapshot(),
+        }
+    }
+}
+
+impl StatsSnapshot {
+    /// 缓存命中率（0.0~1.0），没有缓存查询时为0
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.cache_hits + self.cache_misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.cache_hits as f64 / lookups as f64
+        }
+    }
+
+    /// 某个接口类别的请求数
+    pub fn endpoint_requests(&self, endpoint: Endpoint) -> u64 {
+        self.endpoint_requests[endpoint as usize]
+    }
+
+    /// 单行 key=value 格式的汇总，便于日志系统解析
+    pub fn summary_line(&self) -> String {
+        let mut line = format!(
+            "requests={} cache_hits={} cache_misses={} hit_rate={:.4} bytes_served={} uptime_secs={} peak_concurrency={} coalesced_requests={} bad_requests={} shed_requests={}",
+            self.requests,
+            self.cache_hits,
+            self.cache_misses,
+            self.hit_rate(),
+            self.bytes_served,
+            self.uptime.as_secs(),
+            self.peak_concurrency,
+            self.coalesced_requests,
+            self.bad_requests,
+            self.shed_requests
+        );
+        for endpoint in Endpoint::ALL {
+            line.push_str(&format!(" {}_requests={}", endpoint.name(), self.endpoint_requests(endpoint)));
+        }
+        line
+    }
+
+    /// Prometheus文本格式的指标，供 `/metrics` 使用
+    pub fn prometheus_text(&self) -> String {
+        let metrics: [(&str, &str, &str, String); 9] = [
+            ("crates_proxy_requests_total", "counter", "处理的请求数", self.requests.to_string()),
+            ("crates_proxy_cache_hits_total", "counter", "缓存命中次数", self.cache_hits.to_string()),
+            ("crates_proxy_cache_misses_total", "counter", "缓存未命中次数", self.cache_misses.to_string()),
+            ("crates_proxy_bytes_served_total", "counter", "响应发送的字节数", self.bytes_served.to_string()),
+            ("crates_proxy_coalesced_requests_total", "counter", "等待进行中的下载而没有自己回源的请求数", self.coalesced_requests.to_string()),
+            ("crates_proxy_bad_requests_total", "counter", "路径无法解析的请求数", self.bad_requests.to_string()),
+            ("crates_proxy_shed_requests_total", "counter", "排队已满、直接返回503的请求数", self.shed_requests.to_string()),
+            ("crates_proxy_peak_concurrency", "gauge", "并发请求数峰值", self.peak_concurrency.to_string()),
+            ("crates_proxy_uptime_seconds", "gauge", "运行时间（秒）", self.uptime.as_secs().to_string()),
+        ];
+
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value));
+        }
+        text.push_str("# HELP crates_proxy_endpoint_requests_total 各接口类别的请求数\n# TYPE crates_proxy_endpoint_requests_total counter\n");
+        for endpoint in Endpoint::ALL {
+            text.push_str(&format!(
+                "crates_proxy_endpoint_requests_total{{endpoint=\"{}\"}} {}\n",
+                endpoint.name(),
+                self.endpoint_requests(endpoint)
+            ));
+        }
+        text.push_str(&self.download_size.prometheus_text("crates_proxy_download_size_bytes", "返回的包文件大小（字节）", 1.0));
+        text.push_str(&self.request_duration.prometheus_text("crates_proxy_request_duration_seconds", "请求耗时（秒）", 1e6));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_concurrency_and_hit_rate() {
+        let stats = ServiceStats::default();
+        {
+            let _a = stats.begin_request();
+            let _b = stats.begin_request();
+            let _c = stats.begin_request();
+        }
+        let _d = stats.begin_request();
+        stats.record_hit();
+        stats.record_hit();
+        stats.record_hit();
+        stats.record_miss();
+        stats.record_bytes(1500);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.requests, 4);
+        assert_eq!(snapshot.peak_concurrency, 3);
+        assert_eq!(snapshot.hit_rate(), 0.75);
+        assert!(snapshot.summary_line().contains("bytes_served=1500"));
+        assert!(snapshot.prometheus_text().contains("crates_proxy_cache_hits_total 3\n"));
+    }
+
+    #[test]
+    fn test_endpoint_counters() {
+        let stats = ServiceStats::default();
+        stats.record_endpoint(Endpoint::Download);
+        stats.record_endpoint(Endpoint::Download);
+        stats.record_endpoint(Endpoint::Admin);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.endpoint_requests(Endpoint::Download), 2);
+        assert_eq!(snapshot.endpoint_requests(Endpoint::Admin), 1);
+        assert_eq!(snapshot.endpoint_requests(Endpoint::Index), 0);
+        assert!(snapshot.summary_line().ends_with(" download_requests=2 index_requests=0 api_requests=0 info_requests=0 admin_requests=1 service_requests=0 other_requests=0"));
+        let text = snapshot.prometheus_text();
+        assert!(text.contains("crates_proxy_endpoint_requests_total{endpoint=\"download\"} 2\n"));
+        assert!(text.contains("crates_proxy_endpoint_requests_total{endpoint=\"other\"} 0\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let stats = ServiceStats::default();
+        for bytes in [500, 1024, 5000, 2 << 20, 1 << 30] {
+            stats.record_download_size(bytes);
+        }
+        stats.record_request_duration(Duration::from_millis(2));
+        stats.record_request_duration(Duration::from_millis(200));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.download_size.counts, vec![2, 3, 3, 3, 4, 4, 5]);
+        assert_eq!(snapshot.download_size.count(), 5);
+        assert_eq!(snapshot.download_size.sum, 500 + 1024 + 5000 + (2 << 20) + (1 << 30));
+
+        let text = snapshot.prometheus_text();
+        assert!(text.contains("# TYPE crates_proxy_download_size_bytes histogram\n"));
+        assert!(text.contains("crates_proxy_download_size_bytes_bucket{le=\"1024\"} 2\n"));
+        assert!(text.contains("crates_proxy_download_size_bytes_bucket{le=\"+Inf\"} 5\n"));
+        assert!(text.contains("crates_proxy_download_size_bytes_count 5\n"));
+        assert!(text.contains("crates_proxy_request_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("crates_proxy_request_duration_seconds_bucket{le=\"0.5\"} 2\n"));
+        assert!(text.contains("crates_proxy_request_duration_seconds_sum 0.202\n"));
+    }
+}
@@ -0,0 +1,234 @@
+//! 测试辅助：基于标准库的简易HTTP模拟上游服务器
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// 模拟服务器收到的请求
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl MockRequest {
+    /// 按名称（不区分大小写）获取请求头
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// 模拟服务器返回的响应
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::status(200).with_body(body)
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// 在后台线程中运行的模拟HTTP服务器，记录所有收到的请求
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        {
+            let requests = requests.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+                    thread::spawn(move || Self::serve(stream, &requests, handler.as_ref()));
+                }
+            });
+        }
+
+        Self {
+            addr,
+            requests,
+            stopped,
+        }
+    }
+
+    fn serve(stream: TcpStream, requests: &Mutex<Vec<MockRequest>>, handler: &Handler) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+
+        let content_length = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, v)| v.parse::<usize>().ok())
+            .unwrap_or(0);
+        // 读完请求体再响应，内容不需要保存
+        let mut body = vec![0; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+
+        let request = MockRequest {
+            method,
+            path,
+            headers,
+        };
+        requests.lock().unwrap().push(request.clone());
+
+        let response = handler(&request);
+        let mut head = format!("HTTP/1.1 {} MOCK\r\n", response.status);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.body.len()));
+
+        let mut stream = stream;
+        let _ = stream.write_all(head.as_bytes());
+        if request.method != "HEAD" {
+            let _ = stream.write_all(&response.body);
+        }
+        let _ = stream.flush();
+    }
+
+    /// 服务器根地址，如 `http://127.0.0.1:12345`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// 统计指定路径被请求的次数
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests.lock().unwrap().iter().filter(|r| r.path == path).count()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // 连接一次以唤醒阻塞在accept上的线程
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// 构造一个以gzip魔数开头的伪造crate文件内容
+pub fn fake_crate_bytes(content: &str) -> Vec<u8> {
+    let mut data = vec![0x1f, 0x8b];
+    data.extend_from_slice(content.as_bytes());
+    data
+}
+
+/// 构造真实的 `.crate`（tar.gz）内容，files为顶层目录下的相对路径
+pub fn crate_archive_bytes(root: &str, files: &[&str]) -> Vec<u8> {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+
+    for file in files {
+        let content = format!("// {}\n", file);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{}/{}", root, file), content.as_bytes())
+            .unwrap();
+    }
+
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// 构造crates.io风格的包信息JSON，versions为 (版本号, 是否撤销)
+pub fn crate_versions_json(crate_name: &str, versions: &[(&str, bool)]) -> String {
+    let versions: Vec<serde_json::Value> = versions
+        .iter()
+        .map(|(num, yanked)| {
+            serde_json::json!({
+                "num": num,
+                "dl_path": format!("/api/v1/crates/{}/{}/download", crate_name, num),
+                "checksum": "",
+                "yanked": yanked,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "crate": {
+            "id": crate_name,
+            "name": crate_name,
+            "max_version": versions.first().map(|v| v["num"].clone()).unwrap_or_default(),
+            "downloads": 0,
+        },
+        "versions": versions,
+    })
+    .to_string()
+}
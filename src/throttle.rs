@@ -0,0 +1,88 @@
+//! 响应体限速：按配置的字节速率分段发送，避免少数慢客户端的大下载占满出口带宽
+
+use crate::proxy::ProxyBody;
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 每段最大的字节数，与从磁盘读取文件的分块大小一致
+const MAX_PIECE: usize = 64 * 1024;
+
+/// 按发送的字节数计算每段数据的最早发送时间
+struct Pacer {
+    bytes_per_sec: u64,
+    /// 第一段数据的发送时间，在响应体开始被读取时才确定
+    started: Option<Instant>,
+    sent: u64,
+}
+
+impl Pacer {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self { bytes_per_sec, started: None, sent: 0 }
+    }
+
+    /// 长度为 `len` 的下一段数据最早可以发送的时间
+    fn next_deadline(&mut self, len: usize) -> Instant {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let deadline = started + Duration::from_secs_f64(self.sent as f64 / self.bytes_per_sec as f64);
+        self.sent += len as u64;
+        deadline
+    }
+}
+
+/// 限速时每段的大小：约十分之一秒的数据量，使发送尽量平滑
+fn piece_size(bytes_per_sec: u64) -> usize {
+    (bytes_per_sec / 10).clamp(1, MAX_PIECE as u64) as usize
+}
+
+/// 把响应体包装为按 `bytes_per_sec` 字节/秒发送的流，大块数据先拆成小段再逐段按时发送
+pub fn throttle_body(body: ProxyBody, bytes_per_sec: u64) -> ProxyBody {
+    let piece = piece_size(bytes_per_sec);
+    let mut pacer = Pacer::new(bytes_per_sec);
+
+    let stream = body
+        .into_data_stream()
+        .map_ok(move |chunk: Bytes| {
+            let pieces: Vec<Bytes> = (0..chunk.len())
+                .step_by(piece)
+                .map(|start| chunk.slice(start..(start + piece).min(chunk.len())))
+                .collect();
+            futures_util::stream::iter(pieces.into_iter().map(Ok))
+        })
+        .try_flatten()
+        .and_then(move |piece| {
+            let deadline = pacer.next_deadline(piece.len());
+            async move {
+                tokio::time::sleep_until(deadline).await;
+                Ok(piece)
+            }
+        })
+        .map_ok(Frame::data);
+    StreamBody::new(stream).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::full;
+
+    #[tokio::test]
+    async fn test_throttled_body_takes_expected_time() {
+        let data = vec![7u8; 150_000];
+        let started = std::time::Instant::now();
+        let body = throttle_body(full(data.clone()), 100_000).collect().await.unwrap().to_bytes();
+
+        assert_eq!(body.as_ref(), data.as_slice());
+        // 最后一段在 (150000 - 10000) / 100000 = 1.4 秒时发送
+        assert!(started.elapsed() >= Duration::from_millis(1400), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn test_piece_size() {
+        assert_eq!(piece_size(5), 1);
+        assert_eq!(piece_size(100_000), 10_000);
+        assert_eq!(piece_size(100 * 1024 * 1024), MAX_PIECE);
+    }
+}
@@ -1,15 +1,23 @@
-use crate::config::Config;
-use melange_db::{Db, Config as DbConfig, Tree};
+use crate::cache::{newest_first, VERSIONS_DB_DIR};
+use crate::clock;
+use crate::config::{Config, DuplicateVersionPolicy};
+use melange_db::{Batch, Db, Config as DbConfig, Tree};
+use rand::Rng;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::fs;
 use std::io;
-use std::path::Path;
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
 /// 版本信息数据结构
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VersionInfo {
     /// 版本号
     pub version: String,
@@ -23,10 +31,16 @@ pub struct VersionInfo {
     pub created_at: u64,
     /// 过期时间戳
     pub expires_at: u64,
+    /// 累计下载次数，每次成功返回该版本的包文件时加一，刷新版本信息时保留
+    #[serde(default)]
+    pub download_count: u64,
+    /// 通过 `/api/v1/crates/new` 本地发布的版本，不会过期，也不会被上游数据覆盖
+    #[serde(default)]
+    pub local: bool,
 }
 
 /// 包的最新版本映射
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LatestVersionMapping {
     /// 包名
     pub crate_name: String,
@@ -38,6 +52,92 @@ pub struct LatestVersionMapping {
     pub expires_at: u64,
 }
 
+/// 一个包的一条版本信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRecord {
+    pub crate_name: String,
+    pub info: VersionInfo,
+}
+
+/// 已从注册表删除的包的标记
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub crate_name: String,
+    /// 标记时间戳
+    pub created_at: u64,
+}
+
+/// 版本数据库的完整导出，用于迁移到其他实例
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionDbDump {
+    pub versions: Vec<VersionRecord>,
+    pub latest: Vec<LatestVersionMapping>,
+}
+
+/// 版本数据库记录的编码格式，写在每条记录的第一个字节。新增字段时需要升级格式并提供迁移
+const RECORD_FORMAT_BINCODE: u8 = 1;
+
+/// 元数据树中记录当前编码格式的键
+const RECORD_FORMAT_KEY: &[u8] = b"record_format";
+
+/// 编码一条记录：格式标记 + bincode
+fn encode_record<T: Serialize>(record: &T) -> Result<Vec<u8>, VersionManagerError> {
+    let mut data = vec![RECORD_FORMAT_BINCODE];
+    bincode::serialize_into(&mut data, record)?;
+    Ok(data)
+}
+
+/// 解码一条记录，兼容旧版本写入的JSON记录（以 `{` 开头）
+fn decode_record<T: DeserializeOwned>(data: &[u8]) -> Result<T, VersionManagerError> {
+    match data.first() {
+        Some(&RECORD_FORMAT_BINCODE) => Ok(bincode::deserialize(&data[1..])?),
+        Some(b'{') => Ok(serde_json::from_slice(data)?),
+        Some(&tag) => Err(VersionManagerError::UnknownRecordFormat(tag)),
+        None => Err(VersionManagerError::UnknownRecordFormat(0)),
+    }
+}
+
+/// 版本信息键锁的分片数
+const KEY_LOCK_SHARDS: usize = 64;
+
+/// 按键哈希分片的锁：同一个键的读-改-写串行执行，不同分片之间互不阻塞
+struct KeyLocks {
+    shards: Vec<Mutex<()>>,
+}
+
+impl KeyLocks {
+    fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    fn shard(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() as usize) % self.shards.len()
+    }
+
+    fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        self.shards[self.shard(key)].lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 锁住多个键所在的分片，按分片序号加锁以避免死锁
+    fn lock_all<'a>(&self, keys: impl IntoIterator<Item = &'a String>) -> Vec<MutexGuard<'_, ()>> {
+        let mut shards: Vec<usize> = keys.into_iter().map(|key| self.shard(key)).collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+            .into_iter()
+            .map(|shard| self.shards[shard].lock().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+}
+
+fn version_key(crate_name: &str, version: &str) -> String {
+    format!("{}:{}", crate_name, version)
+}
+
 /// MelangeDB版本管理器
 pub struct VersionManager {
     /// 数据库实例
@@ -46,10 +146,32 @@ pub struct VersionManager {
     versions_tree: Arc<Tree<1024>>,
     /// 最新版本映射树
     latest_tree: Arc<Tree<1024>>,
+    /// 损坏记录的隔离树，键为 `{树名}/{原键}`，值为原始内容
+    quarantine_tree: Arc<Tree<1024>>,
+    /// 已从注册表删除的包，键为包名
+    tombstones_tree: Arc<Tree<1024>>,
     /// 内存缓存（用于快速访问）
     memory_cache: Arc<RwLock<HashMap<String, String>>>,
-    /// 默认TTL
-    default_ttl: Duration,
+    /// 默认TTL（秒），可在配置重载时调整
+    default_ttl: AtomicU64,
+    /// 数据库写操作次数（单条写入和批量提交各计一次）
+    write_ops: AtomicU64,
+    /// 过期判断允许的时钟偏差（秒）
+    clock_skew_tolerance: u64,
+    /// 过期时间的随机抖动（TTL的百分比）
+    ttl_jitter_pct: u64,
+    /// 叠加在系统时钟上的偏移（秒），用于模拟时钟跳变
+    clock_offset_secs: AtomicI64,
+    /// 版本信息的键锁，保护读-改-写序列
+    key_locks: KeyLocks,
+    /// 是否把损坏的记录移入隔离区
+    repair_corrupt_entries: bool,
+    /// 累计发现的损坏记录数
+    corrupt_entries: AtomicU64,
+    /// 每个包最多保存的版本数，0表示不限制
+    max_versions_per_crate: usize,
+    /// 重新缓存已有版本信息时的处理方式
+    duplicate_version_policy: DuplicateVersionPolicy,
 }
 
 #[derive(Debug, Error)]
@@ -58,54 +180,209 @@ pub enum VersionManagerError {
     DatabaseError(#[from] io::Error),
     #[error("序列化错误: {0}")]
     SerializationError(#[from] serde_json::Error),
-    #[error("系统时间错误: {0}")]
-    SystemTimeError(#[from] std::time::SystemTimeError),
+    #[error("二进制编码错误: {0}")]
+    EncodingError(#[from] bincode::Error),
+    #[error("未知的记录格式: {0:#04x}")]
+    UnknownRecordFormat(u8),
     #[error("数据过期: {0}")]
+    #[allow(dead_code)]
     ExpiredError(String),
     #[error("数据不存在: {0}")]
+    #[allow(dead_code)]
     NotFoundError(String),
+    #[error("{0} 是版本解析关键字，不能作为版本号保存")]
+    ReservedVersion(String),
+}
+
+/// 请求路径中表示"解析为最新版本"的关键字，不是具体的版本号。
+/// 解析后缓存文件和版本信息都以具体版本为键，数据库中不会出现以它为版本号的记录
+pub const LATEST: &str = "latest";
+
+/// 版本数据库的配置：缓存容量取自 `cache.db_cache_capacity_bytes`
+fn db_config(config: &Config, db_path: &Path) -> DbConfig {
+    let mut db_config = DbConfig::new()
+        .path(db_path)
+        .cache_capacity_bytes(config.cache.db_cache_capacity_bytes)
+        .flush_every_ms(Some(5000)); // 5秒flush间隔
+
+    // 启用智能flush策略
+    db_config.smart_flush_config.enabled = true;
+    db_config.smart_flush_config.base_interval_ms = 5000;
+    db_config.smart_flush_config.min_interval_ms = 1000;
+    db_config.smart_flush_config.max_interval_ms = 30000;
+    db_config
 }
 
 impl VersionManager {
     /// 创建新的版本管理器
     pub fn new(config: &Config) -> Result<Self, VersionManagerError> {
-        let db_path = Path::new(&config.cache.storage_path).join("versions_db");
-
-        // 创建数据库配置
-        let mut db_config = DbConfig::new()
-            .path(&db_path)
-            .cache_capacity_bytes(100 * 1024 * 1024) // 100MB缓存
-            .flush_every_ms(Some(5000)); // 5秒flush间隔
-
-        // 启用智能flush策略
-        db_config.smart_flush_config.enabled = true;
-        db_config.smart_flush_config.base_interval_ms = 5000;
-        db_config.smart_flush_config.min_interval_ms = 1000;
-        db_config.smart_flush_config.max_interval_ms = 30000;
+        let mut db_path = Path::new(&config.cache.storage_path).join(VERSIONS_DB_DIR);
+        if config.cache.read_only {
+            db_path = read_only_db_copy(&db_path)?;
+        }
 
         // 创建数据库
-        let db = Arc::new(db_config.open()?);
+        let db = Arc::new(db_config(config, &db_path).open()?);
 
         // 打开数据树
         let versions_tree = Arc::new(db.open_tree(b"versions")?);
         let latest_tree = Arc::new(db.open_tree(b"latest_versions")?);
+        let quarantine_tree = Arc::new(db.open_tree(b"quarantine")?);
+        let tombstones_tree = Arc::new(db.open_tree(b"tombstones")?);
+        let meta_tree = db.open_tree(b"meta")?;
 
-        rat_logger::info!("版本管理器初始化成功，数据库路径: {:?}", db_path);
+        rat_logger::info!(
+            "版本管理器初始化成功，数据库路径: {:?}，缓存容量: {} 字节",
+            db_path, config.cache.db_cache_capacity_bytes
+        );
 
-        Ok(Self {
+        let manager = Self {
             db,
             versions_tree,
             latest_tree,
+            quarantine_tree,
+            tombstones_tree,
             memory_cache: Arc::new(RwLock::new(HashMap::new())),
-            default_ttl: Duration::from_secs(config.cache.default_ttl),
-        })
+            default_ttl: AtomicU64::new(config.cache.default_ttl),
+            write_ops: AtomicU64::new(0),
+            clock_skew_tolerance: config.cache.clock_skew_tolerance,
+            ttl_jitter_pct: config.cache.ttl_jitter_pct.min(100),
+            clock_offset_secs: AtomicI64::new(0),
+            key_locks: KeyLocks::new(KEY_LOCK_SHARDS),
+            repair_corrupt_entries: config.cache.repair_corrupt_entries,
+            corrupt_entries: AtomicU64::new(0),
+            max_versions_per_crate: config.cache.max_versions_per_crate,
+            duplicate_version_policy: config.cache.duplicate_version_policy,
+        };
+
+        if meta_tree.get(RECORD_FORMAT_KEY)?.as_deref() != Some(&[RECORD_FORMAT_BINCODE][..]) {
+            manager.migrate_records()?;
+            meta_tree.insert(RECORD_FORMAT_KEY, vec![RECORD_FORMAT_BINCODE])?;
+        }
+
+        Ok(manager)
+    }
+
+    /// 把旧版本写入的JSON记录转换为当前的二进制格式，返回转换的条数。无法解析的记录保持原样
+    fn migrate_records(&self) -> Result<usize, VersionManagerError> {
+        let mut migrated = 0;
+        migrated += Self::migrate_tree::<VersionInfo>(&self.versions_tree)?;
+        migrated += Self::migrate_tree::<LatestVersionMapping>(&self.latest_tree)?;
+        if migrated > 0 {
+            self.write_ops.fetch_add(2, Ordering::Relaxed);
+            rat_logger::info!("版本数据库已迁移为二进制格式，转换 {} 条记录", migrated);
+        }
+        Ok(migrated)
+    }
+
+    fn migrate_tree<T: Serialize + DeserializeOwned>(tree: &Tree<1024>) -> Result<usize, VersionManagerError> {
+        let mut batch = Batch::default();
+        let mut count = 0;
+        for kv in tree.iter() {
+            let (key, value) = kv?;
+            if value.first() != Some(&b'{') {
+                continue;
+            }
+            match serde_json::from_slice::<T>(&value) {
+                Ok(record) => {
+                    batch.insert(&*key, encode_record(&record)?);
+                    count += 1;
+                }
+                Err(e) => rat_logger::warn!("迁移时跳过无法解析的记录 {}: {}", String::from_utf8_lossy(&key), e),
+            }
+        }
+        if count > 0 {
+            tree.apply_batch(batch)?;
+        }
+        Ok(count)
+    }
+
+    /// 当前Unix时间（秒），系统时钟异常时不返回错误
+    fn now_secs(&self) -> u64 {
+        let offset = self.clock_offset_secs.load(Ordering::Relaxed);
+        let now = if offset >= 0 {
+            SystemTime::now() + Duration::from_secs(offset as u64)
+        } else {
+            SystemTime::now() - Duration::from_secs(offset.unsigned_abs())
+        };
+        clock::unix_secs(now)
+    }
+
+    /// 调整叠加在系统时钟上的偏移，模拟时钟向前或向后跳变
+    #[cfg(test)]
+    pub fn set_clock_offset(&self, offset_secs: i64) {
+        self.clock_offset_secs.store(offset_secs, Ordering::Relaxed);
+    }
+
+    /// 按配置的时钟偏差容忍度判断记录是否过期
+    fn is_expired(&self, now: u64, created_at: u64, expires_at: u64) -> bool {
+        clock::is_expired(now, created_at, expires_at, self.clock_skew_tolerance)
+    }
+
+    /// 解析树中的一条记录。损坏的记录记录日志后跳过，开启 `repair_corrupt_entries` 时移入隔离区，
+    /// 避免一条坏记录导致整个遍历失败
+    fn decode_entry<T: DeserializeOwned>(
+        &self,
+        tree: &Tree<1024>,
+        tree_name: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<T>, VersionManagerError> {
+        let error = match decode_record(value) {
+            Ok(entry) => return Ok(Some(entry)),
+            Err(e) => e,
+        };
+
+        self.corrupt_entries.fetch_add(1, Ordering::Relaxed);
+        let key = String::from_utf8_lossy(key);
+        rat_logger::warn!("跳过损坏的记录 {}/{}: {}", tree_name, key, error);
+        if self.repair_corrupt_entries {
+            let quarantine_key = format!("{}/{}", tree_name, key);
+            self.quarantine_tree.insert(quarantine_key.as_bytes(), value.to_vec())?;
+            tree.remove(key.as_bytes())?;
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+            rat_logger::warn!("已将损坏的记录移入隔离区: {}", quarantine_key);
+        }
+        Ok(None)
+    }
+
+    /// 按默认TTL计算过期时间，并在 ±ttl_jitter_pct% 范围内随机抖动
+    fn expires_at(&self, now: u64) -> u64 {
+        let ttl = self.default_ttl();
+        let jitter = ttl.saturating_mul(self.ttl_jitter_pct) / 100;
+        let offset = if jitter > 0 {
+            rand::thread_rng().gen_range(0..=jitter * 2)
+        } else {
+            0
+        };
+        now.saturating_add(ttl - jitter).saturating_add(offset)
+    }
+
+    /// 当前的默认TTL（秒）
+    pub fn default_ttl(&self) -> u64 {
+        self.default_ttl.load(Ordering::Relaxed)
+    }
+
+    /// 运行时调整默认TTL，仅影响之后写入的数据
+    pub fn set_default_ttl(&self, ttl: u64) {
+        self.default_ttl.store(ttl, Ordering::Relaxed);
+    }
+
+    /// 内存缓存的读锁。持锁的线程panic后锁会中毒，其中只有可从数据库重建的数据，直接沿用
+    fn cache_read(&self) -> RwLockReadGuard<'_, HashMap<String, String>> {
+        self.memory_cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 内存缓存的写锁，中毒时同样沿用
+    fn cache_write(&self) -> RwLockWriteGuard<'_, HashMap<String, String>> {
+        self.memory_cache.write().unwrap_or_else(PoisonError::into_inner)
     }
 
     /// 获取包的最新版本号
     pub fn get_latest_version(&self, crate_name: &str) -> Result<Option<String>, VersionManagerError> {
         // 首先检查内存缓存
         {
-            let cache = self.memory_cache.read().unwrap();
+            let cache = self.cache_read();
             if let Some(version) = cache.get(crate_name) {
                 rat_logger::debug!("从内存缓存获取版本: {} -> {}", crate_name, version);
                 return Ok(Some(version.clone()));
@@ -114,12 +391,13 @@ impl VersionManager {
 
         // 检查数据库
         let key = crate_name.as_bytes();
-        if let Some(data) = self.latest_tree.get(key)? {
-            let mapping: LatestVersionMapping = serde_json::from_slice(&data)?;
+        if let Some(data) = self.latest_tree.get(key)?
+            && let Some(mapping) = self.decode_entry::<LatestVersionMapping>(&self.latest_tree, "latest_versions", key, &data)?
+        {
 
             // 检查是否过期
-            let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-            if current_time > mapping.expires_at {
+            let current_time = self.now_secs();
+            if self.is_expired(current_time, mapping.updated_at, mapping.expires_at) {
                 rat_logger::warn!("最新版本映射已过期: {} -> {}", crate_name, mapping.latest_version);
                 self.latest_tree.remove(key)?;
                 return Ok(None);
@@ -127,7 +405,7 @@ impl VersionManager {
 
             // 更新内存缓存
             {
-                let mut cache = self.memory_cache.write().unwrap();
+                let mut cache = self.cache_write();
                 cache.insert(crate_name.to_string(), mapping.latest_version.clone());
             }
 
@@ -140,8 +418,11 @@ impl VersionManager {
 
     /// 设置包的最新版本号
     pub fn set_latest_version(&self, crate_name: &str, version: &str) -> Result<(), VersionManagerError> {
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-        let expires_at = current_time + self.default_ttl.as_secs();
+        if version == LATEST {
+            return Err(VersionManagerError::ReservedVersion(version.to_string()));
+        }
+        let current_time = self.now_secs();
+        let expires_at = self.expires_at(current_time);
 
         let mapping = LatestVersionMapping {
             crate_name: crate_name.to_string(),
@@ -150,28 +431,36 @@ impl VersionManager {
             expires_at,
         };
 
-        let data = serde_json::to_vec(&mapping)?;
+        let data = encode_record(&mapping)?;
         self.latest_tree.insert(crate_name.as_bytes(), data)?;
+        self.write_ops.fetch_add(1, Ordering::Relaxed);
 
         // 更新内存缓存
         {
-            let mut cache = self.memory_cache.write().unwrap();
+            let mut cache = self.cache_write();
             cache.insert(crate_name.to_string(), version.to_string());
         }
 
-        rat_logger::info!("设置最新版本: {} -> {} (TTL: {}s)", crate_name, version, self.default_ttl.as_secs());
+        rat_logger::info!("设置最新版本: {} -> {} (TTL: {}s)", crate_name, version, self.default_ttl());
         Ok(())
     }
 
     /// 获取版本信息
     pub fn get_version_info(&self, crate_name: &str, version: &str) -> Result<Option<VersionInfo>, VersionManagerError> {
-        let key = format!("{}:{}", crate_name, version);
-        if let Some(data) = self.versions_tree.get(key.as_bytes())? {
-            let version_info: VersionInfo = serde_json::from_slice(&data)?;
+        let key = version_key(crate_name, version);
+        let _guard = self.key_locks.lock(&key);
+        self.read_version_info(crate_name, version, &key)
+    }
+
+    /// 读取未过期的版本信息，过期时删除。调用方需持有该键的锁
+    fn read_version_info(&self, crate_name: &str, version: &str, key: &str) -> Result<Option<VersionInfo>, VersionManagerError> {
+        if let Some(data) = self.versions_tree.get(key.as_bytes())?
+            && let Some(version_info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", key.as_bytes(), &data)?
+        {
 
             // 检查是否过期
-            let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-            if current_time > version_info.expires_at {
+            let current_time = self.now_secs();
+            if self.is_expired(current_time, version_info.created_at, version_info.expires_at) {
                 rat_logger::warn!("版本信息已过期: {}:{} -> {}", crate_name, version, version_info.version);
                 self.versions_tree.remove(key.as_bytes())?;
                 return Ok(None);
@@ -185,24 +474,165 @@ impl VersionManager {
 
     /// 设置版本信息
     pub fn set_version_info(&self, crate_name: &str, version: &str, version_info: VersionInfo) -> Result<(), VersionManagerError> {
-        let key = format!("{}:{}", crate_name, version);
-        let data = serde_json::to_vec(&version_info)?;
+        if version == LATEST || version_info.version == LATEST {
+            return Err(VersionManagerError::ReservedVersion(LATEST.to_string()));
+        }
+        let key = version_key(crate_name, version);
+        let _guard = self.key_locks.lock(&key);
+        let mut version_info = version_info;
+        if self.duplicate_version_policy != DuplicateVersionPolicy::Overwrite
+            && let Some(data) = self.versions_tree.get(key.as_bytes())?
+            && let Ok(existing) = decode_record::<VersionInfo>(&data)
+            && !self.apply_duplicate_policy(&existing, &mut version_info)
+        {
+            rat_logger::debug!("版本信息没有变化，保留原记录: {}:{}", crate_name, version);
+            return Ok(());
+        }
+        let data = encode_record(&version_info)?;
         self.versions_tree.insert(key.as_bytes(), data)?;
+        self.write_ops.fetch_add(1, Ordering::Relaxed);
 
         rat_logger::info!("设置版本信息: {}:{} -> {}", crate_name, version, version_info.version);
         Ok(())
     }
 
+    /// 在键锁内读取、修改并写回版本信息，记录不存在或已过期时返回None
+    pub fn update_version_info<F>(&self, crate_name: &str, version: &str, update: F) -> Result<Option<VersionInfo>, VersionManagerError>
+    where
+        F: FnOnce(&mut VersionInfo),
+    {
+        let key = version_key(crate_name, version);
+        let _guard = self.key_locks.lock(&key);
+
+        let Some(mut version_info) = self.read_version_info(crate_name, version, &key)? else {
+            return Ok(None);
+        };
+        update(&mut version_info);
+        self.versions_tree.insert(key.as_bytes(), encode_record(&version_info)?)?;
+        self.write_ops.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(version_info))
+    }
+
+    /// 版本的下载次数加一，返回更新后的次数
+    pub fn record_download(&self, crate_name: &str, version: &str) -> Result<Option<u64>, VersionManagerError> {
+        let updated = self.update_version_info(crate_name, version, |info| info.download_count += 1)?;
+        Ok(updated.map(|info| info.download_count))
+    }
+
+    /// 批量写入同一个包的多个版本信息
+    ///
+    /// 所有记录在一次原子批处理中提交，要么全部写入，要么全部不写入。
+    /// 序列化失败的单条记录会被跳过并记录警告，返回实际写入的条数。
+    pub fn set_version_infos(&self, crate_name: &str, version_infos: &[VersionInfo]) -> Result<usize, VersionManagerError> {
+        let mut batch = Batch::default();
+        let mut count = 0;
+
+        let keys: Vec<String> = version_infos
+            .iter()
+            .map(|version_info| version_key(crate_name, &version_info.version))
+            .collect();
+        let _guards = self.key_locks.lock_all(&keys);
+
+        let evicted = self.versions_over_limit(crate_name, version_infos)?;
+        for version in &evicted {
+            batch.remove(version_key(crate_name, version).as_bytes());
+        }
+
+        for (key, version_info) in keys.iter().zip(version_infos) {
+            if evicted.contains(&version_info.version) {
+                continue;
+            }
+            if version_info.version == LATEST {
+                rat_logger::warn!("跳过版本号为 {} 的记录: {}", LATEST, crate_name);
+                continue;
+            }
+            // 刷新版本信息（如撤销状态）时保留已累计的下载次数，本地发布的版本保持不变
+            let mut version_info = version_info.clone();
+            if let Some(data) = self.versions_tree.get(key.as_bytes())?
+                && let Ok(existing) = decode_record::<VersionInfo>(&data)
+            {
+                if existing.local || !self.apply_duplicate_policy(&existing, &mut version_info) {
+                    continue;
+                }
+                version_info.download_count = existing.download_count;
+            }
+
+            match encode_record(&version_info) {
+                Ok(data) => {
+                    batch.insert(key.as_bytes(), data);
+                    count += 1;
+                }
+                Err(e) => {
+                    rat_logger::warn!("序列化版本信息失败 {}: {}", key, e);
+                }
+            }
+        }
+
+        if count == 0 && evicted.is_empty() {
+            return Ok(0);
+        }
+
+        self.versions_tree.apply_batch(batch)?;
+        self.write_ops.fetch_add(1, Ordering::Relaxed);
+
+        if !evicted.is_empty() {
+            rat_logger::info!("包 {} 超过版本数上限 {}，移除 {} 个最旧的版本信息", crate_name, self.max_versions_per_crate, evicted.len());
+        }
+        rat_logger::info!("批量写入包 {} 的 {} 个版本信息", crate_name, count);
+        Ok(count)
+    }
+
+    /// 按 `duplicate_version_policy` 用已有记录调整待写入的记录，返回false表示保留原记录、不需要写入。
+    /// 已过期的记录视为不存在
+    fn apply_duplicate_policy(&self, existing: &VersionInfo, version_info: &mut VersionInfo) -> bool {
+        if self.duplicate_version_policy == DuplicateVersionPolicy::Overwrite
+            || self.is_expired(self.now_secs(), existing.created_at, existing.expires_at)
+        {
+            return true;
+        }
+        let unchanged = existing.checksum == version_info.checksum
+            && existing.yanked == version_info.yanked
+            && existing.download_path == version_info.download_path;
+        if self.duplicate_version_policy == DuplicateVersionPolicy::SkipUnchanged && unchanged {
+            return false;
+        }
+        version_info.created_at = existing.created_at;
+        true
+    }
+
+    /// 写入新版本后超出 `max_versions_per_crate` 的旧版本号（包括待写入的和已有的），本地发布的版本不计入也不移除
+    fn versions_over_limit(&self, crate_name: &str, version_infos: &[VersionInfo]) -> Result<HashSet<String>, VersionManagerError> {
+        if self.max_versions_per_crate == 0 {
+            return Ok(HashSet::new());
+        }
+
+        let mut versions: HashSet<String> = version_infos.iter().map(|info| info.version.clone()).collect();
+        let prefix = format!("{}:", crate_name);
+        for kv in self.versions_tree.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = kv?;
+            let version = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            if decode_record::<VersionInfo>(&value).is_ok_and(|info| info.local) {
+                versions.remove(&version);
+                continue;
+            }
+            versions.insert(version);
+        }
+
+        let mut versions: Vec<String> = versions.into_iter().collect();
+        versions.sort_by(|a, b| newest_first(a, b));
+        Ok(versions.into_iter().skip(self.max_versions_per_crate).collect())
+    }
+
     /// 获取包的所有版本
     pub fn get_all_versions(&self, crate_name: &str) -> Result<Vec<VersionInfo>, VersionManagerError> {
         let prefix = format!("{}:", crate_name);
         let mut versions = Vec::new();
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let current_time = self.now_secs();
 
         for kv in self.versions_tree.scan_prefix(prefix.as_bytes()) {
             let (key, value) = kv?;
-            if let Ok(version_info) = serde_json::from_slice::<VersionInfo>(&value) {
-                if current_time <= version_info.expires_at {
+            if let Some(version_info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", &key, &value)? {
+                if !self.is_expired(current_time, version_info.created_at, version_info.expires_at) {
                     versions.push(version_info);
                 } else {
                     // 清理过期数据
@@ -216,6 +646,7 @@ impl VersionManager {
     }
 
     /// 创建版本信息
+    #[allow(dead_code)]
     pub fn create_version_info(
         &self,
         crate_name: &str,
@@ -224,50 +655,115 @@ impl VersionManager {
         checksum: &str,
         yanked: bool,
     ) -> Result<VersionInfo, VersionManagerError> {
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-        let expires_at = current_time + self.default_ttl.as_secs();
+        let version_info = self.build_version_info(version, download_path, checksum, yanked)?;
+        self.set_version_info(crate_name, version, version_info.clone())?;
+        Ok(version_info)
+    }
 
-        let version_info = VersionInfo {
+    /// 构造带有当前TTL的版本信息，不写入数据库
+    pub fn build_version_info(
+        &self,
+        version: &str,
+        download_path: &str,
+        checksum: &str,
+        yanked: bool,
+    ) -> Result<VersionInfo, VersionManagerError> {
+        let current_time = self.now_secs();
+        let expires_at = self.expires_at(current_time);
+
+        Ok(VersionInfo {
             version: version.to_string(),
             download_path: download_path.to_string(),
             checksum: checksum.to_string(),
             yanked,
             created_at: current_time,
             expires_at,
-        };
+            download_count: 0,
+            local: false,
+        })
+    }
 
+    /// 记录一个本地发布的版本，返回写入的版本信息
+    pub fn publish_local_version(&self, crate_name: &str, version: &str, checksum: &str) -> Result<VersionInfo, VersionManagerError> {
+        let version_info = VersionInfo {
+            version: version.to_string(),
+            download_path: format!("/api/v1/crates/{}/{}/download", crate_name, version),
+            checksum: checksum.to_string(),
+            yanked: false,
+            created_at: self.now_secs(),
+            expires_at: u64::MAX,
+            download_count: 0,
+            local: true,
+        };
         self.set_version_info(crate_name, version, version_info.clone())?;
         Ok(version_info)
     }
 
+    /// 删除一条版本记录，返回记录是否存在
+    pub fn remove_version(&self, crate_name: &str, version: &str) -> Result<bool, VersionManagerError> {
+        let key = version_key(crate_name, version);
+        let _guard = self.key_locks.lock(&key);
+        let removed = self.versions_tree.remove(key.as_bytes())?.is_some();
+        if removed {
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(removed)
+    }
+
+    /// 数据库中全部本地发布版本的记录
+    pub fn local_version_records(&self) -> Result<Vec<VersionRecord>, VersionManagerError> {
+        let mut records = Vec::new();
+        for kv in self.versions_tree.iter() {
+            let (key, value) = kv?;
+            let key = String::from_utf8_lossy(&key);
+            let Some((crate_name, _)) = key.split_once(':') else { continue };
+            if let Some(info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", key.as_bytes(), &value)?
+                && info.local
+            {
+                records.push(VersionRecord {
+                    crate_name: crate_name.to_string(),
+                    info,
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    /// 包的所有本地发布版本
+    pub fn get_local_versions(&self, crate_name: &str) -> Result<Vec<VersionInfo>, VersionManagerError> {
+        let mut versions = self.get_all_versions(crate_name)?;
+        versions.retain(|info| info.local);
+        Ok(versions)
+    }
+
     /// 清理过期数据
     pub fn cleanup_expired_data(&self) -> Result<usize, VersionManagerError> {
         let mut cleaned_count = 0;
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let current_time = self.now_secs();
 
         // 清理过期版本信息
         for kv in self.versions_tree.iter() {
             let (key, value) = kv?;
-            if let Ok(version_info) = serde_json::from_slice::<VersionInfo>(&value) {
-                if current_time > version_info.expires_at {
-                    self.versions_tree.remove(&key)?;
-                    cleaned_count += 1;
-                }
+            if let Some(version_info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", &key, &value)?
+                && self.is_expired(current_time, version_info.created_at, version_info.expires_at)
+            {
+                self.versions_tree.remove(&key)?;
+                cleaned_count += 1;
             }
         }
 
         // 清理过期最新版本映射
         for kv in self.latest_tree.iter() {
             let (key, value) = kv?;
-            if let Ok(mapping) = serde_json::from_slice::<LatestVersionMapping>(&value) {
-                if current_time > mapping.expires_at {
-                    self.latest_tree.remove(&key)?;
-                    cleaned_count += 1;
-
-                    // 同时清理内存缓存
-                    let mut cache = self.memory_cache.write().unwrap();
-                    cache.remove(&mapping.crate_name);
-                }
+            if let Some(mapping) = self.decode_entry::<LatestVersionMapping>(&self.latest_tree, "latest_versions", &key, &value)?
+                && self.is_expired(current_time, mapping.updated_at, mapping.expires_at)
+            {
+                self.latest_tree.remove(&key)?;
+                cleaned_count += 1;
+
+                // 同时清理内存缓存
+                let mut cache = self.cache_write();
+                cache.remove(&mapping.crate_name);
             }
         }
 
@@ -279,18 +775,19 @@ impl VersionManager {
     }
 
     /// 获取统计信息
+    #[allow(dead_code)]
     pub fn get_stats(&self) -> Result<VersionManagerStats, VersionManagerError> {
         let mut latest_count = 0;
         let mut version_count = 0;
         let mut expired_count = 0;
-        let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let current_time = self.now_secs();
 
         // 统计最新版本映射
         for kv in self.latest_tree.iter() {
-            let (_, value) = kv?;
-            if let Ok(mapping) = serde_json::from_slice::<LatestVersionMapping>(&value) {
+            let (key, value) = kv?;
+            if let Some(mapping) = self.decode_entry::<LatestVersionMapping>(&self.latest_tree, "latest_versions", &key, &value)? {
                 latest_count += 1;
-                if current_time > mapping.expires_at {
+                if self.is_expired(current_time, mapping.updated_at, mapping.expires_at) {
                     expired_count += 1;
                 }
             }
@@ -298,25 +795,203 @@ impl VersionManager {
 
         // 统计版本信息
         for kv in self.versions_tree.iter() {
-            let (_, value) = kv?;
-            if let Ok(version_info) = serde_json::from_slice::<VersionInfo>(&value) {
+            let (key, value) = kv?;
+            if let Some(version_info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", &key, &value)? {
                 version_count += 1;
-                if current_time > version_info.expires_at {
+                if self.is_expired(current_time, version_info.created_at, version_info.expires_at) {
                     expired_count += 1;
                 }
             }
         }
 
-        let memory_cache_size = self.memory_cache.read().unwrap().len();
+        let memory_cache_size = self.cache_read().len();
 
         Ok(VersionManagerStats {
             latest_mappings_count: latest_count,
             versions_count: version_count,
             expired_count,
             memory_cache_size,
+            write_ops: self.write_ops.load(Ordering::Relaxed),
+            corrupt_entries: self.corrupt_entries.load(Ordering::Relaxed),
         })
     }
 
+    /// 导出数据库中的全部记录（包括已过期的），保留原有的时间戳
+    pub fn dump(&self) -> Result<VersionDbDump, VersionManagerError> {
+        let mut dump = VersionDbDump::default();
+
+        for kv in self.versions_tree.iter() {
+            let (key, value) = kv?;
+            let key = String::from_utf8_lossy(&key);
+            let Some((crate_name, _)) = key.split_once(':') else {
+                rat_logger::warn!("跳过格式错误的版本记录: {}", key);
+                continue;
+            };
+            if let Some(info) = self.decode_entry(&self.versions_tree, "versions", key.as_bytes(), &value)? {
+                dump.versions.push(VersionRecord {
+                    crate_name: crate_name.to_string(),
+                    info,
+                });
+            }
+        }
+
+        for kv in self.latest_tree.iter() {
+            let (key, value) = kv?;
+            if let Some(mapping) = self.decode_entry(&self.latest_tree, "latest_versions", &key, &value)? {
+                dump.latest.push(mapping);
+            }
+        }
+
+        Ok(dump)
+    }
+
+    /// 导入导出的记录，覆盖同名记录，返回导入的条数
+    pub fn restore(&self, dump: &VersionDbDump) -> Result<usize, VersionManagerError> {
+        let mut versions = Batch::default();
+        for record in &dump.versions {
+            let key = format!("{}:{}", record.crate_name, record.info.version);
+            versions.insert(key.as_bytes(), encode_record(&record.info)?);
+        }
+        self.versions_tree.apply_batch(versions)?;
+
+        let mut latest = Batch::default();
+        for mapping in &dump.latest {
+            latest.insert(mapping.crate_name.as_bytes(), encode_record(mapping)?);
+        }
+        self.latest_tree.apply_batch(latest)?;
+        self.write_ops.fetch_add(2, Ordering::Relaxed);
+
+        // 内存缓存优先于数据库，清除导入涉及的包，之后从数据库重新读取
+        {
+            let mut cache = self.cache_write();
+            for mapping in &dump.latest {
+                cache.remove(&mapping.crate_name);
+            }
+        }
+
+        Ok(dump.versions.len() + dump.latest.len())
+    }
+
+    /// 把一个包名下的全部记录（包括已过期的）合并到另一个包名下，返回移动的版本记录数。
+    /// 目标已有同版本记录时保留目标记录并累加下载次数；最新版本映射保留更新时间较新的一条
+    pub fn merge_crate(&self, from: &str, to: &str) -> Result<usize, VersionManagerError> {
+        let prefix = format!("{}:", from);
+        let mut sources = Vec::new();
+        for kv in self.versions_tree.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = kv?;
+            if let Some(info) = self.decode_entry::<VersionInfo>(&self.versions_tree, "versions", &key, &value)? {
+                sources.push(info);
+            }
+        }
+
+        let mut keys = Vec::new();
+        for info in &sources {
+            keys.push(version_key(from, &info.version));
+            keys.push(version_key(to, &info.version));
+        }
+        let _guards = self.key_locks.lock_all(&keys);
+
+        let mut batch = Batch::default();
+        for info in &sources {
+            let target_key = version_key(to, &info.version);
+            let merged = match self.versions_tree.get(target_key.as_bytes())? {
+                Some(data) => match decode_record::<VersionInfo>(&data) {
+                    Ok(mut existing) => {
+                        existing.download_count += info.download_count;
+                        existing
+                    }
+                    Err(_) => info.clone(),
+                },
+                None => info.clone(),
+            };
+            batch.insert(target_key.as_bytes(), encode_record(&merged)?);
+            batch.remove(version_key(from, &info.version).as_bytes());
+        }
+        if !sources.is_empty() {
+            self.versions_tree.apply_batch(batch)?;
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(data) = self.latest_tree.get(from.as_bytes())? {
+            if let Some(mut mapping) = self.decode_entry::<LatestVersionMapping>(&self.latest_tree, "latest_versions", from.as_bytes(), &data)? {
+                let existing = match self.latest_tree.get(to.as_bytes())? {
+                    Some(data) => decode_record::<LatestVersionMapping>(&data).ok(),
+                    None => None,
+                };
+                if existing.is_none_or(|existing| existing.updated_at < mapping.updated_at) {
+                    mapping.crate_name = to.to_string();
+                    self.latest_tree.insert(to.as_bytes(), encode_record(&mapping)?)?;
+                }
+            }
+            self.latest_tree.remove(from.as_bytes())?;
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+        }
+
+        {
+            let mut cache = self.cache_write();
+            cache.remove(from);
+            cache.remove(to);
+        }
+
+        if !sources.is_empty() {
+            rat_logger::info!("合并包 {} 的 {} 条版本信息到 {}", from, sources.len(), to);
+        }
+        Ok(sources.len())
+    }
+
+    /// 标记包已从注册表删除，已标记时保留原来的标记时间
+    pub fn add_tombstone(&self, crate_name: &str) -> Result<Tombstone, VersionManagerError> {
+        if let Some(data) = self.tombstones_tree.get(crate_name.as_bytes())?
+            && let Some(tombstone) = self.decode_entry::<Tombstone>(&self.tombstones_tree, "tombstones", crate_name.as_bytes(), &data)?
+        {
+            return Ok(tombstone);
+        }
+
+        let tombstone = Tombstone {
+            crate_name: crate_name.to_string(),
+            created_at: self.now_secs(),
+        };
+        self.tombstones_tree.insert(crate_name.as_bytes(), encode_record(&tombstone)?)?;
+        self.write_ops.fetch_add(1, Ordering::Relaxed);
+        rat_logger::warn!("包 {} 已标记为删除", crate_name);
+        Ok(tombstone)
+    }
+
+    /// 取消包的删除标记，返回之前是否有标记
+    pub fn remove_tombstone(&self, crate_name: &str) -> Result<bool, VersionManagerError> {
+        let removed = self.tombstones_tree.remove(crate_name.as_bytes())?.is_some();
+        if removed {
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+            rat_logger::info!("取消包 {} 的删除标记", crate_name);
+        }
+        Ok(removed)
+    }
+
+    pub fn is_tombstoned(&self, crate_name: &str) -> Result<bool, VersionManagerError> {
+        Ok(self.tombstones_tree.get(crate_name.as_bytes())?.is_some())
+    }
+
+    /// 删除包的全部版本信息和最新版本映射，返回删除的记录数
+    pub fn remove_crate(&self, crate_name: &str) -> Result<usize, VersionManagerError> {
+        let prefix = format!("{}:", crate_name);
+        let mut batch = Batch::default();
+        let mut removed = 0;
+        for kv in self.versions_tree.scan_prefix(prefix.as_bytes()) {
+            let (key, _) = kv?;
+            batch.remove(key);
+            removed += 1;
+        }
+        if removed > 0 {
+            self.versions_tree.apply_batch(batch)?;
+            self.write_ops.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.latest_tree.remove(crate_name.as_bytes())?.is_some() {
+            removed += 1;
+        }
+        self.cache_write().remove(crate_name);
+        Ok(removed)
+    }
+
     /// 强制刷新数据库
     pub fn flush(&self) -> Result<(), VersionManagerError> {
         self.db.flush()?;
@@ -326,6 +1001,7 @@ impl VersionManager {
 }
 
 /// 版本管理器统计信息
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct VersionManagerStats {
     /// 最新版本映射数量
@@ -336,6 +1012,40 @@ pub struct VersionManagerStats {
     pub expired_count: usize,
     /// 内存缓存大小
     pub memory_cache_size: usize,
+    /// 数据库写操作次数
+    pub write_ops: u64,
+    /// 启动以来遍历时发现的损坏记录数
+    pub corrupt_entries: u64,
+}
+
+/// 只读模式下无法在缓存目录中打开数据库，复制到系统临时目录后使用，
+/// 之后写入的版本信息只保存在副本中，缓存目录保持不变
+fn read_only_db_copy(source: &Path) -> Result<PathBuf, VersionManagerError> {
+    let target = std::env::temp_dir().join(format!(
+        "crates_proxy-readonly-db-{}-{}",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    if source.is_dir() {
+        copy_dir(source, &target)?;
+        melange_db::cleanup_lock_files(&target)?;
+    }
+    rat_logger::info!("只读模式，版本数据库副本: {:?}", target);
+    Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
 }
 
 impl Drop for VersionManager {
@@ -344,4 +1054,347 @@ impl Drop for VersionManager {
             rat_logger::error!("版本管理器销毁时刷新失败: {}", e);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn test_manager(dir: &Path) -> VersionManager {
+        let mut config = Config::default();
+        config.cache.storage_path = dir.display().to_string();
+        VersionManager::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_batch_write_persists_all_versions() {
+        let dir = tempdir().unwrap();
+        let manager = test_manager(dir.path());
+
+        let infos: Vec<VersionInfo> = (0..200)
+            .map(|i| {
+                let version = format!("1.0.{}", i);
+                manager
+                    .build_version_info(&version, &format!("/dl/{}", version), "abc", false)
+                    .unwrap()
+            })
+            .collect();
+
+        let before = manager.get_stats().unwrap().write_ops;
+        assert_eq!(manager.set_version_infos("serde", &infos).unwrap(), 200);
+        let batched_ops = manager.get_stats().unwrap().write_ops - before;
+
+        assert_eq!(manager.get_all_versions("serde").unwrap().len(), 200);
+        assert_eq!(manager.get_version_info("serde", "1.0.199").unwrap().unwrap().download_path, "/dl/1.0.199");
+
+        // 逐条写入同样数量的版本需要的写操作次数
+        let before = manager.get_stats().unwrap().write_ops;
+        for info in &infos {
+            manager.set_version_info("tokio", &info.version, info.clone()).unwrap();
+        }
+        let per_insert_ops = manager.get_stats().unwrap().write_ops - before;
+
+        assert_eq!(batched_ops, 1);
+        assert_eq!(per_insert_ops, 200);
+    }
+
+    #[test]
+    fn test_max_versions_per_crate_keeps_newest() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.max_versions_per_crate = 3;
+        let manager = VersionManager::new(&config).unwrap();
+
+        let build = |version: &str| manager.build_version_info(version, "/dl", "abc", false).unwrap();
+        manager.publish_local_version("serde", "0.1.0", "abc").unwrap();
+        let infos: Vec<VersionInfo> = ["1.0.2", "1.0.10", "1.0.0", "1.0.9", "1.0.1"].iter().map(|v| build(v)).collect();
+        assert_eq!(manager.set_version_infos("serde", &infos).unwrap(), 3);
+
+        let mut versions: Vec<String> = manager.get_all_versions("serde").unwrap().into_iter().map(|info| info.version).collect();
+        versions.sort_by(|a, b| newest_first(a, b));
+        assert_eq!(versions, vec!["1.0.10", "1.0.9", "1.0.2", "0.1.0"]);
+
+        // 后续写入的新版本会挤掉已有的最旧版本
+        manager.set_version_infos("serde", &[build("1.1.0")]).unwrap();
+        assert!(manager.get_version_info("serde", "1.0.2").unwrap().is_none());
+        assert!(manager.get_version_info("serde", "1.1.0").unwrap().is_some());
+        assert!(manager.get_version_info("serde", "0.1.0").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_corrupt_entries_are_skipped_and_quarantined() {
+        for repair in [false, true] {
+            let dir = tempdir().unwrap();
+            let mut config = Config::default();
+            config.cache.storage_path = dir.path().display().to_string();
+            config.cache.repair_corrupt_entries = repair;
+            let manager = VersionManager::new(&config).unwrap();
+
+            for version in ["1.0.0", "2.0.0"] {
+                manager.create_version_info("foo", version, "/dl", "abc", false).unwrap();
+            }
+            manager.set_latest_version("bar", "1.0.0").unwrap();
+            manager.versions_tree.insert(b"foo:1.5.0", b"{not json".to_vec()).unwrap();
+            manager.latest_tree.insert(b"baz", b"\xff\xfe".to_vec()).unwrap();
+
+            // 坏记录不影响其他记录的读取、统计、清理和导出
+            assert_eq!(manager.get_all_versions("foo").unwrap().len(), 2);
+            assert_eq!(manager.get_version_info("foo", "1.5.0").unwrap(), None);
+            assert_eq!(manager.get_latest_version("baz").unwrap(), None);
+            let stats = manager.get_stats().unwrap();
+            assert_eq!((stats.versions_count, stats.latest_mappings_count), (2, 1));
+            assert!(stats.corrupt_entries > 0);
+            assert_eq!(manager.cleanup_expired_data().unwrap(), 0);
+            let dump = manager.dump().unwrap();
+            assert_eq!((dump.versions.len(), dump.latest.len()), (2, 1));
+
+            assert_eq!(manager.versions_tree.get(b"foo:1.5.0").unwrap().is_some(), !repair);
+            assert_eq!(manager.latest_tree.get(b"baz").unwrap().is_some(), !repair);
+            assert_eq!(manager.quarantine_tree.get(b"versions/foo:1.5.0").unwrap().is_some(), repair);
+            assert_eq!(manager.quarantine_tree.get(b"latest_versions/baz").unwrap().is_some(), repair);
+        }
+    }
+
+    #[test]
+    fn test_binary_record_round_trip() {
+        let info = VersionInfo {
+            version: "1.2.3".to_string(),
+            download_path: "/api/v1/crates/serde/1.2.3/download".to_string(),
+            checksum: "ab".repeat(32),
+            yanked: true,
+            created_at: 1_700_000_000,
+            expires_at: u64::MAX,
+            download_count: 42,
+            local: false,
+        };
+        let encoded = encode_record(&info).unwrap();
+        assert_eq!(encoded[0], RECORD_FORMAT_BINCODE);
+        assert!(encoded.len() < serde_json::to_vec(&info).unwrap().len());
+        assert_eq!(decode_record::<VersionInfo>(&encoded).unwrap(), info);
+
+        let mapping = LatestVersionMapping {
+            crate_name: "serde".to_string(),
+            latest_version: "1.2.3".to_string(),
+            updated_at: 1,
+            expires_at: 2,
+        };
+        assert_eq!(decode_record::<LatestVersionMapping>(&encode_record(&mapping).unwrap()).unwrap(), mapping);
+        assert!(matches!(decode_record::<VersionInfo>(b"\x7f"), Err(VersionManagerError::UnknownRecordFormat(0x7f))));
+    }
+
+    #[test]
+    fn test_json_records_migrated_to_binary() {
+        let dir = tempdir().unwrap();
+        let info = {
+            let manager = test_manager(dir.path());
+            let info = manager.build_version_info("1.0.0", "/dl", "abc", false).unwrap();
+            let mapping = LatestVersionMapping {
+                crate_name: "serde".to_string(),
+                latest_version: "1.0.0".to_string(),
+                updated_at: manager.now_secs(),
+                expires_at: manager.now_secs() + 3600,
+            };
+            // 模拟旧版本写入的JSON记录
+            manager.versions_tree.insert(b"serde:1.0.0", serde_json::to_vec(&info).unwrap()).unwrap();
+            manager.latest_tree.insert(b"serde", serde_json::to_vec(&mapping).unwrap()).unwrap();
+            manager.db.open_tree(b"meta").unwrap().remove(RECORD_FORMAT_KEY).unwrap();
+
+            // 迁移前也能读取JSON记录
+            assert_eq!(manager.get_version_info("serde", "1.0.0").unwrap(), Some(info.clone()));
+            info
+        };
+
+        let manager = test_manager(dir.path());
+        let stored = manager.versions_tree.get(b"serde:1.0.0").unwrap().unwrap();
+        assert_eq!(stored[0], RECORD_FORMAT_BINCODE);
+        assert_eq!(manager.latest_tree.get(b"serde").unwrap().unwrap()[0], RECORD_FORMAT_BINCODE);
+        assert_eq!(manager.get_version_info("serde", "1.0.0").unwrap(), Some(info));
+        assert_eq!(manager.get_latest_version("serde").unwrap().as_deref(), Some("1.0.0"));
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let dir = tempdir().unwrap();
+        let manager = test_manager(dir.path());
+        manager.create_version_info("serde", "1.0.0", "/dl", "abc", false).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..16 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        manager.record_download("serde", "1.0.0").unwrap();
+                    }
+                });
+            }
+            // 同时刷新撤销状态，不应覆盖计数
+            scope.spawn(|| {
+                for i in 0..50 {
+                    manager.update_version_info("serde", "1.0.0", |info| info.yanked = i % 2 == 0).unwrap();
+                }
+            });
+        });
+
+        let info = manager.get_version_info("serde", "1.0.0").unwrap().unwrap();
+        assert_eq!(info.download_count, 800);
+        assert!(!info.yanked);
+
+        // 从上游刷新版本列表时保留计数
+        let refreshed = manager.build_version_info("1.0.0", "/dl", "abc", true).unwrap();
+        manager.set_version_infos("serde", &[refreshed]).unwrap();
+        let info = manager.get_version_info("serde", "1.0.0").unwrap().unwrap();
+        assert_eq!(info.download_count, 800);
+        assert!(info.yanked);
+
+        assert_eq!(manager.record_download("serde", "9.9.9").unwrap(), None);
+    }
+
+    #[test]
+    fn test_ttl_jitter_spreads_expiry() {
+        let dir = tempdir().unwrap();
+        let manager = test_manager(dir.path());
+        manager.set_default_ttl(3600);
+
+        let infos: Vec<VersionInfo> = (0..20)
+            .map(|i| manager.build_version_info(&format!("1.0.{}", i), "/dl", "abc", false).unwrap())
+            .collect();
+        for info in &infos {
+            // 默认抖动10%：3600±360秒
+            let ttl = info.expires_at - info.created_at;
+            assert!((3240..=3960).contains(&ttl), "ttl {} 超出抖动范围", ttl);
+        }
+        let distinct: std::collections::HashSet<u64> = infos.iter().map(|info| info.expires_at - info.created_at).collect();
+        assert!(distinct.len() > 1);
+
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().join("no_jitter").display().to_string();
+        config.cache.ttl_jitter_pct = 0;
+        let manager = VersionManager::new(&config).unwrap();
+        let info = manager.build_version_info("1.0.0", "/dl", "abc", false).unwrap();
+        assert_eq!(info.expires_at - info.created_at, config.cache.default_ttl);
+    }
+
+    #[test]
+    fn test_poisoned_memory_cache_is_recovered() {
+        let dir = tempdir().unwrap();
+        let manager = Arc::new(test_manager(dir.path()));
+        manager.set_latest_version("serde", "1.0.0").unwrap();
+
+        let holder = manager.clone();
+        let result = std::thread::spawn(move || {
+            let _cache = holder.memory_cache.write().unwrap();
+            panic!("模拟持锁时panic");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(manager.memory_cache.is_poisoned());
+
+        // 中毒后读写照常进行
+        assert_eq!(manager.get_latest_version("serde").unwrap().as_deref(), Some("1.0.0"));
+        manager.set_latest_version("serde", "1.1.0").unwrap();
+        manager.set_latest_version("tokio", "1.40.0").unwrap();
+        assert_eq!(manager.get_latest_version("serde").unwrap().as_deref(), Some("1.1.0"));
+        assert_eq!(manager.cache_read().len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_version_policy() {
+        for policy in [
+            DuplicateVersionPolicy::Overwrite,
+            DuplicateVersionPolicy::PreserveCreated,
+            DuplicateVersionPolicy::SkipUnchanged,
+        ] {
+            let dir = tempdir().unwrap();
+            let mut config = Config::default();
+            config.cache.storage_path = dir.path().display().to_string();
+            config.cache.ttl_jitter_pct = 0;
+            config.cache.duplicate_version_policy = policy;
+            let manager = VersionManager::new(&config).unwrap();
+
+            let first = manager.create_version_info("serde", "1.0.0", "/dl", "abc", false).unwrap();
+            manager.set_clock_offset(100);
+            manager.create_version_info("serde", "1.0.0", "/dl", "abc", false).unwrap();
+            let stored = manager.get_version_info("serde", "1.0.0").unwrap().unwrap();
+            match policy {
+                DuplicateVersionPolicy::Overwrite => assert!(stored.created_at >= first.created_at + 100),
+                DuplicateVersionPolicy::PreserveCreated => {
+                    assert_eq!(stored.created_at, first.created_at);
+                    assert!(stored.expires_at >= first.expires_at + 100);
+                }
+                DuplicateVersionPolicy::SkipUnchanged => {
+                    assert_eq!(stored.created_at, first.created_at);
+                    assert_eq!(stored.expires_at, first.expires_at);
+                }
+            }
+
+            // 内容变化时照常写入，保留策略下创建时间不变
+            manager.set_version_infos("serde", &[manager.build_version_info("1.0.0", "/dl", "abc", true).unwrap()]).unwrap();
+            let stored = manager.get_version_info("serde", "1.0.0").unwrap().unwrap();
+            assert!(stored.yanked);
+            assert_eq!(stored.created_at == first.created_at, policy != DuplicateVersionPolicy::Overwrite);
+        }
+    }
+
+    #[test]
+    fn test_custom_db_cache_capacity() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().display().to_string();
+        config.cache.db_cache_capacity_bytes = 4 * 1024 * 1024;
+        config.validate().unwrap();
+
+        let db_path = dir.path().join(VERSIONS_DB_DIR);
+        assert_eq!(db_config(&config, &db_path).cache_capacity_bytes, 4 * 1024 * 1024);
+
+        let manager = VersionManager::new(&config).unwrap();
+        manager.create_version_info("foo", "1.0.0", "", "abc", false).unwrap();
+        assert_eq!(manager.get_version_info("foo", "1.0.0").unwrap().unwrap().checksum, "abc");
+
+        config.cache.db_cache_capacity_bytes = 1024;
+        assert!(matches!(config.validate(), Err(crate::config::ConfigError::CacheError(_))));
+    }
+
+    #[test]
+    fn test_latest_keyword_is_not_stored_as_version() {
+        let dir = tempdir().unwrap();
+        let manager = test_manager(dir.path());
+
+        assert!(matches!(
+            manager.create_version_info("foo", LATEST, "", "", false),
+            Err(VersionManagerError::ReservedVersion(_))
+        ));
+        assert!(matches!(manager.set_latest_version("foo", LATEST), Err(VersionManagerError::ReservedVersion(_))));
+
+        let infos = [
+            manager.build_version_info("1.0.0", "", "aa", false).unwrap(),
+            manager.build_version_info(LATEST, "", "bb", false).unwrap(),
+        ];
+        assert_eq!(manager.set_version_infos("foo", &infos).unwrap(), 1);
+        assert!(manager.get_version_info("foo", LATEST).unwrap().is_none());
+        assert_eq!(manager.get_all_versions("foo").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_backward_clock_does_not_error() {
+        let dir = tempdir().unwrap();
+        let manager = test_manager(dir.path());
+        manager.create_version_info("serde", "1.0.0", "/dl", "abc", false).unwrap();
+
+        // 容差内的小幅回拨不影响有效性
+        manager.set_clock_offset(-10);
+        assert!(manager.get_version_info("serde", "1.0.0").unwrap().is_some());
+
+        // 大幅回拨后记录的创建时间在"未来"，视为过期
+        manager.set_clock_offset(-86400);
+        assert!(manager.get_version_info("serde", "1.0.0").unwrap().is_none());
+
+        // 时钟早于Unix纪元时也不返回错误
+        let before_epoch = -(clock::unix_secs(SystemTime::now()) as i64) - 100;
+        manager.set_clock_offset(before_epoch);
+        let info = manager.build_version_info("1.0.1", "/dl", "abc", false).unwrap();
+        assert_eq!(info.created_at, 0);
+        assert!(manager.get_all_versions("serde").is_ok());
+        assert!(manager.cleanup_expired_data().is_ok());
+    }
+}
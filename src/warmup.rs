@@ -0,0 +1,370 @@
+//! 缓存预热：按下载量从上游获取热门包，并通过代理服务下载其最新版本；
+//! 以及按常驻缓存列表（`cache.warm_list_path`）定期确保指定的包已缓存且不过期
+
+use crate::audit::DownloadRecord;
+use crate::cache::CacheManager;
+use crate::config::Config;
+use crate::crates_api::{ApiError, CratesApiClient, PopularCrate};
+use crate::proxy::{ProxyError, ProxyService};
+use crate::version_manager::LATEST;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::{Request, StatusCode};
+use std::time::Duration;
+
+/// crates.io 单页最多返回的条目数
+const MAX_PAGE_SIZE: usize = 100;
+
+/// 预热参数
+#[derive(Debug, Clone)]
+pub struct WarmupOptions {
+    /// 每页请求的条目数，不超过100
+    pub page_size: usize,
+    /// 两次上游API请求之间的间隔，遵守crates.io每秒一次的爬取约定
+    pub request_interval: Duration,
+    /// 遇到429限流时的最大重试次数
+    pub max_retries: u32,
+}
+
+impl Default for WarmupOptions {
+    fn default() -> Self {
+        Self {
+            page_size: MAX_PAGE_SIZE,
+            request_interval: Duration::from_secs(1),
+            max_retries: 5,
+        }
+    }
+}
+
+/// 预热结果
+#[derive(Debug, Default)]
+pub struct WarmupReport {
+    /// 已在缓存中的包（包括本次下载的）
+    pub cached: Vec<String>,
+    /// 下载失败的包及原因
+    pub failed: Vec<(String, String)>,
+}
+
+/// 获取下载量最高的前count个包，遇到429时按指数退避重试
+fn fetch_popular_crates(
+    api_client: &CratesApiClient,
+    count: usize,
+    options: &WarmupOptions,
+) -> Result<Vec<PopularCrate>, ApiError> {
+    let per_page = options.page_size.clamp(1, MAX_PAGE_SIZE).min(count.max(1));
+    let mut popular = Vec::with_capacity(count);
+    let mut page = 1;
+
+    while popular.len() < count {
+        if page > 1 {
+            std::thread::sleep(options.request_interval);
+        }
+
+        let mut attempt = 0;
+        let crates = loop {
+            match api_client.list_crates_by_downloads(page, per_page) {
+                Err(ApiError::HttpError(429, _)) if attempt < options.max_retries => {
+                    let backoff = options.request_interval.max(Duration::from_millis(10)) * 2u32.pow(attempt);
+                    attempt += 1;
+                    rat_logger::warn!("上游限流，{:?} 后第 {} 次重试第 {} 页", backoff, attempt, page);
+                    std::thread::sleep(backoff);
+                }
+                result => break result?,
+            }
+        };
+
+        let last_page = crates.len() < per_page;
+        popular.extend(crates);
+        if last_page {
+            break;
+        }
+        page += 1;
+    }
+
+    popular.truncate(count);
+    Ok(popular)
+}
+
+/// 预热下载量最高的count个包的最新版本，逐个输出进度
+pub async fn warm_popular(
+    config: &Config,
+    service: &ProxyService,
+    count: usize,
+    options: &WarmupOptions,
+) -> Result<WarmupReport, ProxyError> {
+    let api_client = CratesApiClient::new(config);
+    let popular = fetch_popular_crates(&api_client, count, options)?;
+    println!("获取到 {} 个热门包，开始预热", popular.len());
+
+    let mut report = WarmupReport::default();
+    let total = popular.len();
+
+    for (index, krate) in popular.into_iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(options.request_interval).await;
+        }
+
+        let request = Request::builder()
+            .uri(format!("/api/v1/crates/{}/latest/download", krate.name))
+            .body(Empty::<Bytes>::new())?;
+
+        match service.handle_request(request).await {
+            Ok(response) if response.status() == StatusCode::OK => {
+                service.flush_cache_writes().await;
+                println!("[{}/{}] {} 已缓存", index + 1, total, krate.name);
+                report.cached.push(krate.name);
+            }
+            Ok(response) => {
+                let reason = format!("HTTP {}", response.status());
+                println!("[{}/{}] {} 预热失败: {}", index + 1, total, krate.name, reason);
+                report.failed.push((krate.name, reason));
+            }
+            Err(e) => {
+                println!("[{}/{}] {} 预热失败: {}", index + 1, total, krate.name, e);
+                report.failed.push((krate.name, e.to_string()));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// 常驻缓存列表中的一项，version为None时保持最新版本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarmEntry {
+    pub crate_name: String,
+    pub version: Option<String>,
+}
+
+/// 解析常驻缓存列表：每行 `crate` 或 `crate@version`，忽略空行和 `#` 开头的注释
+pub fn parse_warm_list(content: &str) -> Vec<WarmEntry> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('@') {
+            Some((crate_name, version)) => WarmEntry {
+                crate_name: crate_name.trim().to_string(),
+                version: Some(version.trim().to_string()),
+            },
+            None => WarmEntry {
+                crate_name: line.to_string(),
+                version: None,
+            },
+        })
+        .collect()
+}
+
+async fn fetch_crate(service: &ProxyService, crate_name: &str, version: &str) -> Result<Option<String>, String> {
+    let request = Request::builder()
+        .uri(format!("/api/v1/crates/{}/{}/download", crate_name, version))
+        .body(Empty::<Bytes>::new())
+        .map_err(|e| e.to_string())?;
+    let response = service.handle_request(request).await.map_err(|e| e.to_string())?;
+    if response.status() != StatusCode::OK {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    service.flush_cache_writes().await;
+    Ok(response.extensions().get::<DownloadRecord>().map(|record| record.version.clone()))
+}
+
+/// 确保列表中的包已缓存：未缓存的下载，window秒内会过期的删除后重新下载
+pub async fn refresh_warm_list(
+    service: &ProxyService,
+    cache_manager: &CacheManager,
+    entries: &[WarmEntry],
+    window: u64,
+) -> WarmupReport {
+    let mut report = WarmupReport::default();
+
+    for entry in entries {
+        let requested = entry.version.as_deref().unwrap_or(LATEST);
+        let result = match fetch_crate(service, &entry.crate_name, requested).await {
+            Ok(Some(version)) => {
+                let filename = cache_manager.crate_filename(&entry.crate_name, &version);
+                if cache_manager.expires_within(&entry.crate_name, &version, &filename, window) {
+                    rat_logger::info!("常驻缓存即将过期，重新下载: {}-{}", entry.crate_name, version);
+                    match cache_manager.remove_cached_file(&entry.crate_name, &version, &filename) {
+                        Ok(_) => fetch_crate(service, &entry.crate_name, &version).await.map(|_| ()),
+                        Err(e) => Err(e.to_string()),
+                    }
+                } else {
+                    Ok(())
+                }
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        let name = format!("{}@{}", entry.crate_name, requested);
+        match result {
+            Ok(()) => report.cached.push(name),
+            Err(reason) => {
+                rat_logger::warn!("常驻缓存 {} 更新失败: {}", name, reason);
+                report.failed.push((name, reason));
+            }
+        }
+    }
+
+    report
+}
+
+/// 启动常驻缓存的后台任务，每个间隔重新读取列表文件，修改列表无需重启
+pub fn start_warm_list_task(config: &Config, service: ProxyService, path: String) -> Result<(), ProxyError> {
+    let cache_manager = CacheManager::from_config(config)?;
+    let interval = config.cache.warm_list_interval.max(1);
+    rat_logger::info!("常驻缓存列表: {}，每 {} 秒检查一次", path, interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            let entries = match std::fs::read_to_string(&path) {
+                Ok(content) => parse_warm_list(&content),
+                Err(e) => {
+                    rat_logger::error!("读取常驻缓存列表失败 {}: {}", path, e);
+                    continue;
+                }
+            };
+            // 在下一次检查之前就会过期的文件本轮提前刷新
+            let report = refresh_warm_list(&service, &cache_manager, &entries, interval).await;
+            rat_logger::info!("常驻缓存检查完成: 成功 {} 个，失败 {} 个", report.cached.len(), report.failed.len());
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheManager;
+    use crate::test_support::{crate_versions_json, fake_crate_bytes, MockResponse, MockServer};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tempfile::tempdir;
+
+    const NAMES: [&str; 5] = ["alpha", "beta", "gamma", "delta", "epsilon"];
+
+    fn listing_page(page: usize, per_page: usize) -> String {
+        let crates: Vec<serde_json::Value> = NAMES
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|name| serde_json::json!({ "name": name, "max_version": "1.0.0", "downloads": 100 }))
+            .collect();
+        serde_json::json!({ "crates": crates, "meta": { "total": NAMES.len() } }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_warm_popular_fetches_top_crates() {
+        let rate_limited = AtomicBool::new(false);
+        let server = MockServer::start(move |req| {
+            if let Some(query) = req.path.strip_prefix("/api/v1/crates?") {
+                assert!(query.contains("sort=downloads"));
+                // 第一次请求模拟限流
+                if !rate_limited.swap(true, Ordering::SeqCst) {
+                    return MockResponse::status(429);
+                }
+                let param = |key: &str| {
+                    query
+                        .split('&')
+                        .find_map(|kv| kv.strip_prefix(key))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap()
+                };
+                return MockResponse::ok(listing_page(param("page="), param("per_page=")));
+            }
+
+            let parts: Vec<&str> = req.path.trim_start_matches("/api/v1/crates/").split('/').collect();
+            match parts.as_slice() {
+                [name] => MockResponse::ok(crate_versions_json(name, &[("1.0.0", false)])),
+                [name, "1.0.0", "download"] => MockResponse::ok(fake_crate_bytes(name)),
+                _ => MockResponse::status(404),
+            }
+        });
+
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().join("cache").display().to_string();
+        config.upstream.api_url = server.url();
+        let service = ProxyService::new(&config).unwrap();
+
+        let options = WarmupOptions {
+            page_size: 2,
+            request_interval: Duration::ZERO,
+            max_retries: 3,
+        };
+        let report = warm_popular(&config, &service, 3, &options).await.unwrap();
+
+        assert_eq!(report.cached, vec!["alpha", "beta", "gamma"]);
+        assert!(report.failed.is_empty());
+
+        // 分页请求了两页，第一页经历了一次限流重试
+        let listing_requests = server
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/api/v1/crates?"))
+            .count();
+        assert_eq!(listing_requests, 3);
+
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        for name in ["alpha", "beta", "gamma"] {
+            let filename = format!("{}-1.0.0.crate", name);
+            assert!(cache_manager.is_cached(name, "1.0.0", &filename));
+            assert_eq!(server.hits(&format!("/api/v1/crates/{}/1.0.0/download", name)), 1);
+        }
+        assert_eq!(server.hits("/api/v1/crates/delta"), 0);
+    }
+
+    #[test]
+    fn test_parse_warm_list() {
+        let entries = parse_warm_list("# 常驻缓存\nserde\n\n  tokio@1.40.0  \n");
+        assert_eq!(
+            entries,
+            vec![
+                WarmEntry { crate_name: "serde".into(), version: None },
+                WarmEntry { crate_name: "tokio".into(), version: Some("1.40.0".into()) },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_warm_list_keeps_crates_cached() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v1/crates/serde" => MockResponse::ok(crate_versions_json("serde", &[("1.1.0", false), ("1.0.0", false)])),
+            "/api/v1/crates/serde/1.1.0/download" => MockResponse::ok(fake_crate_bytes("serde 1.1.0")),
+            "/api/v1/crates/serde/1.0.0/download" => MockResponse::ok(fake_crate_bytes("serde 1.0.0")),
+            _ => MockResponse::status(404),
+        });
+
+        let dir = tempdir().unwrap();
+        let list_path = dir.path().join("warm.txt");
+        std::fs::write(&list_path, "serde\nserde@1.0.0\nmissing\n").unwrap();
+
+        let mut config = Config::default();
+        config.cache.storage_path = dir.path().join("cache").display().to_string();
+        config.cache.default_ttl = 600;
+        config.upstream.api_url = server.url();
+        let service = ProxyService::new(&config).unwrap();
+        let cache_manager = CacheManager::from_config(&config).unwrap();
+        let entries = parse_warm_list(&std::fs::read_to_string(&list_path).unwrap());
+
+        // 首次检查下载列表中的包
+        let report = refresh_warm_list(&service, &cache_manager, &entries, 60).await;
+        assert_eq!(report.cached, vec!["serde@latest", "serde@1.0.0"]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(cache_manager.is_cached("serde", "1.1.0", "serde-1.1.0.crate"));
+        assert!(cache_manager.is_cached("serde", "1.0.0", "serde-1.0.0.crate"));
+        assert_eq!(server.hits("/api/v1/crates/serde/1.1.0/download"), 1);
+
+        // 离过期还远时不重新下载
+        refresh_warm_list(&service, &cache_manager, &entries, 60).await;
+        assert_eq!(server.hits("/api/v1/crates/serde/1.1.0/download"), 1);
+
+        // 在检查窗口内会过期的文件提前重新下载，之后仍在缓存中
+        refresh_warm_list(&service, &cache_manager, &entries, 600).await;
+        assert_eq!(server.hits("/api/v1/crates/serde/1.1.0/download"), 2);
+        assert_eq!(server.hits("/api/v1/crates/serde/1.0.0/download"), 2);
+        assert!(!cache_manager.expires_within("serde", "1.1.0", "serde-1.1.0.crate", 60));
+        assert!(cache_manager.is_cached("serde", "1.0.0", "serde-1.0.0.crate"));
+    }
+}